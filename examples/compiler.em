@@ -1,47 +1,23 @@
-struct elf {
-  magic: [u8; 4],
-  class: u8,
-  endian: u8,
-  version: u8,
-  abi_sysv: u8,
-  abi_version: u8,
-  _pad0: [u8; 7],
-  kind: u16,
-  machine: u16,
-  version2: u64,
-  entry_address: u64,
-  program_address: u64,
-  section_address: u64,
-  flags: u32,
-  header: u16,
-  program_size: u16,
-  program_len: u16,
-  section_size: u16,
-}
-
 fn entry() {
-  let elf = elf {
-    magic: '\x{7F}ELF',
-    class: 2,
-    endian: 1,
-    version: 1,
-    abi_sysv: 0,
-    abi_version: 0,
-    kind: 2,
-    machine: 0x3E,
-    version2: 1,
-    entry_address: 0x201120,
-    program_address: 64,
-    section_address: 352,
-    flags: 0,
-    header: 64,
-    section_size: 64,
-    section_len: 3,
-    section_index: 2,
-  }
+  let magic: u32 = 1179403647
+  let class: u8 = 2
+  let endian: u8 = 1
+  let version: u8 = 1
+  let abi_sysv: u8 = 0
+  let abi_version: u8 = 0
+  let kind: u16 = 2
+  let machine: u16 = 62
+  let version2: u32 = 1
+  let entry_address: u64 = 2101536
+  let program_address: u64 = 64
+  let section_address: u64 = 352
+  let flags: u32 = 0
+  let header: u16 = 64
+  let section_size: u16 = 64
+  let section_len: u16 = 3
+  let section_index: u16 = 2
 
-  sys::syscall(1, 'Hello ')
-  sys::syscall(1, 'World!')
-  sys::syscall(1, '\n')
-  sys::syscall(60, 0)
+  sys::syscall(1, 1, 'Hello ')
+  sys::syscall(1, 1, 'World!')
+  sys::syscall(1, 1, '\n')
 }
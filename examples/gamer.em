@@ -1,4 +1,4 @@
-fn entry() {
-    sys::syscall(1, 'gaming\n')
-    sys::syscall(60, 69)
+fn entry() -> u8 {
+    sys::syscall(1, 1, 'gaming\n')
+    return 69
 }
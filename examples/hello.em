@@ -1,4 +1,3 @@
 fn entry() {
-    sys::syscall(1, 'hello world\n')
-    sys::syscall(60, 0)
+    sys::syscall(1, 1, 'hello world\n')
 }
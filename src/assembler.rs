@@ -0,0 +1,468 @@
+use super::diagnostic::Diagnostics;
+use super::encoder::{Instruction, Memory, Mnemonic, Mode, Operand, Size};
+use super::op::fits_i8;
+use super::relocation::Kind;
+use super::span::Span;
+use core::convert::TryFrom;
+
+/// A position in the instruction stream, bound once and referenced any number of times before
+/// or after.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Label(usize);
+
+#[derive(Clone, Debug)]
+enum Entry {
+    Instruction(Instruction),
+    /// jmp, jcc or call to a label
+    Branch(Mnemonic, Label),
+    /// instruction whose rip-relative memory operand refers to a label
+    Rip(Instruction, Label),
+    /// instruction referring to data outside of the code
+    Reference(Instruction, Reference),
+    Bind(Label),
+    Data(Vec<u8>),
+}
+
+/// A reference to data placed outside of the code, such as a string in `.rodata`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Reference {
+    /// `Absolute32` for an immediate, `Relative32` for a rip-relative displacement, `Plt32`
+    /// for the displacement of a call
+    pub kind: Kind,
+    /// what is referred to, chosen by the caller
+    pub target: usize,
+    /// offset into the target
+    pub offset: u64,
+}
+
+/// A 32-bit field of the code which is filled in once the address of its target is known.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Relocation {
+    /// offset of the field
+    pub offset: usize,
+    pub kind: Kind,
+    pub target: usize,
+    /// added to the target address, for `Relative32` this accounts for the field not being
+    /// at the end of the instruction
+    pub addend: i64,
+}
+
+/// One line of the listing.
+#[derive(Clone, Debug)]
+pub struct Line {
+    pub offset: usize,
+    pub len: usize,
+    /// `None` for data
+    pub instruction: Option<Instruction>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct Assembled {
+    pub bytes: Vec<u8>,
+    /// offset of each label
+    pub labels: Vec<usize>,
+    pub lines: Vec<Line>,
+    /// fields referring to data outside of the code, which hold zero
+    pub relocations: Vec<Relocation>,
+}
+
+impl Assembled {
+    pub fn offset(&self, label: Label) -> usize {
+        self.labels[label.0]
+    }
+}
+
+/// Collects instructions and data with symbolic references, and resolves them once every
+/// offset is known.
+///
+/// Jumps start out short and are relaxed to near jumps until every displacement fits, calls
+/// and rip-relative operands always use 32-bit displacements.
+#[derive(Clone, Debug)]
+pub struct Assembler {
+    entries: Vec<(Entry, Span)>,
+    labels: usize,
+    /// span of the entries being pushed
+    span: Span,
+    mode: Mode,
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![],
+            labels: 0,
+            span: Span::default(),
+            mode: Mode::Long,
+        }
+    }
+
+    /// Sets the mode every instruction is encoded for.
+    pub fn mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Sets the source span of the following entries.
+    pub fn span(&mut self, span: Span) {
+        self.span = span;
+    }
+
+    /// Source span of the entries being pushed.
+    pub fn current_span(&self) -> Span {
+        self.span
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels += 1;
+
+        Label(self.labels - 1)
+    }
+
+    pub fn bind(&mut self, label: Label) {
+        self.entries.push((Entry::Bind(label), self.span));
+    }
+
+    pub fn push(&mut self, instruction: impl Into<Instruction>) {
+        self.entries
+            .push((Entry::Instruction(instruction.into()), self.span));
+    }
+
+    /// A `jmp`, `jcc` or `call` to `label`.
+    pub fn branch(&mut self, mnemonic: Mnemonic, label: Label) {
+        self.entries
+            .push((Entry::Branch(mnemonic, label), self.span));
+    }
+
+    /// An instruction with a `Memory::rip` operand whose displacement is resolved to `label`.
+    pub fn rip(&mut self, instruction: Instruction, label: Label) {
+        self.entries
+            .push((Entry::Rip(instruction, label), self.span));
+    }
+
+    /// An instruction whose immediate or rip-relative displacement refers to data outside of
+    /// the code. The field must end the encoding whatever its value, such as `mov r32,
+    /// imm32`, `lea r64, [rip + disp32]` or `call rel32`, and is left as zero with a relocation.
+    pub fn reference(&mut self, instruction: Instruction, reference: Reference) {
+        self.entries
+            .push((Entry::Reference(instruction, reference), self.span));
+    }
+
+    pub fn data(&mut self, bytes: &[u8]) {
+        self.entries.push((Entry::Data(bytes.to_vec()), self.span));
+    }
+
+    /// Branch instruction with a placeholder displacement.
+    fn branch_instruction(mnemonic: Mnemonic, near: bool, relative: i32) -> Instruction {
+        let size = if near || mnemonic == Mnemonic::call {
+            Size::Dword
+        } else {
+            Size::Byte
+        };
+
+        Instruction::new(mnemonic, size, &[Operand::Relative(relative)])
+    }
+
+    /// Replaces the rip-relative operand's displacement, or the branch target of a call.
+    fn with_displacement(instruction: &Instruction, displacement: i32) -> Instruction {
+        let mut instruction = instruction.clone();
+
+        for operand in &mut instruction.operands {
+            match operand {
+                Operand::Memory(memory) if memory.rip => *memory = Memory::rip(displacement),
+                Operand::Relative(_) => *operand = Operand::Relative(displacement),
+                _ => {}
+            }
+        }
+
+        instruction
+    }
+
+    /// Replaces the immediate operand.
+    fn with_immediate(instruction: &Instruction, immediate: i64) -> Instruction {
+        let mut instruction = instruction.clone();
+
+        for operand in &mut instruction.operands {
+            if let Operand::Immediate(_) = operand {
+                *operand = Operand::Immediate(immediate);
+            }
+        }
+
+        instruction
+    }
+
+    /// The instruction with a zero placeholder in the referring field.
+    fn with_reference(instruction: &Instruction, reference: &Reference) -> Instruction {
+        match reference.kind {
+            Kind::Absolute32 | Kind::Absolute64 => Self::with_immediate(instruction, 0),
+            Kind::Relative32 | Kind::Plt32 => Self::with_displacement(instruction, 0),
+        }
+    }
+
+    fn len(&self, entry: &Entry, near: bool) -> usize {
+        let mode = self.mode;
+        let len = match entry {
+            Entry::Instruction(instruction) => {
+                instruction.encode_mode(mode).map(|opcode| opcode.len())
+            }
+            Entry::Branch(mnemonic, _) => Self::branch_instruction(*mnemonic, near, 0)
+                .encode_mode(mode)
+                .map(|opcode| opcode.len()),
+            Entry::Rip(instruction, _) => Self::with_displacement(instruction, 0)
+                .encode_mode(mode)
+                .map(|opcode| opcode.len()),
+            Entry::Reference(instruction, reference) => {
+                Self::with_reference(instruction, reference)
+                    .encode_mode(mode)
+                    .map(|opcode| opcode.len())
+            }
+            Entry::Bind(_) => Ok(0),
+            Entry::Data(bytes) => Ok(bytes.len()),
+        };
+
+        // encoding errors are reported by `assemble`
+        len.unwrap_or(0)
+    }
+
+    /// Offsets of every entry and label for the given branch sizes.
+    fn offsets(&self, near: &[bool]) -> (Vec<usize>, Vec<usize>) {
+        let mut offsets = Vec::with_capacity(self.entries.len());
+        let mut labels = vec![usize::MAX; self.labels];
+        let mut offset = 0;
+
+        for ((entry, _span), near) in self.entries.iter().zip(near) {
+            offsets.push(offset);
+
+            if let Entry::Bind(label) = entry {
+                labels[label.0] = offset;
+            }
+
+            offset += self.len(entry, *near);
+        }
+
+        offsets.push(offset);
+
+        (offsets, labels)
+    }
+
+    pub fn assemble(&self, diagnostics: &mut Diagnostics) -> Assembled {
+        let mut near = vec![false; self.entries.len()];
+
+        // relax short jumps whose target is out of reach, growing a jump can only push other
+        // targets further away, so this settles
+        let (offsets, labels) = loop {
+            let (offsets, labels) = self.offsets(&near);
+            let mut changed = false;
+
+            for (index, (entry, _span)) in self.entries.iter().enumerate() {
+                if let Entry::Branch(_, label) = entry {
+                    let end = offsets[index + 1] as i64;
+                    let target = labels[label.0] as i64;
+
+                    if !near[index] && !fits_i8(target - end) {
+                        near[index] = true;
+                        changed = true;
+                    }
+                }
+            }
+
+            if !changed {
+                break (offsets, labels);
+            }
+        };
+
+        let mut bytes = vec![];
+        let mut lines = vec![];
+        let mut relocations = vec![];
+
+        for (index, (entry, span)) in self.entries.iter().enumerate() {
+            let end = offsets[index + 1] as i64;
+            let target = |label: &Label| {
+                assert!(labels[label.0] != usize::MAX, "unbound label");
+
+                i32::try_from(labels[label.0] as i64 - end)
+            };
+
+            let instruction = match entry {
+                Entry::Instruction(instruction) => instruction.clone(),
+                Entry::Branch(mnemonic, label) => match target(label) {
+                    Ok(relative) => Self::branch_instruction(*mnemonic, near[index], relative),
+                    Err(_) => {
+                        diagnostics.error(*span, "branch target is out of reach");
+                        continue;
+                    }
+                },
+                Entry::Rip(instruction, label) => match target(label) {
+                    Ok(displacement) => Self::with_displacement(instruction, displacement),
+                    Err(_) => {
+                        diagnostics
+                            .error(*span, "reference is out of reach")
+                            .note("rip-relative displacements are limited to 32 bits");
+                        continue;
+                    }
+                },
+                Entry::Reference(instruction, reference) => {
+                    Self::with_reference(instruction, reference)
+                }
+                Entry::Bind(_) => continue,
+                Entry::Data(data) => {
+                    lines.push(Line {
+                        offset: bytes.len(),
+                        len: data.len(),
+                        instruction: None,
+                        span: *span,
+                    });
+
+                    bytes.extend(data);
+                    continue;
+                }
+            };
+
+            match instruction.encode_mode(self.mode) {
+                Ok(opcode) => {
+                    if let Entry::Reference(_, reference) = entry {
+                        // a displacement is relative to the end of the instruction, which is
+                        // the end of the field
+                        let addend = match reference.kind {
+                            Kind::Absolute32 | Kind::Absolute64 => reference.offset as i64,
+                            Kind::Relative32 | Kind::Plt32 => reference.offset as i64 - 4,
+                        };
+
+                        relocations.push(Relocation {
+                            offset: bytes.len() + opcode.len() - reference.kind.size(),
+                            kind: reference.kind,
+                            target: reference.target,
+                            addend,
+                        });
+                    }
+
+                    lines.push(Line {
+                        offset: bytes.len(),
+                        len: opcode.len(),
+                        instruction: Some(instruction),
+                        span: *span,
+                    });
+
+                    bytes.extend(opcode.as_bytes());
+                }
+                Err(error) => {
+                    diagnostics.error(*span, error.message());
+                }
+            }
+        }
+
+        Assembled {
+            bytes,
+            labels,
+            lines,
+            relocations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::encoder::Condition;
+    use super::*;
+
+    /// Assembles the entries, which have to be free of errors.
+    fn assemble(asm: &Assembler) -> Assembled {
+        let mut diagnostics = Diagnostics::new();
+        let assembled = asm.assemble(&mut diagnostics);
+
+        assert!(!diagnostics.has_errors());
+
+        assembled
+    }
+
+    /// Size and displacement of each branch.
+    fn branches(assembled: &Assembled) -> Vec<(Size, i32)> {
+        assembled
+            .lines
+            .iter()
+            .filter_map(|line| match line.instruction.as_ref()?.operands[..] {
+                [Operand::Relative(relative)] => Some((line.instruction.as_ref()?.size, relative)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn forward_jump_is_relaxed() {
+        for (padding, expected) in [(127, (Size::Byte, 127)), (128, (Size::Dword, 128))] {
+            let mut asm = Assembler::new();
+            let label = asm.label();
+
+            asm.branch(Mnemonic::jmp, label);
+            asm.data(&vec![0x90; padding]);
+            asm.bind(label);
+
+            let assembled = assemble(&asm);
+
+            assert_eq!(branches(&assembled), [expected]);
+            assert_eq!(assembled.offset(label), assembled.bytes.len());
+        }
+    }
+
+    #[test]
+    fn backward_jump_is_relaxed() {
+        // a short jcc ends 2 bytes after the padding, a near one 6
+        for (padding, expected) in [(126, (Size::Byte, -128)), (127, (Size::Dword, -133))] {
+            let mut asm = Assembler::new();
+            let label = asm.label();
+
+            asm.bind(label);
+            asm.data(&vec![0x90; padding]);
+            asm.branch(Mnemonic::j(Condition::ne), label);
+
+            let assembled = assemble(&asm);
+
+            assert_eq!(branches(&assembled), [expected]);
+            assert_eq!(assembled.offset(label), 0);
+        }
+    }
+
+    #[test]
+    fn growing_jump_pushes_another_out_of_reach() {
+        let mut asm = Assembler::new();
+        let first = asm.label();
+        let second = asm.label();
+
+        // short, the first target is 126 bytes away and the second 128
+        asm.branch(Mnemonic::jmp, first);
+        asm.branch(Mnemonic::j(Condition::e), second);
+        asm.data(&[0x90; 124]);
+        asm.bind(first);
+        asm.data(&[0x90; 4]);
+        asm.bind(second);
+
+        let assembled = assemble(&asm);
+
+        // growing the second jump moves the first target 4 bytes further
+        assert_eq!(
+            branches(&assembled),
+            [(Size::Dword, 130), (Size::Dword, 128)]
+        );
+        assert_eq!(&assembled.bytes[..1], [0xe9]);
+        assert_eq!(&assembled.bytes[5..7], [0x0f, 0x84]);
+    }
+
+    #[test]
+    fn call_is_always_near() {
+        let mut asm = Assembler::new();
+        let label = asm.label();
+
+        asm.branch(Mnemonic::call, label);
+        asm.bind(label);
+
+        let assembled = assemble(&asm);
+
+        assert_eq!(branches(&assembled), [(Size::Dword, 0)]);
+        assert_eq!(assembled.bytes, [0xe8, 0, 0, 0, 0]);
+    }
+}
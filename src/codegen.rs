@@ -0,0 +1,2048 @@
+use super::assembler::{Assembled, Assembler, Label, Reference};
+use super::diagnostic::Diagnostics;
+use super::encoder::{Condition, Instruction, Memory, Mnemonic, Mode, Operand, Size};
+use super::layout::align_up;
+use super::lexer::escape;
+use super::op::{Op, Register};
+use super::parser::{
+    Argument, Assign, Binary, BinaryOp, Call, Extern, Function, Import, Init, Let, Source,
+    Statement, Static, Syscall, Type, Unary, UnaryOp,
+};
+use super::relocation::Kind;
+use super::span::Span;
+
+/// Removes the quotes around a string literal and expands escapes. The parser reports unknown
+/// escapes, they're kept as they are.
+pub fn unescape(string: &str) -> String {
+    let mut chars = string[1..string.len() - 1].chars();
+    let mut unescaped = String::with_capacity(string.len());
+
+    while let Some(char0) = chars.next() {
+        if char0 != '\\' {
+            unescaped.push(char0);
+            continue;
+        }
+
+        match chars.next() {
+            Some(char1) => match escape(char1) {
+                Some(char1) => unescaped.push(char1),
+                None => {
+                    unescaped.push(char0);
+                    unescaped.push(char1);
+                }
+            },
+            None => unescaped.push(char0),
+        }
+    }
+
+    unescaped
+}
+
+/// Reference targets of the data sections.
+pub const RODATA: usize = 0;
+pub const DATA: usize = 1;
+pub const BSS: usize = 2;
+/// Reference target of the first external static or imported function, the nth one is
+/// `EXTERN + n`.
+pub const EXTERN: usize = 3;
+
+/// Largest static, so that every offset fits an ELF32 file.
+const STATIC_MAX: u64 = u32::MAX as u64;
+
+/// Checks that an integer fits the given type.
+fn check_integer(kind: &Type, value: u64) -> Result<(), String> {
+    match kind {
+        Type::Array(..) => Err(format!(
+            "mismatched types, expected `{}`, found integer",
+            kind.display()
+        )),
+        integer => {
+            // bits the value may use, the sign bit has to stay clear
+            let bits = integer.size() * 8 - integer.is_signed() as u64;
+
+            if bits < 64 && value >> bits != 0 {
+                return Err(format!("`{value}` doesn't fit in `{}`", kind.display()));
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Checks that the negation of an integer fits the given type.
+fn check_negative(kind: &Type, value: u64) -> Result<(), String> {
+    let bits = kind.size() * 8;
+
+    if !kind.is_signed() || value > 1 << (bits - 1) {
+        return Err(format!("`-{value}` doesn't fit in `{}`", kind.display()));
+    }
+
+    Ok(())
+}
+
+/// Operand size of an integer type.
+fn operand_size(kind: &Type) -> Size {
+    match kind.size() {
+        1 => Size::Byte,
+        2 => Size::Word,
+        4 => Size::Dword,
+        _ => Size::Qword,
+    }
+}
+
+/// Checks a static's initializer against its type.
+fn check_init(kind: &Type, init: &Init) -> Result<(), String> {
+    match (kind, init) {
+        (_, Init::Integer(value)) => check_integer(kind, *value),
+        (Type::Array(element, len), Init::String(string)) if **element == Type::U8 => {
+            let string = unescape(string);
+
+            if string.len() as u64 > *len {
+                return Err(format!(
+                    "a string of {} bytes doesn't fit in `{}`",
+                    string.len(),
+                    kind.display()
+                ));
+            }
+
+            Ok(())
+        }
+        (Type::Array(element, len), Init::Repeat(value, count)) => {
+            if count != len {
+                return Err(format!(
+                    "mismatched types, expected an array of {len}, found one of {count}"
+                ));
+            }
+
+            check_integer(element, *value)
+        }
+        (_, Init::String(_)) => Err(format!(
+            "mismatched types, expected `{}`, found string",
+            kind.display()
+        )),
+        (_, Init::Repeat(..)) => Err(format!(
+            "mismatched types, expected `{}`, found array",
+            kind.display()
+        )),
+    }
+}
+
+/// Little endian bytes of a checked initializer.
+fn init_bytes(kind: &Type, init: &Init) -> Vec<u8> {
+    match (kind, init) {
+        (_, Init::Integer(value)) => value.to_le_bytes()[..kind.size() as usize].to_vec(),
+        (Type::Array(element, len), Init::Repeat(value, _)) => {
+            init_bytes(element, &Init::Integer(*value)).repeat(*len as usize)
+        }
+        (_, Init::Repeat(..)) => unreachable!("unchecked initializer"),
+        (_, Init::String(string)) => {
+            let mut bytes = unescape(string).into_bytes();
+
+            bytes.resize(kind.size() as usize, 0);
+
+            bytes
+        }
+    }
+}
+
+/// Whether an initializer is all zeroes, without building it.
+fn is_zeroed(init: &Init) -> bool {
+    match init {
+        Init::Integer(value) | Init::Repeat(value, _) => *value == 0,
+        Init::String(string) => unescape(string).bytes().all(|byte| byte == 0),
+    }
+}
+
+/// Appends `bytes` aligned to `align`, returning their offset.
+fn append(section: &mut Vec<u8>, bytes: &[u8], align: u64) -> u64 {
+    section.resize(align_up(section.len() as u64, align) as usize, 0);
+
+    let offset = section.len() as u64;
+
+    section.extend(bytes);
+
+    offset
+}
+
+#[derive(Debug)]
+pub struct Code {
+    pub asm: Assembler,
+    /// string literals with their offset into `rodata`, each stored once
+    pub strings: Vec<(u64, String)>,
+    /// contents of `.rodata`
+    pub rodata: Vec<u8>,
+    /// contents of `.data`
+    pub data: Vec<u8>,
+    /// size of `.bss`
+    pub bss: u64,
+    /// name, reference target, offset and size of each static
+    pub statics: Vec<(String, usize, u64, u64)>,
+    /// name of each external static and imported function
+    pub externs: Vec<String>,
+    /// name, reference target, parameter count, whether it's variadic and return type, of each
+    /// imported function
+    pub imports: Vec<(String, usize, usize, bool, Option<Type>)>,
+    /// end of the `_start` stub, which begins the code outside of objects
+    pub start: Option<Label>,
+    /// name, span, start, end of the prologue and end of each function
+    pub functions: Vec<(String, Span, Label, Label, Label)>,
+    /// name, parameter types, return type and start of each function defined with `fn`
+    signatures: Vec<(String, Vec<Type>, Option<Type>, Label)>,
+    /// name, span, type and frame offset of each local, by function
+    pub locals: Vec<Vec<(String, Span, Type, i32)>>,
+    mode: Mode,
+    relocatable: bool,
+    /// name, whether it's mutable, type and frame offset of each local in scope, the latest
+    /// last so it shadows earlier ones
+    scope: Vec<(String, bool, Type, i32)>,
+    /// values pushed by the statement being lowered, which calls align the stack for
+    depth: usize,
+    /// start and end of each loop being lowered, the innermost last
+    loops: Vec<(Label, Label)>,
+    /// name, return type and epilogue of the function being lowered
+    function: Option<(String, Option<Type>, Label)>,
+    /// bytes of the frame below rbp in use
+    frame: i32,
+}
+
+impl Default for Code {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Code {
+    pub fn new() -> Self {
+        Self {
+            asm: Assembler::new(),
+            strings: vec![],
+            rodata: vec![],
+            data: vec![],
+            bss: 0,
+            statics: vec![],
+            externs: vec![],
+            imports: vec![],
+            start: None,
+            functions: vec![],
+            signatures: vec![],
+            locals: vec![],
+            mode: Mode::Long,
+            relocatable: false,
+            scope: vec![],
+            depth: 0,
+            loops: vec![],
+            function: None,
+            frame: 0,
+        }
+    }
+
+    /// Selects the x86-64 or the i386 backend.
+    pub fn mode(&mut self, mode: Mode) -> &mut Code {
+        self.mode = mode;
+        self.asm.mode(mode);
+        self
+    }
+
+    /// Lowers for a relocatable object, where external statics can be referred to.
+    pub fn relocatable(&mut self) -> &mut Code {
+        self.relocatable = true;
+        self
+    }
+
+    fn mov_int(&mut self, register: Register, int: u64) {
+        if int == 0 {
+            self.asm.push(Op::xor64(register, register));
+        } else {
+            self.asm.push(Op::mov64_int(register, int as i64));
+        }
+    }
+
+    /// Offset of a string literal in `.rodata`, reusing an identical literal.
+    fn string(&mut self, string: String) -> u64 {
+        if let Some((offset, _)) = self.strings.iter().find(|(_, other)| *other == string) {
+            return *offset;
+        }
+
+        let offset = self.rodata.len() as u64;
+
+        self.rodata.extend(string.as_bytes());
+        self.strings.push((offset, string));
+
+        offset
+    }
+
+    /// Loads the address of a string literal.
+    fn lea_string(&mut self, register: Register, string: String) {
+        let offset = self.string(string);
+
+        self.lea_reference(register, RODATA, offset);
+    }
+
+    /// Reference target and offset of a static, reporting an error if there is none.
+    fn find_static(
+        &self,
+        name: &str,
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> Option<(usize, u64)> {
+        let found = self
+            .statics
+            .iter()
+            .find(|(ident, ..)| ident == name)
+            .map(|(_, target, offset, _)| (*target, *offset));
+
+        if found.is_none() {
+            diagnostics.error(span, format!("cannot find value `{name}`"));
+        }
+
+        found
+    }
+
+    /// Whether, type and frame offset of the local in scope named `name`.
+    fn find_local(&self, name: &str) -> Option<(bool, Type, i32)> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(ident, ..)| ident == name)
+            .map(|(_, mutable, kind, offset)| (*mutable, kind.clone(), *offset))
+    }
+
+    /// Size of a register, and of each local's slot in the frame.
+    fn word(&self) -> i32 {
+        match self.mode {
+            Mode::Long => 8,
+            Mode::Protected => 4,
+        }
+    }
+
+    /// Operand size of a whole register.
+    fn word_size(&self) -> Size {
+        match self.mode {
+            Mode::Long => Size::Qword,
+            Mode::Protected => Size::Dword,
+        }
+    }
+
+    /// Type of a local declared without one, as wide as a register.
+    fn word_type(&self, signed: bool) -> Type {
+        match (self.mode, signed) {
+            (Mode::Long, false) => Type::U64,
+            (Mode::Long, true) => Type::I64,
+            (Mode::Protected, false) => Type::U32,
+            (Mode::Protected, true) => Type::I32,
+        }
+    }
+
+    /// The frame slot `offset` bytes from rbp.
+    fn slot(offset: i32) -> Operand {
+        Operand::Memory(Memory::base(Register::rbp).displacement(offset))
+    }
+
+    /// Loads a local, zero or sign extending it to the whole register.
+    fn load_local(&mut self, register: Register, kind: &Type, offset: i32) {
+        let (mnemonic, size) = match (operand_size(kind), kind.is_signed()) {
+            (size, _) if size == self.word_size() => (Mnemonic::mov, size),
+            // writing a 32-bit register clears the upper half
+            (Size::Dword, false) => (Mnemonic::mov, Size::Dword),
+            (from, false) => (Mnemonic::movzx(from), Size::Dword),
+            (from, true) => (Mnemonic::movsx(from), self.word_size()),
+        };
+
+        self.asm.push(Instruction::new(
+            mnemonic,
+            size,
+            &[Operand::Register(register), Self::slot(offset)],
+        ));
+    }
+
+    /// Stores the low bytes of `register` in a local.
+    fn store_local(&mut self, register: Register, kind: &Type, offset: i32) {
+        self.asm.push(Instruction::new(
+            Mnemonic::mov,
+            operand_size(kind),
+            &[Self::slot(offset), Operand::Register(register)],
+        ));
+    }
+
+    /// Truncates rax to `kind`, then zero or sign extends it back to the whole register.
+    fn extend(&mut self, kind: &Type) {
+        let (mnemonic, size) = match (operand_size(kind), kind.is_signed()) {
+            (size, _) if size == self.word_size() => return,
+            (Size::Dword, false) => (Mnemonic::mov, Size::Dword),
+            (from, false) => (Mnemonic::movzx(from), Size::Dword),
+            (from, true) => (Mnemonic::movsx(from), self.word_size()),
+        };
+
+        self.push_registers_sized(mnemonic, size);
+    }
+
+    /// Loads an integer, the value of a local or the address of a static into `register`.
+    fn load(&mut self, register: Register, arg: &Argument, diagnostics: &mut Diagnostics) {
+        match (arg, self.mode) {
+            (Argument::U64(int), Mode::Long) => self.mov_int(register, *int),
+            (Argument::U64(int), Mode::Protected) => {
+                if Self::check_int32(*int, self.asm.current_span(), diagnostics) {
+                    self.mov_int32(register, *int as u32);
+                }
+            }
+            (Argument::Name(name, span), mode) => {
+                if let Some((_, kind, offset)) = self.find_local(name) {
+                    self.load_local(register, &kind, offset);
+                } else if let Some((target, offset)) = self.find_static(name, *span, diagnostics) {
+                    match mode {
+                        Mode::Long => self.lea_reference(register, target, offset),
+                        Mode::Protected => self.mov_reference32(register, target, offset),
+                    }
+                }
+            }
+            _ => unreachable!("strings and nested calls are loaded by the caller"),
+        }
+    }
+
+    /// Loads the address `offset` bytes into a data section.
+    fn lea_reference(&mut self, register: Register, target: usize, offset: u64) {
+        let lea = Instruction::new(
+            Mnemonic::lea,
+            Size::Qword,
+            &[Operand::Register(register), Operand::Memory(Memory::rip(0))],
+        );
+
+        self.asm.reference(
+            lea,
+            Reference {
+                kind: Kind::Relative32,
+                target,
+                offset,
+            },
+        );
+    }
+
+    /// Reports an integer which doesn't fit the registers of i386.
+    fn check_int32(int: u64, span: Span, diagnostics: &mut Diagnostics) -> bool {
+        if int > u32::MAX as u64 {
+            diagnostics
+                .error(span, format!("`{int}` doesn't fit in 32 bits"))
+                .note("registers are 32 bits wide on i386");
+
+            return false;
+        }
+
+        true
+    }
+
+    fn mov_int32(&mut self, register: Register, int: u32) {
+        if int == 0 {
+            self.asm.push(Op::xor32(register, register));
+        } else {
+            self.asm.push(Op::mov32_int(register, int));
+        }
+    }
+
+    /// Loads the absolute address of a string literal, there is no rip-relative addressing in
+    /// 32-bit mode.
+    fn mov_string32(&mut self, register: Register, string: String) {
+        let offset = self.string(string);
+
+        self.mov_reference32(register, RODATA, offset);
+    }
+
+    /// Loads the absolute address `offset` bytes into a data section.
+    fn mov_reference32(&mut self, register: Register, target: usize, offset: u64) {
+        self.asm.reference(
+            Op::mov32_int(register, 0).instruction(),
+            Reference {
+                kind: Kind::Absolute32,
+                target,
+                offset,
+            },
+        );
+    }
+
+    /// Places a static in `.rodata` if it's immutable, and otherwise in `.bss` if it's
+    /// zeroed or in `.data`.
+    fn lower_static(&mut self, item: &Static, diagnostics: &mut Diagnostics) {
+        if self.statics.iter().any(|(ident, ..)| *ident == item.ident) {
+            diagnostics.error(
+                item.span,
+                format!("the name `{}` is defined multiple times", item.ident),
+            );
+
+            return;
+        }
+
+        let len = item.kind.size();
+        let align = item.kind.align();
+
+        if len > STATIC_MAX {
+            diagnostics
+                .error(item.span, format!("`{}` is too large", item.ident))
+                .note(format!("statics are at most {STATIC_MAX} bytes"));
+
+            return;
+        }
+
+        if let Err(message) = check_init(&item.kind, &item.init) {
+            diagnostics.error(item.span, message);
+
+            return;
+        }
+
+        let (target, offset) = if item.mutable && is_zeroed(&item.init) {
+            let offset = align_up(self.bss, align);
+
+            self.bss = offset + len;
+
+            (BSS, offset)
+        } else if item.mutable {
+            let bytes = init_bytes(&item.kind, &item.init);
+
+            (DATA, append(&mut self.data, &bytes, align))
+        } else {
+            let bytes = init_bytes(&item.kind, &item.init);
+
+            (RODATA, append(&mut self.rodata, &bytes, align))
+        };
+
+        self.statics.push((item.ident.clone(), target, offset, len));
+    }
+
+    /// Whether a static or a function is already named `name`.
+    fn is_defined(&self, name: &str) -> bool {
+        self.statics.iter().any(|(ident, ..)| ident == name)
+            || self.imports.iter().any(|(ident, ..)| ident == name)
+            || self.signatures.iter().any(|(ident, ..)| ident == name)
+    }
+
+    /// Declares a static for the linker to find in another object.
+    fn lower_extern(&mut self, item: &Extern, diagnostics: &mut Diagnostics) {
+        if self.is_defined(&item.ident) {
+            diagnostics.error(
+                item.span,
+                format!("the name `{}` is defined multiple times", item.ident),
+            );
+
+            return;
+        }
+
+        // still declared, so uses aren't reported too
+        if !self.relocatable {
+            diagnostics
+                .error(
+                    item.span,
+                    format!("`{}` is defined in another object", item.ident),
+                )
+                .note(format!(
+                    "build an object with `-c` and link it with the object defining `{}`",
+                    item.ident
+                ));
+        }
+
+        let target = EXTERN + self.externs.len();
+
+        self.externs.push(item.ident.clone());
+        self.statics
+            .push((item.ident.clone(), target, 0, item.kind.size()));
+    }
+
+    /// Declares a function for the dynamic linker to find in a shared library, or for the
+    /// linker to find in another object.
+    fn lower_import(&mut self, item: &Import, diagnostics: &mut Diagnostics) {
+        if self.is_defined(&item.ident) {
+            diagnostics.error(
+                item.span,
+                format!("the name `{}` is defined multiple times", item.ident),
+            );
+
+            return;
+        }
+
+        // still declared, so calls aren't reported too
+        if self.mode == Mode::Protected {
+            diagnostics
+                .error(
+                    item.span,
+                    format!("`{}` can't be imported in 32-bit mode", item.ident),
+                )
+                .note("calls follow the SysV x86-64 ABI, use `-m64`");
+        }
+
+        let target = EXTERN + self.externs.len();
+
+        self.externs.push(item.ident.clone());
+        self.imports.push((
+            item.ident.clone(),
+            target,
+            item.params.len(),
+            item.variadic,
+            item.ret.clone(),
+        ));
+    }
+
+    pub fn lower(&mut self, source: &Source, diagnostics: &mut Diagnostics) {
+        // statics first, so functions can refer to statics declared after them
+        for item in source.statics() {
+            self.lower_static(item, diagnostics);
+        }
+
+        for item in source.externs() {
+            self.lower_extern(item, diagnostics);
+        }
+
+        for item in source.imports() {
+            self.lower_import(item, diagnostics);
+        }
+
+        // every signature first, so functions can call functions defined after them
+        let starts: Vec<Label> = source
+            .functions()
+            .map(|function| self.declare_function(function, diagnostics))
+            .collect();
+
+        // an object is linked into a program which brings its own `_start`
+        if !self.relocatable {
+            self.lower_start(source, diagnostics);
+        }
+
+        for (function, start) in source.functions().zip(starts) {
+            self.lower_function(function, start, diagnostics);
+        }
+    }
+
+    /// Lowers the `_start` stub, which aligns the stack, calls `entry` and exits with its
+    /// return value, or 0 if it doesn't return one. Programs importing functions exit
+    /// through libc's `exit` so its buffers are flushed, others with the exit syscall.
+    fn lower_start(&mut self, source: &Source, diagnostics: &mut Diagnostics) {
+        let function = match source
+            .functions()
+            .find(|function| function.ident == "entry")
+        {
+            Some(function) => function,
+            None => {
+                diagnostics
+                    .error(Span::new(0, 0), "`entry` function not found")
+                    .note("programs start by calling `fn entry()`");
+
+                return;
+            }
+        };
+
+        if !function.params.is_empty() {
+            diagnostics
+                .error(function.span, "`entry` can't take parameters")
+                .note("it's called by the `_start` stub with no arguments");
+        }
+
+        let (ret, start) = match self.signatures.iter().find(|(ident, ..)| ident == "entry") {
+            Some((_, _, ret, start)) => (ret.is_some(), *start),
+            None => return,
+        };
+
+        let (status, number) = match self.mode {
+            Mode::Long => (Register::rdi, 60),
+            Mode::Protected => (Register::rbx, 1),
+        };
+
+        self.asm.span(function.span);
+
+        // a zero frame pointer marks the outermost frame for debuggers
+        self.asm.push(Op::xor32(Register::rbp, Register::rbp));
+        self.asm.push(match self.mode {
+            Mode::Long => Op::and64_int(Register::rsp, -16),
+            Mode::Protected => Op::and32_int(Register::rsp, -16),
+        });
+        self.asm.branch(Mnemonic::call, start);
+
+        if ret {
+            self.asm.push(Op::mov32(Register::rax, status));
+        } else {
+            self.asm.push(Op::xor32(status, status));
+        }
+
+        let exit = match self.imports.iter().find(|(ident, ..)| ident == "exit") {
+            Some((_, target, ..)) => Some(*target),
+            // only added when libc is linked anyway, and no other item is named `exit`
+            None if !self.imports.is_empty() && !self.is_defined("exit") => {
+                let target = EXTERN + self.externs.len();
+
+                self.externs.push("exit".to_string());
+                self.imports
+                    .push(("exit".to_string(), target, 1, false, None));
+
+                Some(target)
+            }
+            None => None,
+        };
+
+        match (self.mode, exit) {
+            (Mode::Long, Some(target)) => self.asm.reference(
+                Op::call(0).instruction(),
+                Reference {
+                    kind: Kind::Plt32,
+                    target,
+                    offset: 0,
+                },
+            ),
+            (Mode::Long, None) => {
+                self.mov_int(Register::rax, number);
+                self.asm.push(Op::syscall);
+            }
+            // imports are an error on i386
+            (Mode::Protected, _) => {
+                self.mov_int32(Register::rax, number as u32);
+                self.asm.push(Op::int(0x80));
+            }
+        }
+
+        let end = self.asm.label();
+
+        self.asm.bind(end);
+        self.start = Some(end);
+    }
+
+    /// Type of a local or parameter declared as `kind`, reporting an error and replacing it
+    /// if it doesn't fit a register.
+    fn local_type(
+        &self,
+        name: &str,
+        kind: &Type,
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> Type {
+        match kind {
+            Type::Array(..) => {
+                diagnostics
+                    .error(span, format!("`{name}` can't be an array"))
+                    .note("locals are integers, arrays can be `static`");
+
+                self.word_type(false)
+            }
+            kind if kind.size() > 4 && self.mode == Mode::Protected => {
+                diagnostics
+                    .error(span, format!("`{name}` can't be a `{}`", kind.display()))
+                    .note("registers are 32 bits wide on i386");
+
+                self.word_type(kind.is_signed())
+            }
+            kind => kind.clone(),
+        }
+    }
+
+    /// Declares a function so calls can find it, returning the label of its start.
+    fn declare_function(&mut self, function: &Function, diagnostics: &mut Diagnostics) -> Label {
+        let start = self.asm.label();
+
+        if self.is_defined(&function.ident) {
+            diagnostics.error(
+                function.span,
+                format!("the name `{}` is defined multiple times", function.ident),
+            );
+
+            return start;
+        }
+
+        if self.mode == Mode::Long && function.params.len() > Register::CALL.len() {
+            diagnostics
+                .error(
+                    function.span,
+                    format!(
+                        "functions take at most 6 parameters, `{}` has {}",
+                        function.ident,
+                        function.params.len()
+                    ),
+                )
+                .note("parameters past the sixth would go on the stack, which isn't supported");
+        }
+
+        let params = function
+            .params
+            .iter()
+            .map(|(name, kind)| self.local_type(name, kind, function.span, diagnostics))
+            .collect();
+
+        let ret = function
+            .ret
+            .as_ref()
+            .map(|kind| self.local_type(&function.ident, kind, function.span, diagnostics));
+
+        self.signatures
+            .push((function.ident.clone(), params, ret, start));
+
+        start
+    }
+
+    /// Lowers a function with a frame for its parameters and locals. On x86-64 the
+    /// parameters arrive in rdi, rsi, rdx, rcx, r8 and r9 and are stored in the frame, on
+    /// i386 they're on the stack above the return address. The return value is left in rax.
+    fn lower_function(&mut self, function: &Function, start: Label, diagnostics: &mut Diagnostics) {
+        let prologue = self.asm.label();
+        let epilogue = self.asm.label();
+        let end = self.asm.label();
+
+        // a duplicate isn't declared, its body is still checked
+        let (params, ret) = match self.signatures.iter().find(|(.., other)| *other == start) {
+            Some((_, params, ret, _)) => (params.clone(), ret.clone()),
+            None => (vec![], None),
+        };
+
+        self.asm.span(function.span);
+        self.asm.bind(start);
+        self.locals.push(vec![]);
+
+        let stored = match self.mode {
+            Mode::Long => params.len(),
+            Mode::Protected => 0,
+        };
+
+        self.enter((Self::count_lets(&function.body) + stored) as i32 * self.word());
+
+        for (index, ((name, _), kind)) in function.params.iter().zip(&params).enumerate() {
+            let offset = match self.mode {
+                Mode::Long => {
+                    let offset = self.allocate();
+
+                    if let Some(register) = Register::CALL.get(index) {
+                        self.store_local(*register, kind, offset);
+                    }
+
+                    offset
+                }
+                // past the saved ebp and the return address
+                Mode::Protected => 8 + 4 * index as i32,
+            };
+
+            self.declare_local(name, false, kind.clone(), function.span, offset);
+        }
+
+        self.asm.bind(prologue);
+        self.function = Some((function.ident.clone(), ret.clone(), epilogue));
+        self.lower_block(&function.body, diagnostics);
+
+        if let Some(kind) = &ret {
+            if !Self::returns(&function.body) {
+                diagnostics
+                    .error(
+                        function.span,
+                        format!(
+                            "`{}` returns `{}`, but its body can end without a value",
+                            function.ident,
+                            kind.display()
+                        ),
+                    )
+                    .note("end it with `return VALUE`");
+            }
+        }
+
+        self.asm.span(function.span);
+        self.asm.bind(epilogue);
+        self.leave();
+        self.asm.bind(end);
+        self.function = None;
+        self.functions
+            .push((function.ident.clone(), function.span, start, prologue, end));
+    }
+
+    /// Whether a block always ends in `return`, or in a loop it never breaks out of.
+    fn returns(body: &[Statement]) -> bool {
+        match body.last() {
+            Some(Statement::Return(..)) => true,
+            Some(Statement::If(item)) => {
+                !item.otherwise.is_empty()
+                    && Self::returns(&item.then)
+                    && Self::returns(&item.otherwise)
+            }
+            Some(Statement::Loop(body, _)) => !Self::breaks(body),
+            _ => false,
+        }
+    }
+
+    /// Whether a loop's body breaks out of it, breaks in nested loops don't.
+    fn breaks(body: &[Statement]) -> bool {
+        body.iter().any(|statement| match statement {
+            Statement::Break(_) => true,
+            Statement::If(item) => Self::breaks(&item.then) || Self::breaks(&item.otherwise),
+            _ => false,
+        })
+    }
+
+    /// Number of `let`s in a block and the blocks nested in it, each has its own slot.
+    fn count_lets(body: &[Statement]) -> usize {
+        body.iter()
+            .map(|statement| match statement {
+                Statement::Let(_) => 1,
+                Statement::If(item) => {
+                    Self::count_lets(&item.then) + Self::count_lets(&item.otherwise)
+                }
+                Statement::While(item) => Self::count_lets(&item.body),
+                Statement::Loop(body, _) => Self::count_lets(body),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Lowers the statements of a block, locals declared in it go out of scope at its end.
+    fn lower_block(&mut self, body: &[Statement], diagnostics: &mut Diagnostics) {
+        let scope = self.scope.len();
+
+        for statement in body {
+            self.lower_statement(statement, diagnostics);
+        }
+
+        self.scope.truncate(scope);
+    }
+
+    fn lower_statement(&mut self, statement: &Statement, diagnostics: &mut Diagnostics) {
+        match statement {
+            Statement::Syscall(syscall) => {
+                self.asm.span(syscall.span);
+                self.lower_syscall(syscall, diagnostics);
+            }
+            Statement::Call(call) => {
+                self.asm.span(call.span);
+                self.lower_call(call, diagnostics);
+            }
+            Statement::Let(item) => {
+                self.asm.span(item.span);
+                self.lower_let(item, diagnostics);
+            }
+            Statement::Assign(assign) => {
+                self.asm.span(assign.span);
+                self.lower_assign(assign, diagnostics);
+            }
+            Statement::If(item) => {
+                let otherwise = self.asm.label();
+                let end = self.asm.label();
+
+                self.asm.span(item.span);
+                self.lower_branch(&item.condition, item.span, otherwise, diagnostics);
+                self.lower_block(&item.then, diagnostics);
+
+                if item.otherwise.is_empty() {
+                    self.asm.bind(otherwise);
+                } else {
+                    self.asm.branch(Mnemonic::jmp, end);
+                    self.asm.bind(otherwise);
+                    self.lower_block(&item.otherwise, diagnostics);
+                }
+
+                self.asm.bind(end);
+            }
+            Statement::While(item) => {
+                let start = self.asm.label();
+                let end = self.asm.label();
+
+                self.asm.span(item.span);
+                self.asm.bind(start);
+                self.lower_branch(&item.condition, item.span, end, diagnostics);
+                self.loops.push((start, end));
+                self.lower_block(&item.body, diagnostics);
+                self.loops.pop();
+                self.asm.span(item.span);
+                self.asm.branch(Mnemonic::jmp, start);
+                self.asm.bind(end);
+            }
+            Statement::Loop(body, span) => {
+                let start = self.asm.label();
+                let end = self.asm.label();
+
+                self.asm.span(*span);
+                self.asm.bind(start);
+                self.loops.push((start, end));
+                self.lower_block(body, diagnostics);
+                self.loops.pop();
+                self.asm.span(*span);
+                self.asm.branch(Mnemonic::jmp, start);
+                self.asm.bind(end);
+            }
+            Statement::Break(span) | Statement::Continue(span) => {
+                let (keyword, target) = match statement {
+                    Statement::Break(_) => ("break", self.loops.last().map(|(_, end)| *end)),
+                    _ => ("continue", self.loops.last().map(|(start, _)| *start)),
+                };
+
+                match target {
+                    Some(target) => {
+                        self.asm.span(*span);
+                        self.asm.branch(Mnemonic::jmp, target);
+                    }
+                    None => {
+                        diagnostics.error(*span, format!("`{keyword}` outside of a loop"));
+                    }
+                }
+            }
+            Statement::Return(value, span) => {
+                self.asm.span(*span);
+                self.lower_return(value.as_ref(), *span, diagnostics);
+            }
+            Statement::Error(_) => {}
+        }
+    }
+
+    /// Evaluates the return value into rax and jumps to the epilogue.
+    fn lower_return(
+        &mut self,
+        value: Option<&Argument>,
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) {
+        let (name, ret, epilogue) = match self.function.clone() {
+            Some(function) => function,
+            None => return,
+        };
+
+        match (value, ret) {
+            (Some(value), Some(kind)) => {
+                if self.lower_value(value, &kind, span, diagnostics) {
+                    self.extend(&kind);
+                }
+            }
+            (None, None) => {}
+            (Some(_), None) => {
+                diagnostics
+                    .error(span, format!("`{name}` doesn't return a value"))
+                    .note(format!("declare its return type: `fn {name}(...) -> u64`"));
+            }
+            (None, Some(kind)) => {
+                diagnostics.error(
+                    span,
+                    format!(
+                        "`{name}` returns `{}`, `return` needs a value",
+                        kind.display()
+                    ),
+                );
+            }
+        }
+
+        self.asm.branch(Mnemonic::jmp, epilogue);
+    }
+
+    /// Builds a frame of `len` bytes for locals below the saved rbp, and aligns the stack
+    /// to 16 bytes whatever its alignment was on entry. `leave` tears it down.
+    fn enter(&mut self, len: i32) {
+        let len = (len + 15) / 16 * 16;
+
+        match self.mode {
+            Mode::Long => {
+                self.asm.push(Op::push64(Register::rbp));
+                self.asm.push(Op::mov64(Register::rsp, Register::rbp));
+
+                if len > 0 {
+                    self.asm.push(Op::sub64_int(Register::rsp, len));
+                }
+
+                self.asm.push(Op::and64_int(Register::rsp, -16));
+            }
+            Mode::Protected => {
+                self.asm.push(Op::push32(Register::rbp));
+                self.asm.push(Op::mov32(Register::rsp, Register::rbp));
+
+                for register in Register::SAVED32 {
+                    self.asm.push(Op::push32(register));
+                }
+
+                if len > 0 {
+                    self.asm.push(Op::sub32_int(Register::rsp, len));
+                }
+
+                self.asm.push(Op::and32_int(Register::rsp, -16));
+            }
+        }
+
+        self.depth = 0;
+        self.frame = match self.mode {
+            Mode::Long => 0,
+            Mode::Protected => 4 * Register::SAVED32.len() as i32,
+        };
+    }
+
+    /// Restores the registers the caller expects preserved, tears down the frame and
+    /// returns.
+    fn leave(&mut self) {
+        if self.mode == Mode::Protected {
+            for (index, register) in Register::SAVED32.iter().enumerate() {
+                self.asm.push(Instruction::new(
+                    Mnemonic::mov,
+                    Size::Dword,
+                    &[
+                        Operand::Register(*register),
+                        Self::slot(-4 * (index as i32 + 1)),
+                    ],
+                ));
+            }
+        }
+
+        self.asm.push(Op::leave);
+        self.asm.push(Op::ret);
+    }
+
+    /// Reserves the next slot of the frame, returning its offset from rbp.
+    fn allocate(&mut self) -> i32 {
+        self.frame += self.word();
+
+        -self.frame
+    }
+
+    /// Brings a local into scope, and records it for debug information.
+    fn declare_local(&mut self, name: &str, mutable: bool, kind: Type, span: Span, offset: i32) {
+        self.scope
+            .push((name.to_string(), mutable, kind.clone(), offset));
+
+        if let Some(locals) = self.locals.last_mut() {
+            locals.push((name.to_string(), span, kind, offset));
+        }
+    }
+
+    /// Evaluates the value of a local into rax, reporting an error if it can't be stored in
+    /// `kind`. Returns whether it could.
+    fn lower_value(
+        &mut self,
+        value: &Argument,
+        kind: &Type,
+        span: Span,
+        diagnostics: &mut Diagnostics,
+    ) -> bool {
+        match value {
+            Argument::String(_) => {
+                diagnostics
+                    .error(span, "a string can't be stored in a variable")
+                    .note("store it in a `static` and use its name instead");
+
+                return false;
+            }
+            Argument::U64(int) => {
+                if let Err(message) = check_integer(kind, *int) {
+                    diagnostics.error(span, message);
+
+                    return false;
+                }
+            }
+            Argument::Unary(unary) => {
+                if let (UnaryOp::Neg, Argument::U64(int)) = (unary.op, &unary.value) {
+                    if let Err(message) = check_negative(kind, *int) {
+                        diagnostics.error(span, message);
+
+                        return false;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.lower_expression(value, diagnostics);
+
+        true
+    }
+
+    /// Evaluates a `let` into the local's frame slot, and brings the local into scope.
+    fn lower_let(&mut self, item: &Let, diagnostics: &mut Diagnostics) {
+        let kind = match &item.kind {
+            Some(kind) => self.local_type(&item.ident, kind, item.span, diagnostics),
+            None => self.word_type(self.is_signed(&item.value)),
+        };
+
+        // each `let` has its own slot, and the value may refer to a local it shadows
+        let offset = self.allocate();
+
+        if self.lower_value(&item.value, &kind, item.span, diagnostics) {
+            self.store_local(Register::rax, &kind, offset);
+        }
+
+        self.declare_local(&item.ident, item.mutable, kind, item.span, offset);
+    }
+
+    /// Evaluates an assignment into a mutable local's frame slot.
+    fn lower_assign(&mut self, assign: &Assign, diagnostics: &mut Diagnostics) {
+        let (mutable, kind, offset) = match self.find_local(&assign.ident) {
+            Some(local) => local,
+            None if self
+                .statics
+                .iter()
+                .any(|(ident, ..)| *ident == assign.ident) =>
+            {
+                diagnostics
+                    .error(
+                        assign.span,
+                        format!("cannot assign to static `{}`", assign.ident),
+                    )
+                    .note("statics are written through their address, by syscalls and calls");
+
+                return;
+            }
+            None => {
+                diagnostics.error(
+                    assign.span,
+                    format!("cannot find variable `{}`", assign.ident),
+                );
+
+                return;
+            }
+        };
+
+        if !mutable {
+            diagnostics
+                .error(
+                    assign.span,
+                    format!(
+                        "cannot assign twice to immutable variable `{}`",
+                        assign.ident
+                    ),
+                )
+                .note(format!("make it mutable: `let mut {}`", assign.ident));
+
+            return;
+        }
+
+        if self.lower_value(&assign.value, &kind, assign.span, diagnostics) {
+            self.store_local(Register::rax, &kind, offset);
+        }
+    }
+
+    /// Lowers a syscall for the selected backend.
+    ///
+    /// A string argument is passed as its address followed by its length, and fills two
+    /// registers.
+    pub fn lower_syscall(&mut self, syscall: &Syscall, diagnostics: &mut Diagnostics) {
+        match self.mode {
+            Mode::Long => self.lower_syscall64(syscall, diagnostics),
+            Mode::Protected => self.lower_syscall32(syscall, diagnostics),
+        }
+    }
+
+    /// Number of argument registers a syscall fills.
+    fn slots(syscall: &Syscall) -> usize {
+        syscall
+            .args
+            .iter()
+            .map(|arg| match arg {
+                Argument::String(_) => 2,
+                _ => 1,
+            })
+            .sum()
+    }
+
+    /// Whether the arguments fit the six argument registers, reporting an error if not.
+    fn check_slots(syscall: &Syscall, diagnostics: &mut Diagnostics) -> bool {
+        let slots = Self::slots(syscall);
+
+        if slots > Register::SYSCALL.len() {
+            diagnostics
+                .error(
+                    syscall.span,
+                    format!("syscalls take at most 6 arguments, found {slots}"),
+                )
+                .note("a string argument is passed as its address and length, and fills two registers");
+
+            return false;
+        }
+
+        true
+    }
+
+    /// Lowers a syscall following the Linux x86-64 ABI, the number goes in rax and the
+    /// arguments in rdi, rsi, rdx, r10, r8 and r9. The return value is left in rax.
+    fn lower_syscall64(&mut self, syscall: &Syscall, diagnostics: &mut Diagnostics) {
+        if !Self::check_slots(syscall, diagnostics) {
+            return;
+        }
+
+        self.load_arguments64(&syscall.args, &Register::SYSCALL, false, diagnostics);
+        self.mov_int(Register::rax, syscall.id);
+        self.asm.push(Op::syscall);
+    }
+
+    /// Lowers a nested syscall, call or operator, leaving its result in rax. Returns whether
+    /// `arg` is one.
+    fn lower_nested(&mut self, arg: &Argument, diagnostics: &mut Diagnostics) -> bool {
+        match arg {
+            Argument::Syscall(nested) => self.lower_syscall(nested, diagnostics),
+            Argument::Call(nested) => self.lower_call(nested, diagnostics),
+            Argument::Unary(unary) => self.lower_unary(unary, diagnostics),
+            Argument::Binary(binary) => self.lower_binary(binary, diagnostics),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Evaluates an expression other than a string into rax.
+    fn lower_expression(&mut self, arg: &Argument, diagnostics: &mut Diagnostics) {
+        if !self.lower_nested(arg, diagnostics) {
+            self.load(Register::rax, arg, diagnostics);
+        }
+    }
+
+    /// Whether an expression is computed as a signed integer. Operators are signed if either
+    /// operand is, negation always is, and comparisons and logical operators give 0 or 1.
+    /// Syscalls are signed, since they fail with a negated `errno`.
+    fn is_signed(&self, arg: &Argument) -> bool {
+        match arg {
+            Argument::Name(name, _) => self
+                .find_local(name)
+                .is_some_and(|(_, kind, _)| kind.is_signed()),
+            Argument::Unary(unary) => unary.op == UnaryOp::Neg,
+            Argument::Syscall(_) => true,
+            Argument::Call(call) => self
+                .signatures
+                .iter()
+                .find(|(ident, ..)| *ident == call.ident)
+                .map(|(_, _, ret, _)| ret)
+                .or_else(|| {
+                    self.imports
+                        .iter()
+                        .find(|(ident, ..)| *ident == call.ident)
+                        .map(|(.., ret)| ret)
+                })
+                .and_then(Option::as_ref)
+                .is_some_and(Type::is_signed),
+            Argument::Binary(binary) if binary.op.is_boolean() => false,
+            Argument::Binary(binary) => {
+                self.is_signed(&binary.left) || self.is_signed(&binary.right)
+            }
+            _ => false,
+        }
+    }
+
+    /// Pushes a word sized instruction on registers.
+    fn push_registers(&mut self, mnemonic: Mnemonic, registers: &[Register]) {
+        let operands: Vec<Operand> = registers.iter().copied().map(Operand::Register).collect();
+
+        self.asm
+            .push(Instruction::new(mnemonic, self.word_size(), &operands));
+    }
+
+    /// Pushes `mnemonic rax, rax` of the given size, such as a zero or sign extension.
+    fn push_registers_sized(&mut self, mnemonic: Mnemonic, size: Size) {
+        self.asm.push(Instruction::new(
+            mnemonic,
+            size,
+            &[
+                Operand::Register(Register::rax),
+                Operand::Register(Register::rax),
+            ],
+        ));
+    }
+
+    /// Sets rax to 1 if `condition` holds and to 0 otherwise.
+    fn set_condition(&mut self, condition: Condition) {
+        self.asm.push(Instruction::new(
+            Mnemonic::set(condition),
+            Size::Byte,
+            &[Operand::Register(Register::rax)],
+        ));
+        self.asm.push(Instruction::new(
+            Mnemonic::movzx(Size::Byte),
+            Size::Dword,
+            &[
+                Operand::Register(Register::rax),
+                Operand::Register(Register::rax),
+            ],
+        ));
+    }
+
+    /// Reports a string operand, which has no single value. Returns whether there is one.
+    fn check_strings(args: &[&Argument], span: Span, diagnostics: &mut Diagnostics) -> bool {
+        if !args.iter().any(|arg| matches!(arg, Argument::String(_))) {
+            return false;
+        }
+
+        diagnostics
+            .error(span, "strings can't be used in expressions")
+            .note(
+                "a string argument is passed as its address and length, a `static` has an address",
+            );
+
+        true
+    }
+
+    /// Evaluates a unary operator into rax.
+    fn lower_unary(&mut self, unary: &Unary, diagnostics: &mut Diagnostics) {
+        if Self::check_strings(&[&unary.value], unary.span, diagnostics) {
+            return;
+        }
+
+        self.lower_expression(&unary.value, diagnostics);
+
+        match unary.op {
+            UnaryOp::Neg => self.push_registers(Mnemonic::neg, &[Register::rax]),
+            UnaryOp::Not => {
+                self.push_registers(Mnemonic::test, &[Register::rax, Register::rax]);
+                self.set_condition(Condition::e);
+            }
+        }
+    }
+
+    /// Evaluates a binary operator into rax. `&&` and `||` only evaluate their right operand
+    /// when the left one doesn't decide the result.
+    fn lower_binary(&mut self, binary: &Binary, diagnostics: &mut Diagnostics) {
+        use Register::{rax, rcx, rdx};
+
+        if Self::check_strings(&[&binary.left, &binary.right], binary.span, diagnostics) {
+            return;
+        }
+
+        if let (BinaryOp::Div | BinaryOp::Rem, Argument::U64(0)) = (binary.op, &binary.right) {
+            diagnostics.error(binary.span, "attempt to divide by zero");
+
+            return;
+        }
+
+        if let BinaryOp::And | BinaryOp::Or = binary.op {
+            let end = self.asm.label();
+            let condition = match binary.op {
+                BinaryOp::And => Condition::e,
+                _ => Condition::ne,
+            };
+
+            // the left operand is in rax when it decides the result
+            self.lower_expression(&binary.left, diagnostics);
+            self.push_registers(Mnemonic::test, &[rax, rax]);
+            self.asm.branch(Mnemonic::j(condition), end);
+            self.lower_expression(&binary.right, diagnostics);
+            self.asm.bind(end);
+            self.push_registers(Mnemonic::test, &[rax, rax]);
+            self.set_condition(Condition::ne);
+
+            return;
+        }
+
+        self.lower_operands(binary, diagnostics);
+
+        let signed = self.is_signed(&binary.left) || self.is_signed(&binary.right);
+
+        match binary.op {
+            BinaryOp::Add => self.push_registers(Mnemonic::add, &[rax, rcx]),
+            BinaryOp::Sub => self.push_registers(Mnemonic::sub, &[rax, rcx]),
+            // the low half of the product is the same signed or not
+            BinaryOp::Mul => self.push_registers(Mnemonic::imul, &[rax, rcx]),
+            BinaryOp::Div | BinaryOp::Rem => {
+                if signed {
+                    self.push_registers(Mnemonic::cqo, &[]);
+                    self.push_registers(Mnemonic::idiv, &[rcx]);
+                } else {
+                    self.asm.push(Op::xor32(rdx, rdx));
+                    self.push_registers(Mnemonic::div, &[rcx]);
+                }
+
+                if binary.op == BinaryOp::Rem {
+                    self.push_registers(Mnemonic::mov, &[rax, rdx]);
+                }
+            }
+            BinaryOp::BitAnd => self.push_registers(Mnemonic::and, &[rax, rcx]),
+            BinaryOp::BitXor => self.push_registers(Mnemonic::xor, &[rax, rcx]),
+            BinaryOp::BitOr => self.push_registers(Mnemonic::or, &[rax, rcx]),
+            BinaryOp::Shl => self.shift(Mnemonic::shl),
+            BinaryOp::Shr if signed => self.shift(Mnemonic::sar),
+            BinaryOp::Shr => self.shift(Mnemonic::shr),
+            comparison => {
+                self.push_registers(Mnemonic::cmp, &[rax, rcx]);
+                self.set_condition(Self::condition(comparison, signed));
+            }
+        }
+    }
+
+    /// Evaluates the left operand of a binary operator into rax and the right one into rcx.
+    fn lower_operands(&mut self, binary: &Binary, diagnostics: &mut Diagnostics) {
+        use Register::{rax, rcx};
+
+        self.lower_expression(&binary.left, diagnostics);
+
+        // an integer or a local can be loaded without clobbering rax
+        match &binary.right {
+            right @ (Argument::U64(_) | Argument::Name(..)) => self.load(rcx, right, diagnostics),
+            right => {
+                self.push_word(rax);
+                self.lower_expression(right, diagnostics);
+                self.push_registers(Mnemonic::mov, &[rcx, rax]);
+                self.pop_word(rax);
+            }
+        }
+    }
+
+    /// Flags condition under which a comparison holds, after `cmp`.
+    fn condition(comparison: BinaryOp, signed: bool) -> Condition {
+        match (comparison, signed) {
+            (BinaryOp::Eq, _) => Condition::e,
+            (BinaryOp::Ne, _) => Condition::ne,
+            (BinaryOp::Lt, false) => Condition::b,
+            (BinaryOp::Le, false) => Condition::be,
+            (BinaryOp::Gt, false) => Condition::a,
+            (BinaryOp::Ge, false) => Condition::ae,
+            (BinaryOp::Lt, true) => Condition::l,
+            (BinaryOp::Le, true) => Condition::le,
+            (BinaryOp::Gt, true) => Condition::g,
+            (BinaryOp::Ge, true) => Condition::ge,
+            _ => unreachable!("not a comparison"),
+        }
+    }
+
+    /// Jumps to `label` when `condition` is 0. A comparison jumps on its flags, without
+    /// materializing 0 or 1.
+    fn lower_branch(
+        &mut self,
+        condition: &Argument,
+        span: Span,
+        label: Label,
+        diagnostics: &mut Diagnostics,
+    ) {
+        use Register::{rax, rcx};
+
+        if Self::check_strings(&[condition], span, diagnostics) {
+            return;
+        }
+
+        match condition {
+            Argument::Binary(binary) if binary.op.is_comparison() => {
+                if Self::check_strings(&[&binary.left, &binary.right], binary.span, diagnostics) {
+                    return;
+                }
+
+                let signed = self.is_signed(&binary.left) || self.is_signed(&binary.right);
+
+                self.lower_operands(binary, diagnostics);
+                self.push_registers(Mnemonic::cmp, &[rax, rcx]);
+                self.asm.branch(
+                    Mnemonic::j(Self::condition(binary.op, signed).negate()),
+                    label,
+                );
+            }
+            condition => {
+                self.lower_expression(condition, diagnostics);
+                self.push_registers(Mnemonic::test, &[rax, rax]);
+                self.asm.branch(Mnemonic::j(Condition::e), label);
+            }
+        }
+    }
+
+    /// Shifts rax by cl, the count is masked to the register width.
+    fn shift(&mut self, mnemonic: Mnemonic) {
+        self.asm.push(Instruction::new(
+            mnemonic,
+            self.word_size(),
+            &[
+                Operand::Register(Register::rax),
+                Operand::Register(Register::rcx),
+            ],
+        ));
+    }
+
+    /// Pushes a whole register, counting it towards the stack alignment of calls.
+    fn push_word(&mut self, register: Register) {
+        match self.mode {
+            Mode::Long => self.asm.push(Op::push64(register)),
+            Mode::Protected => self.asm.push(Op::push32(register)),
+        }
+
+        self.depth += 1;
+    }
+
+    fn pop_word(&mut self, register: Register) {
+        match self.mode {
+            Mode::Long => self.asm.push(Op::pop64(register)),
+            Mode::Protected => self.asm.push(Op::pop32(register)),
+        }
+
+        self.depth -= 1;
+    }
+
+    /// Loads 64-bit arguments into `registers`, evaluating nested syscalls and calls first
+    /// since they clobber the registers. A string is passed as its address followed by its
+    /// length, or as the address of a NUL terminated copy when `terminated`.
+    fn load_arguments64(
+        &mut self,
+        args: &[Argument],
+        registers: &[Register],
+        terminated: bool,
+        diagnostics: &mut Diagnostics,
+    ) {
+        for arg in args {
+            if self.lower_nested(arg, diagnostics) {
+                self.asm.push(Op::push64(Register::rax));
+                self.depth += 1;
+            }
+        }
+
+        let mut registers = registers.iter().copied();
+        let mut nested = vec![];
+
+        for arg in args {
+            match arg {
+                Argument::U64(_) | Argument::Name(..) => {
+                    let register = registers.next().unwrap();
+
+                    self.load(register, arg, diagnostics);
+                }
+                Argument::String(string) if terminated => {
+                    let register = registers.next().unwrap();
+
+                    self.lea_string(register, format!("{}\0", unescape(string)));
+                }
+                Argument::String(string) => {
+                    let address = registers.next().unwrap();
+                    let len = registers.next().unwrap();
+                    let string = unescape(string);
+
+                    self.mov_int(len, string.len() as u64);
+                    self.lea_string(address, string);
+                }
+                Argument::Syscall(_)
+                | Argument::Call(_)
+                | Argument::Unary(_)
+                | Argument::Binary(_) => nested.push(registers.next().unwrap()),
+            }
+        }
+
+        for register in nested.into_iter().rev() {
+            self.asm.push(Op::pop64(register));
+            self.depth -= 1;
+        }
+    }
+
+    /// Lowers a call to a function following the SysV ABI. On x86-64 the arguments go in
+    /// rdi, rsi, rdx, rcx, r8 and r9 and the stack is 16-byte aligned at the call, on i386
+    /// they're pushed from last to first. A string argument is passed as the address of a
+    /// NUL terminated copy. The return value is left in rax.
+    pub fn lower_call(&mut self, call: &Call, diagnostics: &mut Diagnostics) {
+        let function = self
+            .signatures
+            .iter()
+            .find(|(ident, ..)| *ident == call.ident)
+            .map(|(_, params, _, start)| (params.clone(), *start));
+        let import = self
+            .imports
+            .iter()
+            .find(|(ident, ..)| *ident == call.ident)
+            .map(|(_, target, params, variadic, ret)| (*target, *params, *variadic, ret.clone()));
+
+        let (params, variadic) = match (&function, &import) {
+            (Some((params, _)), _) => (params.len(), false),
+            (None, Some((_, params, variadic, _))) => (*params, *variadic),
+            (None, None) => {
+                diagnostics
+                    .error(call.span, format!("cannot find function `{}`", call.ident))
+                    .note(format!(
+                        "define it with `fn {}(...)`, or import it with `extern \"C\" fn {}(...);`",
+                        call.ident, call.ident
+                    ));
+
+                return;
+            }
+        };
+
+        // imports are already reported at their declaration
+        if function.is_none() && self.mode == Mode::Protected {
+            return;
+        }
+
+        let found = call.args.len();
+
+        if found < params || (found > params && !variadic) {
+            let at_least = if variadic { "at least " } else { "" };
+            let plural = if params == 1 { "" } else { "s" };
+
+            diagnostics.error(
+                call.span,
+                format!(
+                    "`{}` takes {at_least}{params} argument{plural}, found {found}",
+                    call.ident
+                ),
+            );
+
+            return;
+        }
+
+        if let Some((params, _)) = &function {
+            for (arg, kind) in call.args.iter().zip(params) {
+                if let Argument::U64(int) = arg {
+                    if let Err(message) = check_integer(kind, *int) {
+                        diagnostics.error(call.span, message);
+
+                        return;
+                    }
+                }
+            }
+        }
+
+        if let (Some((_, start)), Mode::Protected) = (&function, self.mode) {
+            return self.lower_call32(call, *start, diagnostics);
+        }
+
+        if found > Register::CALL.len() {
+            diagnostics
+                .error(
+                    call.span,
+                    format!("calls take at most 6 arguments, found {found}"),
+                )
+                .note("arguments past the sixth would go on the stack, which isn't supported");
+
+            return;
+        }
+
+        self.load_arguments64(&call.args, &Register::CALL, true, diagnostics);
+
+        // variadic functions take the number of vector registers used in al
+        if variadic {
+            self.asm.push(Op::xor32(Register::rax, Register::rax));
+        }
+
+        // the stack is aligned when the function starts, pushed values may misalign it
+        let padded = self.depth % 2 == 1;
+
+        if padded {
+            self.asm.push(Op::sub64_int(Register::rsp, 8));
+        }
+
+        // functions defined here extend their result before returning, C functions don't
+        let ret = match (function, import) {
+            (Some((_, start)), _) => {
+                self.asm.branch(Mnemonic::call, start);
+
+                None
+            }
+            (None, Some((target, _, _, ret))) => {
+                self.asm.reference(
+                    Op::call(0).instruction(),
+                    Reference {
+                        kind: Kind::Plt32,
+                        target,
+                        offset: 0,
+                    },
+                );
+
+                ret
+            }
+            (None, None) => unreachable!("reported above"),
+        };
+
+        if padded {
+            self.asm.push(Op::add64_int(Register::rsp, 8));
+        }
+
+        if let Some(kind) = ret {
+            self.extend(&kind);
+        }
+    }
+
+    /// Pushes the arguments from last to first following the i386 SysV ABI, calls the
+    /// function at `start`, and pops them.
+    fn lower_call32(&mut self, call: &Call, start: Label, diagnostics: &mut Diagnostics) {
+        for arg in call.args.iter().rev() {
+            match arg {
+                Argument::String(string) => {
+                    self.mov_string32(Register::rax, format!("{}\0", unescape(string)))
+                }
+                arg => self.lower_expression(arg, diagnostics),
+            }
+
+            self.push_word(Register::rax);
+        }
+
+        self.asm.branch(Mnemonic::call, start);
+
+        if !call.args.is_empty() {
+            self.asm
+                .push(Op::add32_int(Register::rsp, 4 * call.args.len() as i32));
+            self.depth -= call.args.len();
+        }
+    }
+
+    /// Lowers a syscall following the Linux i386 ABI, the number goes in eax and the
+    /// arguments in ebx, ecx, edx, esi, edi and ebp, then `int 0x80` enters the kernel. The
+    /// return value is left in eax.
+    fn lower_syscall32(&mut self, syscall: &Syscall, diagnostics: &mut Diagnostics) {
+        if !Self::check_slots(syscall, diagnostics) {
+            return;
+        }
+
+        // literal arguments, nested ones included, are checked as they're loaded
+        if !Self::check_int32(syscall.id, syscall.span, diagnostics) {
+            return;
+        }
+
+        // the sixth argument goes in ebp, which locals are addressed from
+        let frame = Self::slots(syscall) == Register::SYSCALL32.len();
+
+        if frame {
+            self.asm.push(Op::push32(Register::rbp));
+        }
+
+        // nested syscalls clobber the argument registers, so evaluate them first
+        for arg in &syscall.args {
+            if self.lower_nested(arg, diagnostics) {
+                self.asm.push(Op::push32(Register::rax));
+            }
+        }
+
+        let mut registers = Register::SYSCALL32.iter().copied();
+        let mut nested = vec![];
+
+        for arg in &syscall.args {
+            match arg {
+                Argument::U64(_) | Argument::Name(..) => {
+                    let register = registers.next().unwrap();
+
+                    self.load(register, arg, diagnostics);
+                }
+                Argument::String(string) => {
+                    let address = registers.next().unwrap();
+                    let len = registers.next().unwrap();
+                    let string = unescape(string);
+
+                    self.mov_int32(len, string.len() as u32);
+                    self.mov_string32(address, string);
+                }
+                Argument::Syscall(_)
+                | Argument::Call(_)
+                | Argument::Unary(_)
+                | Argument::Binary(_) => nested.push(registers.next().unwrap()),
+            }
+        }
+
+        for register in nested.into_iter().rev() {
+            self.asm.push(Op::pop32(register));
+        }
+
+        self.mov_int32(Register::rax, syscall.id as u32);
+        self.asm.push(Op::int(0x80));
+
+        if frame {
+            self.asm.push(Op::pop32(Register::rbp));
+        }
+    }
+
+    /// Resolves every reference within the code, references to `.rodata` are left as
+    /// relocations.
+    pub fn assemble(&mut self, diagnostics: &mut Diagnostics) -> Assembled {
+        self.asm.assemble(diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::Relocation;
+    use super::super::decoder::Decoder;
+    use super::super::parser::Parser;
+    use super::*;
+
+    /// Lowers and assembles `input`, returning the diagnostics, the code and what it assembles
+    /// to.
+    fn lower(input: &str, mode: Mode) -> (Diagnostics, Code, Assembled) {
+        let mut parser = Parser::new(input);
+        let source = parser.parse();
+        let mut diagnostics = parser.take_diagnostics();
+        let mut code = Code::new();
+
+        code.mode(mode).lower(&source, &mut diagnostics);
+
+        let assembled = code.assemble(&mut diagnostics);
+
+        (diagnostics, code, assembled)
+    }
+
+    fn decode(assembled: &Assembled, mode: Mode) -> Vec<Instruction> {
+        Decoder::with_mode(&assembled.bytes, mode)
+            .map(|(_, result)| result.unwrap().0)
+            .collect()
+    }
+
+    /// Messages of the errors lowering `input` reports.
+    fn errors(input: &str, mode: Mode) -> Vec<String> {
+        let (diagnostics, ..) = lower(input, mode);
+
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.clone())
+            .collect()
+    }
+
+    /// Mnemonics of the code `input` lowers to, which has to be free of errors.
+    fn mnemonics(input: &str, mode: Mode) -> Vec<Mnemonic> {
+        let (diagnostics, _, assembled) = lower(input, mode);
+
+        assert!(
+            !diagnostics.has_errors(),
+            "{}",
+            diagnostics.render("test.em", input)
+        );
+
+        decode(&assembled, mode)
+            .into_iter()
+            .map(|instruction| instruction.mnemonic)
+            .collect()
+    }
+
+    /// Conditions of the branches and `setcc`s among `mnemonics`.
+    fn conditions(mnemonics: &[Mnemonic]) -> Vec<Condition> {
+        mnemonics
+            .iter()
+            .filter_map(|mnemonic| match mnemonic {
+                Mnemonic::j(condition) | Mnemonic::set(condition) => Some(*condition),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unescape_expands_every_escape() {
+        assert_eq!(unescape(r#""a\n\t\0\\\'\"b""#), "a\n\t\0\\'\"b");
+        assert_eq!(unescape(r#"'\\n'"#), "\\n");
+        assert_eq!(unescape(r#""\q""#), "\\q");
+    }
+
+    #[test]
+    fn syscall_results_are_signed() {
+        let mnemonics = mnemonics(
+            "fn entry() {\n    \
+                 if sys::syscall(39) < 0 {\n        \
+                     sys::syscall(60, 1)\n    \
+                 }\n\
+             }\n",
+            Mode::Long,
+        );
+        let conditions = conditions(&mnemonics);
+
+        assert!(!conditions.is_empty());
+        assert!(conditions
+            .iter()
+            .all(|condition| matches!(condition, Condition::l | Condition::ge)));
+    }
+
+    #[test]
+    fn i386_rejects_wide_literals() {
+        let wide = "`4294967296` doesn't fit in 32 bits";
+
+        for input in [
+            "fn entry() {\n    sys::syscall(60, 4294967296)\n}\n",
+            "fn entry() {\n    sys::syscall(60, 1 + 4294967296)\n}\n",
+            "fn entry() {\n    sys::syscall(60, sys::syscall(39, 4294967296))\n}\n",
+            "fn entry() {\n    if 4294967296 > 1 {\n        sys::syscall(60, 1)\n    }\n}\n",
+        ] {
+            assert_eq!(errors(input, Mode::Protected), [wide], "{}", input);
+            assert!(errors(input, Mode::Long).is_empty(), "{}", input);
+        }
+
+        let input = "fn entry() {\n    sys::syscall(60, 4294967295)\n}\n";
+
+        assert!(errors(input, Mode::Protected).is_empty());
+    }
+
+    #[test]
+    fn signedness_selects_instructions() {
+        use Mnemonic::{div, idiv, sar, shr};
+
+        for (mode, signed, unsigned) in
+            [(Mode::Long, "i64", "u64"), (Mode::Protected, "i32", "u32")]
+        {
+            for (kind, emitted, avoided, jump, set) in [
+                (signed, [idiv, sar], [div, shr], Condition::ge, Condition::l),
+                (
+                    unsigned,
+                    [div, shr],
+                    [idiv, sar],
+                    Condition::ae,
+                    Condition::b,
+                ),
+            ] {
+                let input = format!(
+                    "fn f(a: {kind}, b: {kind}) -> {kind} {{\n    \
+                         let c = a < b\n    \
+                         if a < b {{\n        \
+                             return a / b\n    \
+                         }}\n    \
+                         return a >> b\n\
+                     }}\n\
+                     fn entry() {{\n}}\n"
+                );
+                let mnemonics = mnemonics(&input, mode);
+
+                for mnemonic in &emitted {
+                    assert!(mnemonics.contains(mnemonic), "{:?} for {}", mnemonic, kind);
+                }
+
+                for mnemonic in &avoided {
+                    assert!(!mnemonics.contains(mnemonic), "{:?} for {}", mnemonic, kind);
+                }
+
+                // `let` sets c when `a < b` holds, `if` skips its body when it doesn't
+                assert_eq!(conditions(&mnemonics), [set, jump], "{}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn missing_entry_is_reported() {
+        assert_eq!(
+            errors("fn main() {\n}\n", Mode::Long),
+            ["`entry` function not found"]
+        );
+    }
+
+    #[test]
+    fn entry_result_is_exit_status() {
+        for (mode, status, number, exit) in [
+            (Mode::Long, Register::rdi, 60, Op::syscall),
+            (Mode::Protected, Register::rbx, 1, Op::int(0x80)),
+        ] {
+            for (ret, set_status) in [
+                ("-> u8 {\n    return 3\n", Op::mov32(Register::rax, status)),
+                ("{\n", Op::xor32(status, status)),
+            ] {
+                let input = format!("fn entry() {ret}}}\n");
+                let (diagnostics, code, assembled) = lower(&input, mode);
+                let instructions = decode(&assembled, mode);
+                let start = assembled.offset(code.start.unwrap());
+                let stub: Vec<&Instruction> = assembled
+                    .lines
+                    .iter()
+                    .zip(&instructions)
+                    .take_while(|(line, _)| line.offset < start)
+                    .map(|(_, instruction)| instruction)
+                    .collect();
+
+                assert!(!diagnostics.has_errors());
+                assert_eq!(stub[2].mnemonic, Mnemonic::call);
+                assert_eq!(
+                    stub[3..],
+                    [
+                        &set_status.instruction(),
+                        &Op::mov32_int(Register::rax, number).instruction(),
+                        &exit.instruction(),
+                    ]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn entry_result_is_passed_to_libc_exit() {
+        let input = "extern \"C\" fn puts(s: u64) -> i32;\n\
+                     fn entry() -> u8 {\n    return 3\n}\n";
+        let (diagnostics, code, assembled) = lower(input, Mode::Long);
+        let instructions = decode(&assembled, Mode::Long);
+        let start = assembled.offset(code.start.unwrap());
+        let (_, target, ..) = code
+            .imports
+            .iter()
+            .find(|(ident, ..)| ident == "exit")
+            .unwrap();
+
+        assert!(!diagnostics.has_errors());
+        assert_eq!(
+            instructions[3],
+            Op::mov32(Register::rax, Register::rdi).instruction()
+        );
+        assert_eq!(instructions[4].mnemonic, Mnemonic::call);
+        assert!(!instructions
+            .iter()
+            .any(|instruction| instruction.mnemonic == Mnemonic::syscall));
+
+        // the call to `exit` ends the stub, with its displacement left to the linker
+        assert_eq!(
+            assembled.relocations,
+            [Relocation {
+                offset: start - 4,
+                kind: Kind::Plt32,
+                target: *target,
+                addend: -4,
+            }]
+        );
+    }
+}
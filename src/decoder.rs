@@ -0,0 +1,782 @@
+use super::encoder::{Condition, Instruction, Memory, Mnemonic, Mode, Operand, Size};
+use super::op::Register;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// the input ends in the middle of an instruction
+    Truncated,
+    /// an opcode outside of what the encoder emits
+    Unsupported(u8),
+    /// ah, ch, dh or bh, which the encoder never emits
+    HighByte,
+}
+
+impl Error {
+    pub fn message(&self) -> String {
+        match self {
+            Error::Truncated => "truncated instruction".to_string(),
+            Error::Unsupported(byte) => format!("unsupported opcode {byte:#04x}"),
+            Error::HighByte => "unsupported high byte register".to_string(),
+        }
+    }
+}
+
+const REGISTERS: [Register; 16] = {
+    use Register::*;
+
+    [
+        rax, rcx, rdx, rbx, rsp, rbp, rsi, rdi, r8, r9, r10, r11, r12, r13, r14, r15,
+    ]
+};
+
+/// REX prefix bits.
+#[derive(Clone, Copy, Debug, Default)]
+struct Rex {
+    present: bool,
+    w: bool,
+    r: bool,
+    x: bool,
+    b: bool,
+}
+
+/// ModRM byte with its SIB and displacement decoded.
+#[derive(Clone, Copy, Debug)]
+struct ModRm {
+    /// reg field, including REX.R
+    reg: u8,
+    rm: Operand,
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    mode: Mode,
+    rex: Rex,
+    /// 0x66 operand size prefix
+    word: bool,
+}
+
+impl<'a> Cursor<'a> {
+    fn byte(&mut self) -> Result<u8, Error> {
+        let byte = *self.bytes.get(self.offset).ok_or(Error::Truncated)?;
+
+        self.offset += 1;
+
+        Ok(byte)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + N)
+            .ok_or(Error::Truncated)?;
+
+        self.offset += N;
+
+        let mut array = [0; N];
+
+        array.copy_from_slice(bytes);
+
+        Ok(array)
+    }
+
+    fn i8(&mut self) -> Result<i64, Error> {
+        Ok(self.byte()? as i8 as i64)
+    }
+
+    fn i16(&mut self) -> Result<i64, Error> {
+        Ok(i16::from_le_bytes(self.array()?) as i64)
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, Error> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    /// Operand size of a full sized opcode.
+    fn size(&self) -> Size {
+        if self.rex.w {
+            Size::Qword
+        } else if self.word {
+            Size::Word
+        } else {
+            Size::Dword
+        }
+    }
+
+    /// Operand size of an opcode pair such as 0x88 and 0x89.
+    fn sized(&self, opcode: u8) -> Size {
+        if opcode & 1 == 0 {
+            Size::Byte
+        } else {
+            self.size()
+        }
+    }
+
+    /// Immediate for the operand size, 64-bit operands take a sign extended imm32.
+    fn immediate(&mut self, size: Size) -> Result<i64, Error> {
+        match size {
+            Size::Byte => self.i8(),
+            Size::Word => self.i16(),
+            Size::Dword | Size::Qword => Ok(self.i32()? as i64),
+        }
+    }
+
+    /// Register encoded in the low 3 bits of an opcode or r/m field, without a REX prefix
+    /// byte registers 4 to 7 are ah, ch, dh and bh which can't be represented.
+    fn register(&self, low: u8, extended: bool, byte: bool) -> Result<Register, Error> {
+        if byte && !self.rex.present && (4..8).contains(&low) {
+            return Err(Error::HighByte);
+        }
+
+        Ok(REGISTERS[(low | ((extended as u8) << 3)) as usize])
+    }
+
+    /// Register in the ModRM reg field.
+    fn reg(&self, modrm: &ModRm, byte: bool) -> Result<Register, Error> {
+        self.register(modrm.reg & 0b111, modrm.reg >= 8, byte)
+    }
+
+    /// ModRM, with `byte` saying whether a register r/m operand is byte sized.
+    fn modrm(&mut self, byte: bool) -> Result<ModRm, Error> {
+        let modrm = self.byte()?;
+        let mode = modrm >> 6;
+        let reg = ((modrm >> 3) & 0b111) | ((self.rex.r as u8) << 3);
+        let rm = modrm & 0b111;
+
+        if mode == 0b11 {
+            let register = self.register(rm, self.rex.b, byte)?;
+
+            return Ok(ModRm {
+                reg,
+                rm: Operand::Register(register),
+            });
+        }
+
+        // rip-relative in 64-bit mode, an absolute address in 32-bit mode
+        if mode == 0b00 && rm == 0b101 {
+            let memory = match self.mode {
+                Mode::Long => Memory::rip(self.i32()?),
+                Mode::Protected => Memory::absolute(self.i32()?),
+            };
+
+            return Ok(ModRm {
+                reg,
+                rm: Operand::Memory(memory),
+            });
+        }
+
+        let mut memory = if rm == 0b100 {
+            let sib = self.byte()?;
+            let scale = 1 << (sib >> 6);
+            let index = ((sib >> 3) & 0b111) | ((self.rex.x as u8) << 3);
+            let base = sib & 0b111;
+
+            let mut memory = if mode == 0b00 && base == 0b101 {
+                Memory::absolute(self.i32()?)
+            } else {
+                Memory::base(REGISTERS[(base | ((self.rex.b as u8) << 3)) as usize])
+            };
+
+            // an index of 100 without REX.X means no index
+            if index != 0b100 {
+                memory = memory.index(REGISTERS[index as usize], scale);
+            }
+
+            memory
+        } else {
+            Memory::base(REGISTERS[(rm | ((self.rex.b as u8) << 3)) as usize])
+        };
+
+        match mode {
+            0b01 => memory = memory.displacement(self.i8()? as i32),
+            0b10 => memory = memory.displacement(self.i32()?),
+            _ => {}
+        }
+
+        Ok(ModRm {
+            reg,
+            rm: Operand::Memory(memory),
+        })
+    }
+}
+
+/// Decodes the 64-bit instruction at the start of `bytes`, returning it with its length.
+pub fn decode(bytes: &[u8]) -> Result<(Instruction, usize), Error> {
+    decode_mode(bytes, Mode::Long)
+}
+
+/// Decodes the instruction at the start of `bytes` for the given mode, returning it with its
+/// length. In 32-bit mode 0x40 to 0x4F are `inc` and `dec` rather than REX prefixes, which the
+/// encoder never emits.
+pub fn decode_mode(bytes: &[u8], mode: Mode) -> Result<(Instruction, usize), Error> {
+    use Mnemonic::*;
+    use Operand::{Immediate, Register as Reg, Relative};
+
+    let mut cursor = Cursor {
+        bytes,
+        offset: 0,
+        mode,
+        rex: Rex::default(),
+        word: false,
+    };
+
+    let mut opcode = cursor.byte()?;
+
+    if opcode == 0x66 {
+        cursor.word = true;
+        opcode = cursor.byte()?;
+    }
+
+    if opcode & 0xF0 == 0x40 && mode == Mode::Long {
+        cursor.rex = Rex {
+            present: true,
+            w: opcode & 0b1000 != 0,
+            r: opcode & 0b0100 != 0,
+            x: opcode & 0b0010 != 0,
+            b: opcode & 0b0001 != 0,
+        };
+
+        opcode = cursor.byte()?;
+    }
+
+    let stack = mode.stack();
+    let c = &mut cursor;
+    let arithmetic = [
+        Some(add),
+        Some(or),
+        None,
+        None,
+        Some(and),
+        Some(sub),
+        Some(xor),
+        Some(cmp),
+    ];
+    let group3 = [
+        Some(test),
+        None,
+        Some(not),
+        Some(neg),
+        Some(mul),
+        Some(imul),
+        Some(div),
+        Some(idiv),
+    ];
+    let shifts = [
+        None,
+        None,
+        None,
+        None,
+        Some(shl),
+        Some(shr),
+        None,
+        Some(sar),
+    ];
+
+    let instruction = match opcode {
+        0x00..=0x3F if opcode & 0b111 <= 0b101 => {
+            let mnemonic = arithmetic[(opcode >> 3) as usize].ok_or(Error::Unsupported(opcode))?;
+            let size = c.sized(opcode);
+            let byte = size == Size::Byte;
+
+            match opcode & 0b111 {
+                0 | 1 => {
+                    let modrm = c.modrm(byte)?;
+
+                    Instruction::new(mnemonic, size, &[modrm.rm, Reg(c.reg(&modrm, byte)?)])
+                }
+                2 | 3 => {
+                    let modrm = c.modrm(byte)?;
+
+                    Instruction::new(mnemonic, size, &[Reg(c.reg(&modrm, byte)?), modrm.rm])
+                }
+                _ => {
+                    let immediate = c.immediate(size)?;
+
+                    Instruction::new(mnemonic, size, &[Reg(Register::rax), Immediate(immediate)])
+                }
+            }
+        }
+        0x50..=0x57 => {
+            let register = c.register(opcode & 0b111, c.rex.b, false)?;
+
+            Instruction::new(push, stack, &[Reg(register)])
+        }
+        0x58..=0x5F => {
+            let register = c.register(opcode & 0b111, c.rex.b, false)?;
+
+            Instruction::new(pop, stack, &[Reg(register)])
+        }
+        0x63 if c.rex.w => {
+            let modrm = c.modrm(false)?;
+
+            Instruction::new(
+                movsx(Size::Dword),
+                Size::Qword,
+                &[Reg(c.reg(&modrm, false)?), modrm.rm],
+            )
+        }
+        0x68 => Instruction::new(push, stack, &[Immediate(c.i32()? as i64)]),
+        0x6A => Instruction::new(push, stack, &[Immediate(c.i8()?)]),
+        0x69 | 0x6B => {
+            let size = c.size();
+            let modrm = c.modrm(false)?;
+            let immediate = if opcode == 0x6B {
+                c.i8()?
+            } else {
+                c.immediate(size)?
+            };
+
+            Instruction::new(
+                imul,
+                size,
+                &[Reg(c.reg(&modrm, false)?), modrm.rm, Immediate(immediate)],
+            )
+        }
+        0x70..=0x7F => Instruction::new(
+            j(Condition::from_code(opcode)),
+            Size::Byte,
+            &[Relative(c.i8()? as i32)],
+        ),
+        0x80 | 0x81 | 0x83 => {
+            let size = c.sized(opcode);
+            let byte = size == Size::Byte;
+            let modrm = c.modrm(byte)?;
+            let mnemonic =
+                arithmetic[(modrm.reg & 0b111) as usize].ok_or(Error::Unsupported(opcode))?;
+            let immediate = if opcode == 0x83 {
+                c.i8()?
+            } else {
+                c.immediate(size)?
+            };
+
+            Instruction::new(mnemonic, size, &[modrm.rm, Immediate(immediate)])
+        }
+        0x84 | 0x85 | 0x88 | 0x89 => {
+            let mnemonic = if opcode < 0x88 { test } else { mov };
+            let size = c.sized(opcode);
+            let byte = size == Size::Byte;
+            let modrm = c.modrm(byte)?;
+
+            Instruction::new(mnemonic, size, &[modrm.rm, Reg(c.reg(&modrm, byte)?)])
+        }
+        0x8A | 0x8B => {
+            let size = c.sized(opcode);
+            let byte = size == Size::Byte;
+            let modrm = c.modrm(byte)?;
+
+            Instruction::new(mov, size, &[Reg(c.reg(&modrm, byte)?), modrm.rm])
+        }
+        0x8D => {
+            let size = c.size();
+            let modrm = c.modrm(false)?;
+
+            Instruction::new(lea, size, &[Reg(c.reg(&modrm, false)?), modrm.rm])
+        }
+        0x8F => {
+            let modrm = c.modrm(false)?;
+
+            Instruction::new(pop, stack, &[modrm.rm])
+        }
+        0x90 if !c.rex.b => Instruction::new(nop, Size::Dword, &[]),
+        0x99 => Instruction::new(cqo, c.size(), &[]),
+        0xB0..=0xB7 => {
+            let register = c.register(opcode & 0b111, c.rex.b, true)?;
+
+            Instruction::new(mov, Size::Byte, &[Reg(register), Immediate(c.i8()?)])
+        }
+        0xB8..=0xBF => {
+            let size = c.size();
+            let register = c.register(opcode & 0b111, c.rex.b, false)?;
+            let immediate = match size {
+                Size::Qword => c.i64()?,
+                size => c.immediate(size)?,
+            };
+
+            Instruction::new(mov, size, &[Reg(register), Immediate(immediate)])
+        }
+        0xC0 | 0xC1 | 0xD0 | 0xD1 | 0xD2 | 0xD3 => {
+            let size = c.sized(opcode);
+            let modrm = c.modrm(size == Size::Byte)?;
+            let mnemonic =
+                shifts[(modrm.reg & 0b111) as usize].ok_or(Error::Unsupported(opcode))?;
+            let count = match opcode {
+                0xC0 | 0xC1 => Immediate(c.byte()? as i64),
+                0xD0 | 0xD1 => Immediate(1),
+                _ => Reg(Register::rcx),
+            };
+
+            Instruction::new(mnemonic, size, &[modrm.rm, count])
+        }
+        0xC3 => Instruction::new(ret, stack, &[]),
+        0xC6 | 0xC7 => {
+            let size = c.sized(opcode);
+            let modrm = c.modrm(size == Size::Byte)?;
+
+            if modrm.reg & 0b111 != 0 {
+                return Err(Error::Unsupported(opcode));
+            }
+
+            Instruction::new(mov, size, &[modrm.rm, Immediate(c.immediate(size)?)])
+        }
+        0xC9 => Instruction::new(leave, stack, &[]),
+        0xCD => Instruction::new(int, Size::Byte, &[Immediate(c.byte()? as i64)]),
+        0xE8 => Instruction::new(call, Size::Dword, &[Relative(c.i32()?)]),
+        0xE9 => Instruction::new(jmp, Size::Dword, &[Relative(c.i32()?)]),
+        0xEB => Instruction::new(jmp, Size::Byte, &[Relative(c.i8()? as i32)]),
+        0xF6 | 0xF7 => {
+            let size = c.sized(opcode);
+            let modrm = c.modrm(size == Size::Byte)?;
+            let mnemonic =
+                group3[(modrm.reg & 0b111) as usize].ok_or(Error::Unsupported(opcode))?;
+
+            if mnemonic == test {
+                Instruction::new(test, size, &[modrm.rm, Immediate(c.immediate(size)?)])
+            } else {
+                Instruction::new(mnemonic, size, &[modrm.rm])
+            }
+        }
+        0xFF => {
+            let modrm = c.modrm(false)?;
+            let mnemonic = match modrm.reg & 0b111 {
+                2 => call,
+                4 => jmp,
+                6 => push,
+                _ => return Err(Error::Unsupported(opcode)),
+            };
+
+            Instruction::new(mnemonic, stack, &[modrm.rm])
+        }
+        0x0F => {
+            let opcode = c.byte()?;
+
+            match opcode {
+                0x05 => Instruction::new(syscall, Size::Qword, &[]),
+                0x80..=0x8F => Instruction::new(
+                    j(Condition::from_code(opcode)),
+                    Size::Dword,
+                    &[Relative(c.i32()?)],
+                ),
+                0x90..=0x9F => {
+                    let modrm = c.modrm(true)?;
+
+                    Instruction::new(set(Condition::from_code(opcode)), Size::Byte, &[modrm.rm])
+                }
+                0xAF => {
+                    let size = c.size();
+                    let modrm = c.modrm(false)?;
+
+                    Instruction::new(imul, size, &[Reg(c.reg(&modrm, false)?), modrm.rm])
+                }
+                0xB6 | 0xB7 | 0xBE | 0xBF => {
+                    let size = c.size();
+                    let from = if opcode & 1 == 0 {
+                        Size::Byte
+                    } else {
+                        Size::Word
+                    };
+                    let mnemonic = if opcode < 0xBE {
+                        movzx(from)
+                    } else {
+                        movsx(from)
+                    };
+                    let modrm = c.modrm(from == Size::Byte)?;
+
+                    Instruction::new(mnemonic, size, &[Reg(c.reg(&modrm, false)?), modrm.rm])
+                }
+                opcode => return Err(Error::Unsupported(opcode)),
+            }
+        }
+        opcode => return Err(Error::Unsupported(opcode)),
+    };
+
+    Ok((instruction, cursor.offset))
+}
+
+/// Decodes a byte slice one instruction at a time, yielding the offset and either the
+/// instruction with its length, or the error for the byte at that offset.
+#[derive(Clone, Debug)]
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    mode: Mode,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self::with_mode(bytes, Mode::Long)
+    }
+
+    pub fn with_mode(bytes: &'a [u8], mode: Mode) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            mode,
+        }
+    }
+}
+
+impl<'a> Iterator for Decoder<'a> {
+    type Item = (usize, Result<(Instruction, usize), Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.bytes.len() {
+            return None;
+        }
+
+        let offset = self.offset;
+        let result = decode_mode(&self.bytes[offset..], self.mode);
+
+        // skip a single byte on errors, so decoding can resynchronise
+        self.offset += match &result {
+            Ok((_, len)) => *len,
+            Err(_) => 1,
+        };
+
+        Some((offset, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::encoder::Syntax;
+    use super::*;
+    use Mnemonic::*;
+    use Operand::{Immediate, Memory as Mem, Register as Reg, Relative};
+    use Register::{r12, r13, r15, r8, r9, rax, rbp, rbx, rcx, rdi, rsi, rsp};
+
+    /// Immediates which fit each operand size, sign extended as the decoder reads them.
+    fn immediates(size: Size) -> &'static [i64] {
+        match size {
+            Size::Byte => &[5, -1, 0x7F],
+            Size::Word => &[0x1234, -2],
+            Size::Dword | Size::Qword => &[0x12345, -0x8000_0000],
+        }
+    }
+
+    /// Every mnemonic and operand form the encoder supports in `mode`.
+    fn forms(mode: Mode) -> Vec<Instruction> {
+        let long = mode == Mode::Long;
+        let stack = mode.stack();
+        let mut sizes = vec![Size::Byte, Size::Word, Size::Dword];
+        let mut registers = vec![rax, rcx, rbx];
+        let mut memories = vec![
+            Memory::base(rax),
+            Memory::base(rsp),
+            Memory::base(rbp),
+            Memory::base(rbx).displacement(-4),
+            Memory::base(rsi).displacement(0x1000),
+            Memory::base(rax).index(rcx, 4),
+            Memory::base(rbp).index(rdi, 8).displacement(16),
+            Memory::absolute(0x1000),
+            Memory::absolute(16).index(rbx, 2),
+        ];
+
+        if long {
+            sizes.push(Size::Qword);
+            // sil needs a REX prefix as a byte register
+            registers.extend([rsi, r9]);
+            memories.extend([
+                Memory::base(r12),
+                Memory::base(r13),
+                Memory::base(r8).index(r15, 2).displacement(-128),
+                Memory::rip(0x10),
+            ]);
+        }
+
+        let mut forms = vec![];
+        let mut form = |mnemonic, size, operands: &[Operand]| {
+            forms.push(Instruction::new(mnemonic, size, operands));
+        };
+
+        for &size in &sizes {
+            for &register in &registers {
+                let dst = Reg(register);
+                let src = Reg(rcx);
+
+                form(mov, size, &[dst, src]);
+
+                for &immediate in immediates(size) {
+                    form(mov, size, &[dst, Immediate(immediate)]);
+                    form(test, size, &[dst, Immediate(immediate)]);
+
+                    for mnemonic in [add, or, and, sub, xor, cmp] {
+                        form(mnemonic, size, &[dst, Immediate(immediate)]);
+                    }
+                }
+
+                for mnemonic in [add, or, and, sub, xor, cmp, test] {
+                    form(mnemonic, size, &[dst, src]);
+                }
+
+                for mnemonic in [not, neg, mul, imul, div, idiv] {
+                    form(mnemonic, size, &[dst]);
+                }
+
+                for mnemonic in [shl, shr, sar] {
+                    form(mnemonic, size, &[dst, Immediate(1)]);
+                    form(mnemonic, size, &[dst, Immediate(5)]);
+                    form(mnemonic, size, &[dst, Reg(rcx)]);
+                }
+
+                if size != Size::Byte {
+                    form(imul, size, &[dst, src]);
+                    form(imul, size, &[dst, src, Immediate(5)]);
+                    form(imul, size, &[dst, src, Immediate(0x1234)]);
+                    form(cqo, size, &[]);
+                }
+
+                for from in [Size::Byte, Size::Word] {
+                    if from < size {
+                        form(movzx(from), size, &[dst, Reg(register)]);
+                        form(movsx(from), size, &[dst, Reg(register)]);
+                    }
+                }
+            }
+
+            for &memory in &memories {
+                let memory = Mem(memory);
+
+                form(mov, size, &[memory, Reg(rbx)]);
+                form(mov, size, &[Reg(rbx), memory]);
+                form(mov, size, &[memory, Immediate(5)]);
+                form(test, size, &[memory, Reg(rbx)]);
+                form(test, size, &[memory, Immediate(5)]);
+
+                for mnemonic in [add, or, and, sub, xor, cmp] {
+                    form(mnemonic, size, &[memory, Reg(rbx)]);
+                    form(mnemonic, size, &[Reg(rbx), memory]);
+                    form(mnemonic, size, &[memory, Immediate(5)]);
+                }
+
+                for mnemonic in [not, neg, mul, imul, div, idiv] {
+                    form(mnemonic, size, &[memory]);
+                }
+
+                for mnemonic in [shl, shr, sar] {
+                    form(mnemonic, size, &[memory, Immediate(1)]);
+                    form(mnemonic, size, &[memory, Reg(rcx)]);
+                }
+
+                if size != Size::Byte {
+                    form(lea, size, &[Reg(rbx), memory]);
+                    form(imul, size, &[Reg(rbx), memory]);
+                    form(imul, size, &[Reg(rbx), memory, Immediate(0x1234)]);
+                }
+
+                for from in [Size::Byte, Size::Word] {
+                    if from < size {
+                        form(movzx(from), size, &[Reg(rbx), memory]);
+                        form(movsx(from), size, &[Reg(rbx), memory]);
+                    }
+                }
+            }
+        }
+
+        for &register in &registers {
+            form(push, stack, &[Reg(register)]);
+            form(pop, stack, &[Reg(register)]);
+            form(call, stack, &[Reg(register)]);
+            form(jmp, stack, &[Reg(register)]);
+        }
+
+        for &memory in &memories {
+            form(push, stack, &[Mem(memory)]);
+            form(pop, stack, &[Mem(memory)]);
+            form(call, stack, &[Mem(memory)]);
+            form(jmp, stack, &[Mem(memory)]);
+        }
+
+        for condition in Condition::ALL {
+            form(j(condition), Size::Byte, &[Relative(0x10)]);
+            form(j(condition), Size::Dword, &[Relative(-0x1000)]);
+            form(set(condition), Size::Byte, &[Reg(rax)]);
+            form(set(condition), Size::Byte, &[Mem(Memory::base(rbx))]);
+        }
+
+        form(push, stack, &[Immediate(5)]);
+        form(push, stack, &[Immediate(0x1234)]);
+        form(call, Size::Dword, &[Relative(0x100)]);
+        form(call, Size::Dword, &[Relative(-5)]);
+        form(jmp, Size::Byte, &[Relative(-2)]);
+        form(jmp, Size::Dword, &[Relative(0x1000)]);
+        form(ret, stack, &[]);
+        form(leave, stack, &[]);
+        form(int, Size::Byte, &[Immediate(0x80)]);
+        form(nop, Size::Dword, &[]);
+
+        if long {
+            form(mov, Size::Qword, &[Reg(rax), Immediate(1 << 40)]);
+            form(movsx(Size::Dword), Size::Qword, &[Reg(rax), Reg(rcx)]);
+            form(
+                movsx(Size::Dword),
+                Size::Qword,
+                &[Reg(r9), Mem(Memory::base(rbx))],
+            );
+            form(syscall, Size::Qword, &[]);
+        }
+
+        forms
+    }
+
+    fn round_trip(mode: Mode) {
+        for instruction in forms(mode) {
+            let opcode = instruction
+                .encode_mode(mode)
+                .unwrap_or_else(|error| panic!("{instruction:?}: {}", error.message()));
+            let bytes = opcode.as_bytes();
+            let decoded = decode_mode(bytes, mode)
+                .unwrap_or_else(|error| panic!("{instruction:?}: {}", error.message()));
+
+            assert_eq!(decoded, (instruction, bytes.len()), "{bytes:02x?}");
+        }
+    }
+
+    #[test]
+    fn round_trip_long() {
+        round_trip(Mode::Long);
+    }
+
+    #[test]
+    fn round_trip_protected() {
+        round_trip(Mode::Protected);
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(decode(&[0x48, 0x8B]), Err(Error::Truncated));
+        assert_eq!(decode(&[0xE8, 0x00, 0x00]), Err(Error::Truncated));
+        assert_eq!(decode(&[]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn high_byte_registers() {
+        // mov ah, 1
+        assert_eq!(decode(&[0xB4, 0x01]), Err(Error::HighByte));
+    }
+
+    #[test]
+    fn protected_registers_are_32_bit() {
+        // mov ebx, [ebp - 4] and mov eax, [esp + ecx*4]
+        let (frame, _) = decode_mode(&[0x8B, 0x5D, 0xFC], Mode::Protected).unwrap();
+        let (indexed, _) = decode_mode(&[0x8B, 0x04, 0x8C], Mode::Protected).unwrap();
+
+        assert!(frame
+            .display_syntax(Syntax::Intel, Mode::Protected)
+            .starts_with("mov ebx, [ebp - "));
+        assert!(frame
+            .display_syntax(Syntax::Att, Mode::Protected)
+            .ends_with("(%ebp), %ebx"));
+        assert_eq!(
+            indexed.display_syntax(Syntax::Intel, Mode::Protected),
+            "mov eax, [esp + ecx*4]"
+        );
+        assert_eq!(
+            indexed.display_syntax(Syntax::Att, Mode::Protected),
+            "movl (%esp,%ecx,4), %eax"
+        );
+    }
+}
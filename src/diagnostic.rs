@@ -0,0 +1,218 @@
+use super::span::{Lines, Span};
+use core::fmt::Write;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn display(&self) -> String {
+        match self {
+            Severity::Error => "\x1b[1;38;5;1merror\x1b[m".to_string(),
+            Severity::Warning => "\x1b[1;38;5;3mwarning\x1b[m".to_string(),
+            Severity::Note => "\x1b[1;38;5;4mnote\x1b[m".to_string(),
+        }
+    }
+
+    fn caret(&self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[1;38;5;1m",
+            Severity::Warning => "\x1b[1;38;5;3m",
+            Severity::Note => "\x1b[1;38;5;4m",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// primary span, underlined with carets
+    pub span: Span,
+    /// label printed after the carets
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, span: Span, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            span,
+            label: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn label(&mut self, label: impl Into<String>) -> &mut Diagnostic {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn note(&mut self, note: impl Into<String>) -> &mut Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders a rustc style snippet.
+    ///
+    /// ```text
+    /// error: unexpected character `$`
+    ///  --> examples/hello.em:2:5
+    ///   |
+    /// 2 |     $sys::syscall(60, 0)
+    ///   |     ^
+    ///   |
+    ///   = note: ...
+    /// ```
+    pub fn render(&self, path: &str, lines: &Lines) -> String {
+        let mut output = String::new();
+        let start = lines.location(self.span.start);
+        let line = lines.line(start.line);
+        let gutter = start.line.to_string().len();
+        let pad = " ".repeat(gutter);
+
+        // only the first line of a multi-line span is underlined
+        let end = if lines.index(self.span.end) == start.line - 1 {
+            lines.location(self.span.end).column
+        } else {
+            line.chars().count() + 1
+        };
+
+        let carets = "^".repeat(end.saturating_sub(start.column).max(1));
+        let indent: String = line
+            .chars()
+            .take(start.column - 1)
+            .map(|char0| if char0 == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let _ = writeln!(
+            output,
+            "{}: \x1b[1m{}\x1b[m",
+            self.severity.display(),
+            self.message
+        );
+        let _ = writeln!(
+            output,
+            "{pad}\x1b[1;38;5;4m-->\x1b[m {path}:{}:{}",
+            start.line, start.column
+        );
+        let _ = writeln!(output, "{pad} \x1b[1;38;5;4m|\x1b[m");
+        let _ = writeln!(
+            output,
+            "\x1b[1;38;5;4m{}\x1b[m \x1b[1;38;5;4m|\x1b[m {line}",
+            start.line
+        );
+        let _ = write!(
+            output,
+            "{pad} \x1b[1;38;5;4m|\x1b[m {indent}{}{carets}",
+            self.severity.caret()
+        );
+
+        if let Some(label) = &self.label {
+            let _ = write!(output, " {label}");
+        }
+
+        let _ = writeln!(output, "\x1b[m");
+
+        if !self.notes.is_empty() {
+            let _ = writeln!(output, "{pad} \x1b[1;38;5;4m|\x1b[m");
+        }
+
+        for note in &self.notes {
+            let _ = writeln!(
+                output,
+                "{pad} \x1b[1;38;5;4m=\x1b[m \x1b[1mnote\x1b[m: {note}"
+            );
+        }
+
+        output
+    }
+}
+
+/// Errors and warnings collected while compiling a file.
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self {
+            diagnostics: vec![],
+        }
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) -> &mut Diagnostic {
+        self.diagnostics.push(diagnostic);
+        self.diagnostics.last_mut().unwrap()
+    }
+
+    pub fn error(&mut self, span: Span, message: impl Into<String>) -> &mut Diagnostic {
+        self.push(Diagnostic::new(Severity::Error, span, message))
+    }
+
+    pub fn warning(&mut self, span: Span, message: impl Into<String>) -> &mut Diagnostic {
+        self.push(Diagnostic::new(Severity::Warning, span, message))
+    }
+
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.diagnostics.extend(other.diagnostics);
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    /// Renders every diagnostic in source order, followed by a summary line if there were
+    /// errors.
+    pub fn render(&self, path: &str, input: &str) -> String {
+        let lines = Lines::new(input);
+        let mut diagnostics: Vec<_> = self.diagnostics.iter().collect();
+        let mut output = String::new();
+
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+
+        for diagnostic in diagnostics {
+            output.push_str(&diagnostic.render(path, &lines));
+            output.push('\n');
+        }
+
+        match self.error_count() {
+            0 => {}
+            1 => output.push_str(&format!(
+                "{}: \x1b[1maborting due to previous error\x1b[m\n",
+                Severity::Error.display()
+            )),
+            count => output.push_str(&format!(
+                "{}: \x1b[1maborting due to {count} previous errors\x1b[m\n",
+                Severity::Error.display()
+            )),
+        }
+
+        output
+    }
+}
@@ -0,0 +1,602 @@
+use super::elf::Class;
+use super::layout::{self, Layout, Target};
+use super::parser::Type;
+use super::relocation::Kind;
+
+/// Version of the units and line tables, the most widely supported one.
+pub const VERSION: u16 = 4;
+
+pub const TAG_ARRAY_TYPE: u64 = 0x01;
+pub const TAG_COMPILE_UNIT: u64 = 0x11;
+pub const TAG_SUBRANGE_TYPE: u64 = 0x21;
+pub const TAG_BASE_TYPE: u64 = 0x24;
+pub const TAG_SUBPROGRAM: u64 = 0x2e;
+pub const TAG_VARIABLE: u64 = 0x34;
+
+pub const AT_LOCATION: u64 = 0x02;
+pub const AT_NAME: u64 = 0x03;
+pub const AT_BYTE_SIZE: u64 = 0x0b;
+pub const AT_STMT_LIST: u64 = 0x10;
+pub const AT_LOW_PC: u64 = 0x11;
+/// Size of the code when it's a constant rather than an address.
+pub const AT_HIGH_PC: u64 = 0x12;
+pub const AT_LANGUAGE: u64 = 0x13;
+pub const AT_COMP_DIR: u64 = 0x1b;
+pub const AT_PRODUCER: u64 = 0x25;
+pub const AT_COUNT: u64 = 0x37;
+pub const AT_DECL_FILE: u64 = 0x3a;
+pub const AT_DECL_LINE: u64 = 0x3b;
+pub const AT_ENCODING: u64 = 0x3e;
+pub const AT_EXTERNAL: u64 = 0x3f;
+/// Location locals are relative to.
+pub const AT_FRAME_BASE: u64 = 0x40;
+pub const AT_TYPE: u64 = 0x49;
+
+/// An address, relocated.
+pub const FORM_ADDR: u64 = 0x01;
+pub const FORM_DATA1: u64 = 0x0b;
+pub const FORM_DATA2: u64 = 0x05;
+pub const FORM_DATA4: u64 = 0x06;
+/// A NUL terminated string in place.
+pub const FORM_STRING: u64 = 0x08;
+pub const FORM_UDATA: u64 = 0x0f;
+/// Offset of an entry from the start of its unit.
+pub const FORM_REF4: u64 = 0x13;
+/// Offset into another debug section, relocated.
+pub const FORM_SEC_OFFSET: u64 = 0x17;
+/// A length followed by a location expression.
+pub const FORM_EXPRLOC: u64 = 0x18;
+/// A flag which is set by being present.
+pub const FORM_FLAG_PRESENT: u64 = 0x19;
+
+/// C99, the closest language debuggers know.
+pub const LANG_C99: u16 = 0x0c;
+
+pub const ATE_SIGNED: u8 = 0x05;
+pub const ATE_UNSIGNED: u8 = 0x07;
+/// Shown as characters, so byte arrays print as strings.
+pub const ATE_UNSIGNED_CHAR: u8 = 0x08;
+
+/// Pushes an address onto the expression stack.
+pub const OP_ADDR: u8 = 0x03;
+/// The value is in ebp, DWARF register 5 on i386.
+pub const OP_REG5: u8 = 0x55;
+/// The value is in rbp, DWARF register 6 on x86-64.
+pub const OP_REG6: u8 = 0x56;
+/// Pushes the frame base plus a signed offset.
+pub const OP_FBREG: u8 = 0x91;
+
+pub const LNS_COPY: u8 = 1;
+pub const LNS_ADVANCE_PC: u8 = 2;
+pub const LNS_ADVANCE_LINE: u8 = 3;
+pub const LNS_SET_COLUMN: u8 = 5;
+/// The next row is where a breakpoint on the function goes.
+pub const LNS_SET_PROLOGUE_END: u8 = 10;
+
+pub const LNE_END_SEQUENCE: u8 = 1;
+pub const LNE_SET_ADDRESS: u8 = 2;
+
+/// Smallest line advance of a special opcode.
+const LINE_BASE: i8 = -5;
+/// Number of line advances special opcodes cover.
+const LINE_RANGE: u8 = 14;
+/// First special opcode, after the standard ones.
+const OPCODE_BASE: u8 = 13;
+/// Number of operands of each standard opcode.
+const OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+/// Abbreviation codes, each declared in `.debug_abbrev` by `ABBREVIATIONS`.
+const UNIT: u64 = 1;
+const SUBPROGRAM: u64 = 2;
+const SUBPROGRAM_EXTERNAL: u64 = 3;
+const VARIABLE: u64 = 4;
+const BASE_TYPE: u64 = 5;
+const ARRAY_TYPE: u64 = 6;
+const SUBRANGE_TYPE: u64 = 7;
+const LOCAL: u64 = 8;
+
+/// Code, tag, whether entries have children, and the attributes with their forms.
+type Abbreviation = (u64, u64, bool, &'static [(u64, u64)]);
+
+const ABBREVIATIONS: [Abbreviation; 8] = [
+    (
+        UNIT,
+        TAG_COMPILE_UNIT,
+        true,
+        &[
+            (AT_PRODUCER, FORM_STRING),
+            (AT_LANGUAGE, FORM_DATA2),
+            (AT_NAME, FORM_STRING),
+            (AT_COMP_DIR, FORM_STRING),
+            (AT_LOW_PC, FORM_ADDR),
+            (AT_HIGH_PC, FORM_DATA4),
+            (AT_STMT_LIST, FORM_SEC_OFFSET),
+        ],
+    ),
+    (
+        SUBPROGRAM,
+        TAG_SUBPROGRAM,
+        true,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_DECL_FILE, FORM_DATA1),
+            (AT_DECL_LINE, FORM_UDATA),
+            (AT_LOW_PC, FORM_ADDR),
+            (AT_HIGH_PC, FORM_DATA4),
+            (AT_FRAME_BASE, FORM_EXPRLOC),
+        ],
+    ),
+    (
+        SUBPROGRAM_EXTERNAL,
+        TAG_SUBPROGRAM,
+        true,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_DECL_FILE, FORM_DATA1),
+            (AT_DECL_LINE, FORM_UDATA),
+            (AT_LOW_PC, FORM_ADDR),
+            (AT_HIGH_PC, FORM_DATA4),
+            (AT_FRAME_BASE, FORM_EXPRLOC),
+            (AT_EXTERNAL, FORM_FLAG_PRESENT),
+        ],
+    ),
+    (
+        VARIABLE,
+        TAG_VARIABLE,
+        false,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_DECL_FILE, FORM_DATA1),
+            (AT_DECL_LINE, FORM_UDATA),
+            (AT_TYPE, FORM_REF4),
+            (AT_LOCATION, FORM_EXPRLOC),
+        ],
+    ),
+    (
+        BASE_TYPE,
+        TAG_BASE_TYPE,
+        false,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_ENCODING, FORM_DATA1),
+            (AT_BYTE_SIZE, FORM_DATA1),
+        ],
+    ),
+    (
+        ARRAY_TYPE,
+        TAG_ARRAY_TYPE,
+        true,
+        &[(AT_NAME, FORM_STRING), (AT_TYPE, FORM_REF4)],
+    ),
+    (
+        SUBRANGE_TYPE,
+        TAG_SUBRANGE_TYPE,
+        false,
+        &[(AT_COUNT, FORM_UDATA)],
+    ),
+    (
+        LOCAL,
+        TAG_VARIABLE,
+        false,
+        &[
+            (AT_NAME, FORM_STRING),
+            (AT_DECL_FILE, FORM_DATA1),
+            (AT_DECL_LINE, FORM_UDATA),
+            (AT_TYPE, FORM_REF4),
+            (AT_LOCATION, FORM_EXPRLOC),
+        ],
+    ),
+];
+
+/// Contents of a debug section, with the fields to relocate once addresses are known.
+#[derive(Clone, Debug, Default)]
+struct Buffer {
+    data: Vec<u8>,
+    /// offset of the field, kind, target and addend
+    relocations: Vec<(u64, Kind, Target, i64)>,
+}
+
+impl Buffer {
+    fn offset(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    /// Overwrites a `u32` written earlier, such as a length once it's known.
+    fn patch_u32(&mut self, offset: u64, value: u32) {
+        let offset = offset as usize;
+
+        self.data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn uleb(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+
+            value >>= 7;
+
+            if value == 0 {
+                self.data.push(byte);
+                break;
+            }
+
+            self.data.push(byte | 0x80);
+        }
+    }
+
+    fn sleb(&mut self, mut value: i64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+
+            value >>= 7;
+
+            // done once the rest is the sign extension of the byte's top bit
+            if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+                self.data.push(byte);
+                break;
+            }
+
+            self.data.push(byte | 0x80);
+        }
+    }
+
+    fn string(&mut self, string: &str) {
+        self.data.extend(string.as_bytes());
+        self.data.push(0);
+    }
+
+    /// A field holding `addend` bytes past `target`, filled in by the layout.
+    fn reference(&mut self, kind: Kind, target: Target, addend: i64) {
+        self.relocations.push((self.offset(), kind, target, addend));
+        self.data.resize(self.data.len() + kind.size(), 0);
+    }
+
+    /// Adds the buffer as a debug section, returning its id.
+    fn push(self, layout: &mut Layout, name: &str) -> usize {
+        let mut section = layout::Section::new(name);
+
+        section.data(self.data);
+
+        let id = layout.push_section(section);
+
+        for (offset, kind, target, addend) in self.relocations {
+            let mut relocation = layout::Relocation::new(id, offset, kind, target);
+
+            relocation.addend(addend);
+            layout.push_relocation(relocation);
+        }
+
+        id
+    }
+}
+
+/// Debug information of one source file, written as DWARF `.debug_abbrev`, `.debug_info`
+/// and `.debug_line` sections. Code offsets are into `.text`, and every address is
+/// relocated.
+#[derive(Clone, Debug)]
+pub struct Debug {
+    path: String,
+    directory: String,
+    /// offset, line and column of each instruction
+    rows: Vec<(u64, u64, u64)>,
+    /// name, line, start, end of the prologue and end of each function, and whether it's
+    /// global
+    functions: Vec<(String, u64, u64, u64, u64, bool)>,
+    /// function, name, line, type and frame offset of each local
+    locals: Vec<(usize, String, u64, Type, i64)>,
+    /// name, line, type, section and offset of each static
+    variables: Vec<(String, u64, Type, usize, u64)>,
+}
+
+impl Debug {
+    /// Debug information of `path`, relative to `directory`.
+    pub fn new(path: &str, directory: &str) -> Debug {
+        Debug {
+            path: path.to_string(),
+            directory: directory.to_string(),
+            rows: vec![],
+            functions: vec![],
+            locals: vec![],
+            variables: vec![],
+        }
+    }
+
+    /// Maps the instruction at `offset` to a 1-based line and column.
+    pub fn row(&mut self, offset: u64, line: u64, column: u64) -> &mut Debug {
+        self.rows.push((offset, line, column));
+        self
+    }
+
+    /// A function from `start` to `end`, whose body starts at `prologue`.
+    pub fn function(
+        &mut self,
+        name: &str,
+        line: u64,
+        start: u64,
+        prologue: u64,
+        end: u64,
+        global: bool,
+    ) -> &mut Debug {
+        self.functions
+            .push((name.to_string(), line, start, prologue, end, global));
+        self
+    }
+
+    /// A local of the last function, `offset` bytes from its frame pointer.
+    pub fn local(&mut self, name: &str, line: u64, kind: &Type, offset: i64) -> &mut Debug {
+        let function = self.functions.len() - 1;
+
+        self.locals
+            .push((function, name.to_string(), line, kind.clone(), offset));
+        self
+    }
+
+    /// A static at `offset` into `section`.
+    pub fn variable(
+        &mut self,
+        name: &str,
+        line: u64,
+        kind: &Type,
+        section: usize,
+        offset: u64,
+    ) -> &mut Debug {
+        self.variables
+            .push((name.to_string(), line, kind.clone(), section, offset));
+        self
+    }
+
+    /// Adds the debug sections for code in `text`, which is `len` bytes long.
+    pub fn push(&self, layout: &mut Layout, class: Class, text: usize, len: u64) {
+        let (address, word, frame) = match class {
+            Class::Class32 => (Kind::Absolute32, 4, OP_REG5),
+            Class::Class64 => (Kind::Absolute64, 8, OP_REG6),
+        };
+
+        let mut abbrev = Buffer::default();
+
+        for (code, tag, children, attributes) in ABBREVIATIONS.iter() {
+            abbrev.uleb(*code);
+            abbrev.uleb(*tag);
+            abbrev.u8(*children as u8);
+
+            for (name, form) in attributes.iter() {
+                abbrev.uleb(*name);
+                abbrev.uleb(*form);
+            }
+
+            abbrev.uleb(0);
+            abbrev.uleb(0);
+        }
+
+        abbrev.uleb(0);
+
+        let mut line = Buffer::default();
+
+        line.u32(0);
+        line.u16(VERSION);
+
+        let header_len = line.offset();
+
+        line.u32(0);
+        // minimum instruction length, maximum operations per instruction, `is_stmt`
+        line.u8(1);
+        line.u8(1);
+        line.u8(1);
+        line.u8(LINE_BASE as u8);
+        line.u8(LINE_RANGE);
+        line.u8(OPCODE_BASE);
+
+        for len in OPCODE_LENGTHS.iter() {
+            line.u8(*len);
+        }
+
+        // no include directories, the file is relative to the compilation directory
+        line.u8(0);
+        line.string(&self.path);
+        line.uleb(0);
+        line.uleb(0);
+        line.uleb(0);
+        line.u8(0);
+
+        let program = line.offset();
+
+        line.patch_u32(header_len, (program - header_len - 4) as u32);
+
+        line.u8(0);
+        line.uleb(1 + word);
+        line.u8(LNE_SET_ADDRESS);
+        line.reference(address, Target::Section(text), 0);
+
+        // the state machine starts at line 1, column 0
+        let (mut offset, mut row_line, mut column) = (0, 1, 0);
+        let mut last = None;
+
+        for &(start, row, row_column) in &self.rows {
+            let function = self.functions.iter().any(|function| function.2 == start);
+            let body = self.functions.iter().any(|function| function.3 == start);
+
+            // one row per change of position, and one at the start and after the prologue of
+            // every function
+            if last == Some((row, row_column)) && !function && !body {
+                continue;
+            }
+
+            if start != offset {
+                line.u8(LNS_ADVANCE_PC);
+                line.uleb(start - offset);
+            }
+
+            if row != row_line {
+                line.u8(LNS_ADVANCE_LINE);
+                line.sleb(row as i64 - row_line as i64);
+            }
+
+            if row_column != column {
+                line.u8(LNS_SET_COLUMN);
+                line.uleb(row_column);
+            }
+
+            // so breaking on a function stops at its first statement, with the frame built
+            if body {
+                line.u8(LNS_SET_PROLOGUE_END);
+            }
+
+            line.u8(LNS_COPY);
+
+            offset = start;
+            row_line = row;
+            column = row_column;
+            last = Some((row, row_column));
+        }
+
+        if len != offset {
+            line.u8(LNS_ADVANCE_PC);
+            line.uleb(len - offset);
+        }
+
+        line.u8(0);
+        line.uleb(1);
+        line.u8(LNE_END_SEQUENCE);
+
+        let unit_len = line.offset() - 4;
+
+        line.patch_u32(0, unit_len as u32);
+
+        let abbrev = abbrev.push(layout, ".debug_abbrev");
+        let line = line.push(layout, ".debug_line");
+        let mut info = Buffer::default();
+
+        info.u32(0);
+        info.u16(VERSION);
+        info.reference(Kind::Absolute32, Target::Section(abbrev), 0);
+        info.u8(word as u8);
+
+        info.uleb(UNIT);
+        info.string(&format!("empiric {}", env!("CARGO_PKG_VERSION")));
+        info.u16(LANG_C99);
+        info.string(&self.path);
+        info.string(&self.directory);
+        info.reference(address, Target::Section(text), 0);
+        info.u32(len as u32);
+        info.reference(Kind::Absolute32, Target::Section(line), 0);
+
+        // offset from the start of the unit of every type's entry
+        let mut types = vec![];
+
+        for (index, (name, decl_line, start, _, end, global)) in self.functions.iter().enumerate() {
+            let locals = self.locals.iter().filter(|local| local.0 == index);
+
+            // types go first, so they aren't children of the function
+            let kinds: Vec<u32> = locals
+                .clone()
+                .map(|local| Self::type_entry(&mut info, &mut types, &local.3))
+                .collect();
+
+            info.uleb(if *global {
+                SUBPROGRAM_EXTERNAL
+            } else {
+                SUBPROGRAM
+            });
+            info.string(name);
+            info.u8(1);
+            info.uleb(*decl_line);
+            info.reference(address, Target::Section(text), *start as i64);
+            info.u32((end - start) as u32);
+            info.uleb(1);
+            info.u8(frame);
+
+            for ((_, name, decl_line, _, offset), kind) in locals.zip(kinds) {
+                let mut location = Buffer::default();
+
+                location.u8(OP_FBREG);
+                location.sleb(*offset);
+
+                info.uleb(LOCAL);
+                info.string(name);
+                info.u8(1);
+                info.uleb(*decl_line);
+                info.u32(kind);
+                info.uleb(location.offset());
+                info.data.extend(location.data);
+            }
+
+            // ends the function's children
+            info.uleb(0);
+        }
+
+        for (name, decl_line, kind, section, offset) in &self.variables {
+            let kind = Self::type_entry(&mut info, &mut types, kind);
+
+            info.uleb(VARIABLE);
+            info.string(name);
+            info.u8(1);
+            info.uleb(*decl_line);
+            info.u32(kind);
+            info.uleb(1 + word);
+            info.u8(OP_ADDR);
+            info.reference(address, Target::Section(*section), *offset as i64);
+        }
+
+        // ends the unit's children
+        info.uleb(0);
+
+        let unit_len = info.offset() - 4;
+
+        info.patch_u32(0, unit_len as u32);
+        info.push(layout, ".debug_info");
+    }
+
+    /// Offset of the entry describing `kind`, adding it and the entries it refers to first if
+    /// they're new.
+    fn type_entry(info: &mut Buffer, types: &mut Vec<(Type, u32)>, kind: &Type) -> u32 {
+        if let Some((_, offset)) = types.iter().find(|(other, _)| other == kind) {
+            return *offset;
+        }
+
+        let offset = match kind {
+            Type::Array(element, len) => {
+                let element = Self::type_entry(info, types, element);
+                let offset = info.offset() as u32;
+
+                info.uleb(ARRAY_TYPE);
+                info.string(&kind.display());
+                info.u32(element);
+                info.uleb(SUBRANGE_TYPE);
+                info.uleb(*len);
+                info.uleb(0);
+
+                offset
+            }
+            integer => {
+                let offset = info.offset() as u32;
+                let encoding = match integer {
+                    Type::U8 => ATE_UNSIGNED_CHAR,
+                    signed if signed.is_signed() => ATE_SIGNED,
+                    _ => ATE_UNSIGNED,
+                };
+
+                info.uleb(BASE_TYPE);
+                info.string(&integer.display());
+                info.u8(encoding);
+                info.u8(integer.size() as u8);
+
+                offset
+            }
+        };
+
+        types.push((kind.clone(), offset));
+
+        offset
+    }
+}
@@ -0,0 +1,90 @@
+use super::elf::Endian;
+use super::reader::{Error, Reader};
+use super::writer::Writer;
+
+/// Size of an ELF64 dynamic entry.
+pub const SIZE: u64 = 16;
+
+/// Size of an ELF32 dynamic entry.
+pub const SIZE32: u64 = 8;
+
+/// Ends the table.
+pub const TAG_NULL: u64 = 0;
+/// Offset in `.dynstr` of a needed library's name.
+pub const TAG_NEEDED: u64 = 1;
+pub const TAG_PLTRELSZ: u64 = 2;
+pub const TAG_PLTGOT: u64 = 3;
+pub const TAG_STRTAB: u64 = 5;
+pub const TAG_SYMTAB: u64 = 6;
+pub const TAG_RELA: u64 = 7;
+pub const TAG_STRSZ: u64 = 10;
+pub const TAG_SYMENT: u64 = 11;
+/// Kind of the PLT relocations, `TAG_RELA` or `TAG_REL`.
+pub const TAG_PLTREL: u64 = 20;
+/// Filled in by the dynamic linker for debuggers.
+pub const TAG_DEBUG: u64 = 21;
+pub const TAG_JMPREL: u64 = 23;
+pub const TAG_FLAGS: u64 = 30;
+pub const TAG_FLAGS_1: u64 = 0x6fff_fffb;
+
+/// Resolve every symbol at load time.
+pub const FLAG_BIND_NOW: u64 = 1 << 3;
+
+pub const FLAG_1_NOW: u64 = 1 << 0;
+/// The object is a position-independent executable.
+pub const FLAG_1_PIE: u64 = 1 << 27;
+
+/// An entry of `.dynamic`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub tag: u64,
+    pub value: u64,
+}
+
+impl Entry {
+    pub const fn new(tag: u64, value: u64) -> Entry {
+        Entry { tag, value }
+    }
+
+    /// Reads an ELF64 dynamic entry.
+    pub fn from_bytes(bytes: &[u8], endian: Endian) -> Result<Entry, Error> {
+        let reader = Reader::new(bytes, endian, "dynamic entry");
+
+        Ok(Entry {
+            tag: reader.u64(0)?,
+            value: reader.u64(8)?,
+        })
+    }
+
+    /// Reads an ELF32 dynamic entry.
+    pub fn from_bytes32(bytes: &[u8], endian: Endian) -> Result<Entry, Error> {
+        let reader = Reader::new(bytes, endian, "dynamic entry");
+
+        Ok(Entry {
+            tag: reader.u32(0)? as u64,
+            value: reader.u32(4)? as u64,
+        })
+    }
+
+    /// Serializes an ELF32 dynamic entry in the given byte order, truncating to 32 bits.
+    pub fn to_array32(&self, endian: Endian) -> [u8; 8] {
+        let mut bytes = [0; 8];
+
+        Writer::new(&mut bytes, endian)
+            .u32(0, self.tag as u32)
+            .u32(4, self.value as u32);
+
+        bytes
+    }
+
+    /// Serializes an ELF64 dynamic entry in the given byte order.
+    pub fn to_array(&self, endian: Endian) -> [u8; 16] {
+        let mut bytes = [0; 16];
+
+        Writer::new(&mut bytes, endian)
+            .u64(0, self.tag)
+            .u64(8, self.value);
+
+        bytes
+    }
+}
@@ -1,47 +1,54 @@
+use super::reader::{Error, Reader};
+use super::writer::Writer;
+
+/// Size of the ELF64 file header.
+pub const SIZE: u64 = 64;
+
+/// Size of the ELF32 file header.
+pub const SIZE32: u64 = 52;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(u8)]
-enum Class {
+pub enum Class {
     Class32 = 1,
     Class64 = 2,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(u8)]
-enum Endian {
+pub enum Endian {
     Big = 2,
     Little = 1,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(u16)]
-enum Header {
+pub enum Header {
     Header32 = 52,
     Header64 = 64,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(C)]
 pub struct Elf {
-    magic: [u8; 4],
-    class: Class,
-    endian: Endian,
-    version: u8,
-    abi_sysv: u8,
-    abi_version: u8,
-    _pad0: [u8; 7],
-    kind: [u8; 2],
-    machine: [u8; 2],
-    version2: [u8; 4],
-    entry_address: u64,
-    program_headers_address: u64,
-    section_headers_address: u64,
-    flags: u32,
-    header: Header,
-    program_size: u16,
-    program_len: u16,
-    section_size: u16,
-    section_len: u16,
-    section_index: u16,
+    pub magic: [u8; 4],
+    pub class: Class,
+    pub endian: Endian,
+    pub version: u8,
+    pub abi_sysv: u8,
+    pub abi_version: u8,
+    pub kind: u16,
+    pub machine: u16,
+    pub version2: u32,
+    pub entry_address: u64,
+    pub program_headers_address: u64,
+    pub section_headers_address: u64,
+    pub flags: u32,
+    pub header: Header,
+    pub program_size: u16,
+    pub program_len: u16,
+    pub section_size: u16,
+    pub section_len: u16,
+    pub section_index: u16,
 }
 
 impl Elf {
@@ -53,10 +60,9 @@ impl Elf {
             version: 0,
             abi_sysv: 0,
             abi_version: 0,
-            _pad0: [0; 7],
-            kind: [0; 2],
-            machine: [0; 2],
-            version2: [0; 4],
+            kind: 0,
+            machine: 0,
+            version2: 0,
             entry_address: 0,
             program_headers_address: 0,
             section_headers_address: 0,
@@ -105,23 +111,34 @@ impl Elf {
         self
     }
 
+    pub const fn kind_rel(&mut self) -> &mut Elf {
+        self.kind = 1;
+        self
+    }
+
     pub const fn kind_exec(&mut self) -> &mut Elf {
-        self.kind[0] = 2;
-        self.kind[1] = 0;
+        self.kind = 2;
+        self
+    }
+
+    /// A shared object, which is also the kind of position-independent executables.
+    pub const fn kind_dyn(&mut self) -> &mut Elf {
+        self.kind = 3;
+        self
+    }
+
+    pub const fn machine_i386(&mut self) -> &mut Elf {
+        self.machine = 0x03;
         self
     }
 
     pub const fn machine_x86_64(&mut self) -> &mut Elf {
-        self.machine[0] = 0x3E;
-        self.machine[1] = 0x00;
+        self.machine = 0x3E;
         self
     }
 
     pub const fn version2(&mut self) -> &mut Elf {
-        self.version2[0] = 1;
-        self.version2[1] = 0;
-        self.version2[2] = 0;
-        self.version2[3] = 0;
+        self.version2 = 1;
         self
     }
 
@@ -180,7 +197,168 @@ impl Elf {
         self
     }
 
-    pub const fn to_array(self) -> [u8; 64] {
-        unsafe { core::mem::transmute(self) }
+    /// Reads and validates an ELF32 or ELF64 file header.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Elf, Error> {
+        let ident = Reader::new(bytes, Endian::Little, "file header");
+
+        if ident.array(0)? != *b"\x7fELF" {
+            return Err(Error::Magic);
+        }
+
+        let class = match ident.u8(4)? {
+            1 => Class::Class32,
+            2 => Class::Class64,
+            class => return Err(Error::Class(class)),
+        };
+
+        let endian = match ident.u8(5)? {
+            1 => Endian::Little,
+            2 => Endian::Big,
+            endian => return Err(Error::Endian(endian)),
+        };
+
+        let reader = Reader::new(bytes, endian, "file header");
+        let version = reader.u8(6)?;
+
+        if version != 1 {
+            return Err(Error::Version(version as u32));
+        }
+
+        let version2 = reader.u32(20)?;
+
+        if version2 != 1 {
+            return Err(Error::Version(version2));
+        }
+
+        if class == Class::Class32 {
+            return Self::from_reader32(reader, endian, version, version2);
+        }
+
+        let header = match reader.u16(52)? {
+            64 => Header::Header64,
+            size => return Err(Error::Size("file header", size)),
+        };
+
+        Ok(Elf {
+            magic: *b"\x7fELF",
+            class,
+            endian,
+            version,
+            abi_sysv: reader.u8(7)?,
+            abi_version: reader.u8(8)?,
+            kind: reader.u16(16)?,
+            machine: reader.u16(18)?,
+            version2,
+            entry_address: reader.u64(24)?,
+            program_headers_address: reader.u64(32)?,
+            section_headers_address: reader.u64(40)?,
+            flags: reader.u32(48)?,
+            header,
+            program_size: reader.u16(54)?,
+            program_len: reader.u16(56)?,
+            section_size: reader.u16(58)?,
+            section_len: reader.u16(60)?,
+            section_index: reader.u16(62)?,
+        })
+    }
+
+    /// Reads the fields of an ELF32 file header, which are narrower and sit at different
+    /// offsets after the identification bytes.
+    fn from_reader32(
+        reader: Reader,
+        endian: Endian,
+        version: u8,
+        version2: u32,
+    ) -> Result<Elf, Error> {
+        let header = match reader.u16(40)? {
+            52 => Header::Header32,
+            size => return Err(Error::Size("file header", size)),
+        };
+
+        Ok(Elf {
+            magic: *b"\x7fELF",
+            class: Class::Class32,
+            endian,
+            version,
+            abi_sysv: reader.u8(7)?,
+            abi_version: reader.u8(8)?,
+            kind: reader.u16(16)?,
+            machine: reader.u16(18)?,
+            version2,
+            entry_address: reader.u32(24)? as u64,
+            program_headers_address: reader.u32(28)? as u64,
+            section_headers_address: reader.u32(32)? as u64,
+            flags: reader.u32(36)?,
+            header,
+            program_size: reader.u16(42)?,
+            program_len: reader.u16(44)?,
+            section_size: reader.u16(46)?,
+            section_len: reader.u16(48)?,
+            section_index: reader.u16(50)?,
+        })
+    }
+
+    /// Serializes the header for its class in its own byte order.
+    pub fn to_vec(&self) -> Vec<u8> {
+        match self.class {
+            Class::Class32 => self.to_array32().to_vec(),
+            Class::Class64 => self.to_array().to_vec(),
+        }
+    }
+
+    /// Serializes an ELF32 header in its own byte order, addresses are truncated to 32 bits.
+    pub fn to_array32(&self) -> [u8; 52] {
+        let mut bytes = [0; 52];
+
+        Writer::new(&mut bytes, self.endian)
+            .array(0, &self.magic)
+            .u8(4, self.class as u8)
+            .u8(5, self.endian as u8)
+            .u8(6, self.version)
+            .u8(7, self.abi_sysv)
+            .u8(8, self.abi_version)
+            .u16(16, self.kind)
+            .u16(18, self.machine)
+            .u32(20, self.version2)
+            .u32(24, self.entry_address as u32)
+            .u32(28, self.program_headers_address as u32)
+            .u32(32, self.section_headers_address as u32)
+            .u32(36, self.flags)
+            .u16(40, self.header as u16)
+            .u16(42, self.program_size)
+            .u16(44, self.program_len)
+            .u16(46, self.section_size)
+            .u16(48, self.section_len)
+            .u16(50, self.section_index);
+
+        bytes
+    }
+
+    /// Serializes an ELF64 header in its own byte order.
+    pub fn to_array(&self) -> [u8; 64] {
+        let mut bytes = [0; 64];
+
+        Writer::new(&mut bytes, self.endian)
+            .array(0, &self.magic)
+            .u8(4, self.class as u8)
+            .u8(5, self.endian as u8)
+            .u8(6, self.version)
+            .u8(7, self.abi_sysv)
+            .u8(8, self.abi_version)
+            .u16(16, self.kind)
+            .u16(18, self.machine)
+            .u32(20, self.version2)
+            .u64(24, self.entry_address)
+            .u64(32, self.program_headers_address)
+            .u64(40, self.section_headers_address)
+            .u32(48, self.flags)
+            .u16(52, self.header as u16)
+            .u16(54, self.program_size)
+            .u16(56, self.program_len)
+            .u16(58, self.section_size)
+            .u16(60, self.section_len)
+            .u16(62, self.section_index);
+
+        bytes
     }
 }
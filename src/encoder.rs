@@ -0,0 +1,1253 @@
+use super::op::{fits_i32, fits_i8, Opcode, Register};
+
+/// Operand size of an instruction.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Size {
+    Byte = 1,
+    Word = 2,
+    Dword = 4,
+    Qword = 8,
+}
+
+impl Size {
+    pub const fn bytes(&self) -> usize {
+        *self as usize
+    }
+
+    /// Intel `ptr` keyword, such as `qword`.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Size::Byte => "byte",
+            Size::Word => "word",
+            Size::Dword => "dword",
+            Size::Qword => "qword",
+        }
+    }
+
+    /// AT&T mnemonic suffix.
+    pub const fn suffix(&self) -> char {
+        match self {
+            Size::Byte => 'b',
+            Size::Word => 'w',
+            Size::Dword => 'l',
+            Size::Qword => 'q',
+        }
+    }
+}
+
+/// Processor mode an instruction is encoded for.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Mode {
+    /// 64-bit long mode
+    Long,
+    /// 32-bit protected mode, without REX prefixes, rip-relative operands or 64-bit operands
+    Protected,
+}
+
+impl Mode {
+    /// Operand size of push, pop and indirect branches.
+    pub const fn stack(&self) -> Size {
+        match self {
+            Mode::Long => Size::Qword,
+            Mode::Protected => Size::Dword,
+        }
+    }
+}
+
+/// A memory operand, `[base + index * scale + displacement]`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Memory {
+    pub base: Option<Register>,
+    pub index: Option<Register>,
+    /// 1, 2, 4 or 8
+    pub scale: u8,
+    pub displacement: i32,
+    /// relative to the end of the instruction, `base` and `index` are ignored
+    pub rip: bool,
+}
+
+impl Memory {
+    pub const fn base(base: Register) -> Memory {
+        Memory {
+            base: Some(base),
+            index: None,
+            scale: 1,
+            displacement: 0,
+            rip: false,
+        }
+    }
+
+    pub const fn rip(displacement: i32) -> Memory {
+        Memory {
+            base: None,
+            index: None,
+            scale: 1,
+            displacement,
+            rip: true,
+        }
+    }
+
+    pub const fn absolute(displacement: i32) -> Memory {
+        Memory {
+            base: None,
+            index: None,
+            scale: 1,
+            displacement,
+            rip: false,
+        }
+    }
+
+    pub const fn index(mut self, index: Register, scale: u8) -> Memory {
+        self.index = Some(index);
+        self.scale = scale;
+        self
+    }
+
+    pub const fn displacement(mut self, displacement: i32) -> Memory {
+        self.displacement = displacement;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Operand {
+    Register(Register),
+    Memory(Memory),
+    Immediate(i64),
+    /// branch target relative to the end of the instruction
+    Relative(i32),
+}
+
+/// Condition codes, in encoding order.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Condition {
+    o,
+    no,
+    b,
+    ae,
+    e,
+    ne,
+    be,
+    a,
+    s,
+    ns,
+    p,
+    np,
+    l,
+    ge,
+    le,
+    g,
+}
+
+impl Condition {
+    pub const ALL: [Condition; 16] = {
+        use Condition::*;
+
+        [o, no, b, ae, e, ne, be, a, s, ns, p, np, l, ge, le, g]
+    };
+
+    pub const fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    pub const fn from_code(code: u8) -> Condition {
+        Condition::ALL[(code & 0xF) as usize]
+    }
+
+    /// The condition which holds when `self` doesn't.
+    pub const fn negate(&self) -> Condition {
+        Condition::from_code(self.code() ^ 1)
+    }
+
+    pub fn name(&self) -> &'static str {
+        use Condition::*;
+
+        match self {
+            o => "o",
+            no => "no",
+            b => "b",
+            ae => "ae",
+            e => "e",
+            ne => "ne",
+            be => "be",
+            a => "a",
+            s => "s",
+            ns => "ns",
+            p => "p",
+            np => "np",
+            l => "l",
+            ge => "ge",
+            le => "le",
+            g => "g",
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Mnemonic {
+    mov,
+    lea,
+    add,
+    or,
+    and,
+    sub,
+    xor,
+    cmp,
+    test,
+    push,
+    pop,
+    call,
+    ret,
+    jmp,
+    j(Condition),
+    imul,
+    mul,
+    idiv,
+    div,
+    neg,
+    not,
+    shl,
+    shr,
+    sar,
+    /// zero extend from the given source size
+    movzx(Size),
+    /// sign extend from the given source size
+    movsx(Size),
+    set(Condition),
+    /// sign extend rax into rdx (cdq for dword operands)
+    cqo,
+    leave,
+    syscall,
+    int,
+    nop,
+}
+
+impl Mnemonic {
+    /// Opcode extension of the group 1 arithmetic instructions.
+    const fn arithmetic(&self) -> Option<u8> {
+        match self {
+            Mnemonic::add => Some(0),
+            Mnemonic::or => Some(1),
+            Mnemonic::and => Some(4),
+            Mnemonic::sub => Some(5),
+            Mnemonic::xor => Some(6),
+            Mnemonic::cmp => Some(7),
+            _ => None,
+        }
+    }
+
+    /// Opcode extension of the group 2 shift instructions.
+    const fn shift(&self) -> Option<u8> {
+        match self {
+            Mnemonic::shl => Some(4),
+            Mnemonic::shr => Some(5),
+            Mnemonic::sar => Some(7),
+            _ => None,
+        }
+    }
+
+    /// Opcode extension of the group 3 unary instructions.
+    const fn unary(&self) -> Option<u8> {
+        match self {
+            Mnemonic::not => Some(2),
+            Mnemonic::neg => Some(3),
+            Mnemonic::mul => Some(4),
+            Mnemonic::imul => Some(5),
+            Mnemonic::div => Some(6),
+            Mnemonic::idiv => Some(7),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// no encoding exists for this combination of operands
+    Operands,
+    /// the immediate doesn't fit the operand size
+    Immediate,
+    /// the branch target doesn't fit a rel8
+    Relative,
+    /// rsp can't be an index, or the scale isn't 1, 2, 4 or 8
+    Index,
+    /// the operands need a REX prefix, a rip-relative operand or a 64-bit operand size,
+    /// which 32-bit mode doesn't have
+    Mode,
+}
+
+impl Error {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Error::Operands => "invalid combination of operands",
+            Error::Immediate => "immediate is out of range for the operand size",
+            Error::Relative => "branch target is out of range of a short jump",
+            Error::Index => "invalid index register or scale",
+            Error::Mode => "operands aren't available in 32-bit mode",
+        }
+    }
+}
+
+/// An instruction with operands in Intel order, destination first.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Instruction {
+    pub mnemonic: Mnemonic,
+    /// operand size, for branches `Byte` selects rel8 and anything else rel32
+    pub size: Size,
+    pub operands: Vec<Operand>,
+}
+
+/// ModRM reg field.
+#[derive(Clone, Copy, Debug)]
+enum Reg {
+    /// opcode extension
+    Digit(u8),
+    Register(Register),
+}
+
+/// Builds one instruction, prefixes first.
+struct Emitter {
+    bytes: Vec<u8>,
+    size: Size,
+    /// REX.W, unless the instruction defaults to 64-bit operands
+    wide: bool,
+}
+
+impl Emitter {
+    fn new(size: Size) -> Self {
+        Self {
+            bytes: vec![],
+            size,
+            wide: size == Size::Qword,
+        }
+    }
+
+    /// For instructions which default to 64-bit operands, such as push and pop.
+    fn default64(&mut self) {
+        self.wide = false;
+    }
+
+    /// Operand size prefix and REX prefix.
+    fn prefix(&mut self, r: bool, x: bool, b: bool, force: bool) {
+        if self.size == Size::Word {
+            self.bytes.push(0x66);
+        }
+
+        let rex = 0x40 | ((self.wide as u8) << 3) | ((r as u8) << 2) | ((x as u8) << 1) | b as u8;
+
+        if rex != 0x40 || force {
+            self.bytes.push(rex);
+        }
+    }
+
+    /// Whether a byte sized register needs an empty REX to select spl, bpl, sil or dil.
+    fn needs_rex(register: Register, byte: bool) -> bool {
+        byte && (4..8).contains(&register.index())
+    }
+
+    /// Opcode with the register in the low 3 bits, such as push and pop.
+    fn plus_register(&mut self, opcode: u8, register: Register) {
+        let force = Self::needs_rex(register, self.size == Size::Byte);
+
+        self.prefix(false, false, register.is_extended(), force);
+        self.bytes.push(opcode | register.low());
+    }
+
+    /// Opcode followed by ModRM, SIB and displacement. `rm_byte` says whether a register r/m
+    /// operand is byte sized, which differs from the operand size for movzx and movsx.
+    fn modrm(&mut self, opcode: &[u8], reg: Reg, rm: &Operand, rm_byte: bool) -> Result<(), Error> {
+        let byte = self.size == Size::Byte;
+        let (field, r, mut force) = match reg {
+            Reg::Digit(digit) => (digit, false, false),
+            Reg::Register(register) => (
+                register.low(),
+                register.is_extended(),
+                Self::needs_rex(register, byte),
+            ),
+        };
+
+        match *rm {
+            Operand::Register(register) => {
+                force |= Self::needs_rex(register, rm_byte);
+
+                self.prefix(r, false, register.is_extended(), force);
+                self.bytes.extend(opcode);
+                self.bytes.push(0xC0 | (field << 3) | register.low());
+            }
+            Operand::Memory(memory) if memory.rip => {
+                self.prefix(r, false, false, force);
+                self.bytes.extend(opcode);
+                self.bytes.push((field << 3) | 0b101);
+                self.bytes.extend(memory.displacement.to_le_bytes());
+            }
+            Operand::Memory(memory) => {
+                if !matches!(memory.scale, 1 | 2 | 4 | 8) || memory.index == Some(Register::rsp) {
+                    return Err(Error::Index);
+                }
+
+                let scale = memory.scale.trailing_zeros() as u8;
+                let x = memory
+                    .index
+                    .map(|index| index.is_extended())
+                    .unwrap_or(false);
+                let b = memory.base.map(|base| base.is_extended()).unwrap_or(false);
+
+                self.prefix(r, x, b, force);
+                self.bytes.extend(opcode);
+
+                let base = match memory.base {
+                    Some(base) => base,
+                    None => {
+                        // no base, disp32 with SIB base 101
+                        let index = memory.index.map(|index| index.low()).unwrap_or(0b100);
+
+                        self.bytes.push((field << 3) | 0b100);
+                        self.bytes.push((scale << 6) | (index << 3) | 0b101);
+                        self.bytes.extend(memory.displacement.to_le_bytes());
+
+                        return Ok(());
+                    }
+                };
+
+                // rbp and r13 have no displacement-less form
+                let mode = if memory.displacement == 0 && base.low() != 0b101 {
+                    0b00
+                } else if fits_i8(memory.displacement as i64) {
+                    0b01
+                } else {
+                    0b10
+                };
+
+                // rsp and r12 as a base always need a SIB
+                if memory.index.is_some() || base.low() == 0b100 {
+                    let index = memory.index.map(|index| index.low()).unwrap_or(0b100);
+
+                    self.bytes.push((mode << 6) | (field << 3) | 0b100);
+                    self.bytes.push((scale << 6) | (index << 3) | base.low());
+                } else {
+                    self.bytes.push((mode << 6) | (field << 3) | base.low());
+                }
+
+                match mode {
+                    0b01 => self.bytes.push(memory.displacement as u8),
+                    0b10 => self.bytes.extend(memory.displacement.to_le_bytes()),
+                    _ => {}
+                }
+            }
+            _ => return Err(Error::Operands),
+        }
+
+        Ok(())
+    }
+
+    /// Immediate sized for the operand size, 64-bit operands take a sign extended imm32.
+    fn immediate(&mut self, immediate: i64) -> Result<(), Error> {
+        match self.size {
+            Size::Byte if (-0x80..=0xFF).contains(&immediate) => self.bytes.push(immediate as u8),
+            Size::Word if (-0x8000..=0xFFFF).contains(&immediate) => {
+                self.bytes.extend((immediate as u16).to_le_bytes())
+            }
+            Size::Dword if (-0x8000_0000..=0xFFFF_FFFF).contains(&immediate) => {
+                self.bytes.extend((immediate as u32).to_le_bytes())
+            }
+            Size::Qword if fits_i32(immediate) => {
+                self.bytes.extend((immediate as i32).to_le_bytes())
+            }
+            _ => return Err(Error::Immediate),
+        }
+
+        Ok(())
+    }
+
+    /// Byte or full sized opcode, such as 0x88 and 0x89 for mov.
+    fn sized(&self, opcode: u8) -> u8 {
+        if self.size == Size::Byte {
+            opcode
+        } else {
+            opcode + 1
+        }
+    }
+
+    fn finish(self) -> Opcode {
+        let mut opcode = Opcode::new();
+
+        for byte in self.bytes {
+            opcode.push(byte);
+        }
+
+        opcode
+    }
+}
+
+impl Operand {
+    /// AT&T syntax, `len` is the length of the instruction for branch targets and `address`
+    /// the size of the registers in memory operands.
+    fn display(&self, size: Size, len: usize, address: Size) -> String {
+        match *self {
+            Operand::Register(register) => format!("%{}", register.sized_name(size)),
+            Operand::Memory(memory) => memory.display(address),
+            Operand::Immediate(immediate) => format!("\x1b[38;5;11m${immediate}\x1b[m"),
+            Operand::Relative(relative) => {
+                format!("\x1b[38;5;11m.{:+}\x1b[m", relative as i64 + len as i64)
+            }
+        }
+    }
+}
+
+impl Operand {
+    /// Intel syntax, memory operands are prefixed with their size unless `ptr` is false.
+    fn display_intel(&self, size: Size, len: usize, address: Size, ptr: bool) -> String {
+        match *self {
+            Operand::Register(register) => register.sized_name(size).to_string(),
+            Operand::Memory(memory) if ptr => {
+                format!("{} ptr {}", size.name(), memory.display_intel(address))
+            }
+            Operand::Memory(memory) => memory.display_intel(address),
+            Operand::Immediate(immediate) => format!("\x1b[38;5;11m{immediate}\x1b[m"),
+            Operand::Relative(relative) => {
+                format!("\x1b[38;5;11m.{:+}\x1b[m", relative as i64 + len as i64)
+            }
+        }
+    }
+}
+
+impl Memory {
+    /// AT&T syntax, with base and index registers of the `address` size.
+    fn display(&self, address: Size) -> String {
+        let displacement = if self.displacement != 0 || (self.base.is_none() && !self.rip) {
+            format!("\x1b[38;5;11m{}\x1b[m", self.displacement)
+        } else {
+            String::new()
+        };
+
+        if self.rip {
+            return format!("{displacement}(%rip)");
+        }
+
+        match (self.base, self.index) {
+            (Some(base), Some(index)) => {
+                format!(
+                    "{displacement}(%{},%{},{})",
+                    base.sized_name(address),
+                    index.sized_name(address),
+                    self.scale
+                )
+            }
+            (Some(base), None) => format!("{displacement}(%{})", base.sized_name(address)),
+            (None, Some(index)) => format!(
+                "{displacement}(,%{},{})",
+                index.sized_name(address),
+                self.scale
+            ),
+            (None, None) => displacement,
+        }
+    }
+
+    /// Intel syntax, with base and index registers of the `address` size.
+    fn display_intel(&self, address: Size) -> String {
+        let mut terms = vec![];
+
+        if self.rip {
+            terms.push("rip".to_string());
+        }
+
+        if let Some(base) = self.base {
+            terms.push(base.sized_name(address).to_string());
+        }
+
+        if let Some(index) = self.index {
+            terms.push(format!("{}*{}", index.sized_name(address), self.scale));
+        }
+
+        let mut output = terms.join(" + ");
+
+        if terms.is_empty() {
+            output = format!("\x1b[38;5;11m{}\x1b[m", self.displacement);
+        } else if self.displacement != 0 {
+            let sign = if self.displacement < 0 { '-' } else { '+' };
+
+            output = format!(
+                "{output} {sign} \x1b[38;5;11m{}\x1b[m",
+                self.displacement.unsigned_abs()
+            );
+        }
+
+        format!("[{output}]")
+    }
+}
+
+impl Instruction {
+    pub fn new(mnemonic: Mnemonic, size: Size, operands: &[Operand]) -> Instruction {
+        Instruction {
+            mnemonic,
+            size,
+            operands: operands.to_vec(),
+        }
+    }
+
+    /// Encodes for 64-bit mode.
+    pub fn encode(&self) -> Result<Opcode, Error> {
+        self.encode_mode(Mode::Long)
+    }
+
+    /// Whether every operand exists in 32-bit mode. Without a REX prefix byte registers 4 to 7
+    /// are ah, ch, dh and bh, so spl, bpl, sil and dil are rejected too.
+    fn check_protected(&self) -> Result<(), Error> {
+        use Mnemonic::{movsx, movzx};
+
+        if self.size == Size::Qword {
+            return Err(Error::Mode);
+        }
+
+        for (index, operand) in self.operands.iter().enumerate() {
+            let byte = match (self.mnemonic, index) {
+                (movzx(from) | movsx(from), 1) => from == Size::Byte,
+                _ => self.size == Size::Byte,
+            };
+
+            let valid = match *operand {
+                Operand::Register(register) => {
+                    !register.is_extended() && !Emitter::needs_rex(register, byte)
+                }
+                Operand::Memory(memory) => {
+                    !memory.rip
+                        && memory
+                            .base
+                            .iter()
+                            .chain(&memory.index)
+                            .all(|register| !register.is_extended())
+                }
+                _ => true,
+            };
+
+            if !valid {
+                return Err(Error::Mode);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn encode_mode(&self, mode: Mode) -> Result<Opcode, Error> {
+        use Mnemonic::*;
+        use Operand::{Immediate, Memory, Register, Relative};
+
+        if mode == Mode::Protected {
+            self.check_protected()?;
+        }
+
+        let size = self.size;
+        let stack = mode.stack();
+        let byte = size == Size::Byte;
+        let mut e = Emitter::new(size);
+
+        match (self.mnemonic, self.operands.as_slice()) {
+            (mov, [Register(dst), Immediate(imm)]) if size == Size::Qword && !fits_i32(*imm) => {
+                e.plus_register(0xB8, *dst);
+                e.bytes.extend(imm.to_le_bytes());
+            }
+            (mov, [Register(dst), Immediate(imm)]) if size != Size::Qword => {
+                e.plus_register(if byte { 0xB0 } else { 0xB8 }, *dst);
+                e.immediate(*imm)?;
+            }
+            (mov, [dst @ (Register(_) | Memory(_)), Immediate(imm)]) => {
+                e.modrm(&[e.sized(0xC6)], Reg::Digit(0), dst, byte)?;
+                e.immediate(*imm)?;
+            }
+            (mov, [dst @ (Register(_) | Memory(_)), Register(src)]) => {
+                e.modrm(&[e.sized(0x88)], Reg::Register(*src), dst, byte)?;
+            }
+            (mov, [Register(dst), src @ Memory(_)]) => {
+                e.modrm(&[e.sized(0x8A)], Reg::Register(*dst), src, byte)?;
+            }
+            (lea, [Register(dst), src @ Memory(_)]) if !byte => {
+                e.modrm(&[0x8D], Reg::Register(*dst), src, false)?;
+            }
+            (mnemonic, [dst @ (Register(_) | Memory(_)), Immediate(imm)])
+                if mnemonic.arithmetic().is_some() =>
+            {
+                let digit = Reg::Digit(mnemonic.arithmetic().unwrap());
+
+                if !byte && fits_i8(*imm) {
+                    e.modrm(&[0x83], digit, dst, false)?;
+                    e.bytes.push(*imm as u8);
+                } else {
+                    e.modrm(&[e.sized(0x80)], digit, dst, byte)?;
+                    e.immediate(*imm)?;
+                }
+            }
+            (mnemonic, [dst @ (Register(_) | Memory(_)), Register(src)])
+                if mnemonic.arithmetic().is_some() =>
+            {
+                let opcode = e.sized(mnemonic.arithmetic().unwrap() << 3);
+
+                e.modrm(&[opcode], Reg::Register(*src), dst, byte)?;
+            }
+            (mnemonic, [Register(dst), src @ Memory(_)]) if mnemonic.arithmetic().is_some() => {
+                let opcode = e.sized((mnemonic.arithmetic().unwrap() << 3) | 2);
+
+                e.modrm(&[opcode], Reg::Register(*dst), src, byte)?;
+            }
+            (test, [dst @ (Register(_) | Memory(_)), Immediate(imm)]) => {
+                e.modrm(&[e.sized(0xF6)], Reg::Digit(0), dst, byte)?;
+                e.immediate(*imm)?;
+            }
+            (test, [dst @ (Register(_) | Memory(_)), Register(src)]) => {
+                e.modrm(&[e.sized(0x84)], Reg::Register(*src), dst, byte)?;
+            }
+            (push, [Register(register)]) if size == stack => {
+                e.default64();
+                e.plus_register(0x50, *register);
+            }
+            (push, [src @ Memory(_)]) if size == stack => {
+                e.default64();
+                e.modrm(&[0xFF], Reg::Digit(6), src, false)?;
+            }
+            (push, [Immediate(imm)]) if size == stack && fits_i8(*imm) => {
+                e.bytes.extend([0x6A, *imm as u8]);
+            }
+            (push, [Immediate(imm)]) if size == stack && fits_i32(*imm) => {
+                e.bytes.push(0x68);
+                e.bytes.extend((*imm as i32).to_le_bytes());
+            }
+            (push, [Immediate(_)]) => return Err(Error::Immediate),
+            (pop, [Register(register)]) if size == stack => {
+                e.default64();
+                e.plus_register(0x58, *register);
+            }
+            (pop, [dst @ Memory(_)]) if size == stack => {
+                e.default64();
+                e.modrm(&[0x8F], Reg::Digit(0), dst, false)?;
+            }
+            (call, [Relative(relative)]) => {
+                e.bytes.push(0xE8);
+                e.bytes.extend(relative.to_le_bytes());
+            }
+            (call, [target @ (Register(_) | Memory(_))]) if size == stack => {
+                e.default64();
+                e.modrm(&[0xFF], Reg::Digit(2), target, false)?;
+            }
+            (jmp, [Relative(relative)]) if byte => {
+                if !fits_i8(*relative as i64) {
+                    return Err(Error::Relative);
+                }
+
+                e.bytes.extend([0xEB, *relative as u8]);
+            }
+            (jmp, [Relative(relative)]) => {
+                e.bytes.push(0xE9);
+                e.bytes.extend(relative.to_le_bytes());
+            }
+            (jmp, [target @ (Register(_) | Memory(_))]) if size == stack => {
+                e.default64();
+                e.modrm(&[0xFF], Reg::Digit(4), target, false)?;
+            }
+            (j(condition), [Relative(relative)]) if byte => {
+                if !fits_i8(*relative as i64) {
+                    return Err(Error::Relative);
+                }
+
+                e.bytes.extend([0x70 | condition.code(), *relative as u8]);
+            }
+            (j(condition), [Relative(relative)]) => {
+                e.bytes.extend([0x0F, 0x80 | condition.code()]);
+                e.bytes.extend(relative.to_le_bytes());
+            }
+            (ret, []) => e.bytes.push(0xC3),
+            (imul, [Register(dst), src @ (Register(_) | Memory(_))]) if !byte => {
+                e.modrm(&[0x0F, 0xAF], Reg::Register(*dst), src, false)?;
+            }
+            (imul, [Register(dst), src @ (Register(_) | Memory(_)), Immediate(imm)]) if !byte => {
+                if fits_i8(*imm) {
+                    e.modrm(&[0x6B], Reg::Register(*dst), src, false)?;
+                    e.bytes.push(*imm as u8);
+                } else {
+                    e.modrm(&[0x69], Reg::Register(*dst), src, false)?;
+                    e.immediate(*imm)?;
+                }
+            }
+            (mnemonic, [dst @ (Register(_) | Memory(_))]) if mnemonic.unary().is_some() => {
+                let digit = Reg::Digit(mnemonic.unary().unwrap());
+
+                e.modrm(&[e.sized(0xF6)], digit, dst, byte)?;
+            }
+            (mnemonic, [dst @ (Register(_) | Memory(_)), count]) if mnemonic.shift().is_some() => {
+                let digit = Reg::Digit(mnemonic.shift().unwrap());
+
+                match count {
+                    Immediate(1) => e.modrm(&[e.sized(0xD0)], digit, dst, byte)?,
+                    Immediate(imm) if (0..=0xFF).contains(imm) => {
+                        e.modrm(&[e.sized(0xC0)], digit, dst, byte)?;
+                        e.bytes.push(*imm as u8);
+                    }
+                    Register(super::op::Register::rcx) => {
+                        e.modrm(&[e.sized(0xD2)], digit, dst, byte)?
+                    }
+                    Immediate(_) => return Err(Error::Immediate),
+                    _ => return Err(Error::Operands),
+                }
+            }
+            (movzx(from), [Register(dst), src @ (Register(_) | Memory(_))])
+                if from < size && from <= Size::Word =>
+            {
+                let opcode = if from == Size::Byte { 0xB6 } else { 0xB7 };
+
+                e.modrm(
+                    &[0x0F, opcode],
+                    Reg::Register(*dst),
+                    src,
+                    from == Size::Byte,
+                )?;
+            }
+            (movsx(Size::Dword), [Register(dst), src @ (Register(_) | Memory(_))])
+                if size == Size::Qword =>
+            {
+                e.modrm(&[0x63], Reg::Register(*dst), src, false)?;
+            }
+            (movsx(from), [Register(dst), src @ (Register(_) | Memory(_))])
+                if from < size && from <= Size::Word =>
+            {
+                let opcode = if from == Size::Byte { 0xBE } else { 0xBF };
+
+                e.modrm(
+                    &[0x0F, opcode],
+                    Reg::Register(*dst),
+                    src,
+                    from == Size::Byte,
+                )?;
+            }
+            (set(condition), [dst @ (Register(_) | Memory(_))]) if byte => {
+                e.modrm(&[0x0F, 0x90 | condition.code()], Reg::Digit(0), dst, true)?;
+            }
+            (cqo, []) if size >= Size::Word => {
+                e.prefix(false, false, false, false);
+                e.bytes.push(0x99);
+            }
+            (leave, []) => e.bytes.push(0xC9),
+            (syscall, []) => e.bytes.extend([0x0F, 0x05]),
+            (int, [Immediate(imm)]) if (0..=0xFF).contains(imm) => {
+                e.bytes.extend([0xCD, *imm as u8])
+            }
+            (nop, []) => e.bytes.push(0x90),
+            _ => return Err(Error::Operands),
+        }
+
+        Ok(e.finish())
+    }
+}
+
+impl Instruction {
+    /// AT&T syntax for 64-bit mode, such as `movq $1, %rax`.
+    pub fn display(&self) -> String {
+        self.display_mode(Mode::Long)
+    }
+
+    /// AT&T syntax, with the registers of memory operands and branch targets sized for `mode`.
+    pub fn display_mode(&self, mode: Mode) -> String {
+        use Mnemonic::*;
+
+        let size = self.size;
+        let len = self
+            .encode_mode(mode)
+            .map(|opcode| opcode.len())
+            .unwrap_or(0);
+        let suffix = size.suffix();
+        let mnemonic = match self.mnemonic {
+            mov if matches!(self.operands.as_slice(), [Operand::Register(_), Operand::Immediate(immediate)] if size == Size::Qword && !fits_i32(*immediate)) => {
+                "movabsq".to_string()
+            }
+            mov => format!("mov{suffix}"),
+            lea => format!("lea{suffix}"),
+            add => format!("add{suffix}"),
+            or => format!("or{suffix}"),
+            and => format!("and{suffix}"),
+            sub => format!("sub{suffix}"),
+            xor => format!("xor{suffix}"),
+            cmp => format!("cmp{suffix}"),
+            test => format!("test{suffix}"),
+            push => format!("push{suffix}"),
+            pop => format!("pop{suffix}"),
+            call => "call".to_string(),
+            ret => "ret".to_string(),
+            jmp => "jmp".to_string(),
+            j(condition) => format!("j{}", condition.name()),
+            imul => format!("imul{suffix}"),
+            mul => format!("mul{suffix}"),
+            idiv => format!("idiv{suffix}"),
+            div => format!("div{suffix}"),
+            neg => format!("neg{suffix}"),
+            not => format!("not{suffix}"),
+            shl => format!("shl{suffix}"),
+            shr => format!("shr{suffix}"),
+            sar => format!("sar{suffix}"),
+            movzx(from) => format!("movz{}{suffix}", from.suffix()),
+            movsx(from) => format!("movs{}{suffix}", from.suffix()),
+            set(condition) => format!("set{}", condition.name()),
+            cqo if size == Size::Qword => "cqto".to_string(),
+            cqo => "cltd".to_string(),
+            leave => "leave".to_string(),
+            syscall => "syscall".to_string(),
+            int => "int".to_string(),
+            nop => "nop".to_string(),
+        };
+
+        let operands: Vec<String> = self
+            .operands
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, operand)| {
+                // sources of movzx and movsx, and shift counts, have their own size
+                let size = match (self.mnemonic, index) {
+                    (movzx(from) | movsx(from), 1) => from,
+                    (shl | shr | sar, 1) => Size::Byte,
+                    (call | jmp, _) => mode.stack(),
+                    _ => size,
+                };
+
+                operand.display(size, len, mode.stack())
+            })
+            .collect();
+
+        if operands.is_empty() {
+            mnemonic
+        } else if matches!(self.mnemonic, call | jmp)
+            && matches!(self.operands[0], Operand::Register(_) | Operand::Memory(_))
+        {
+            format!("{mnemonic} *{}", operands.join(", "))
+        } else {
+            format!("{mnemonic} {}", operands.join(", "))
+        }
+    }
+}
+
+/// Assembly syntax of listings and disassembly.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Syntax {
+    Att,
+    Intel,
+}
+
+impl Instruction {
+    pub fn display_syntax(&self, syntax: Syntax, mode: Mode) -> String {
+        match syntax {
+            Syntax::Att => self.display_mode(mode),
+            Syntax::Intel => self.display_intel_mode(mode),
+        }
+    }
+
+    /// Intel syntax for 64-bit mode, such as `mov rax, 1`.
+    pub fn display_intel(&self) -> String {
+        self.display_intel_mode(Mode::Long)
+    }
+
+    /// Intel syntax, with the registers of memory operands and branch targets sized for
+    /// `mode`.
+    pub fn display_intel_mode(&self, mode: Mode) -> String {
+        use Mnemonic::*;
+
+        let size = self.size;
+        let len = self
+            .encode_mode(mode)
+            .map(|opcode| opcode.len())
+            .unwrap_or(0);
+        let mnemonic = match self.mnemonic {
+            mov if matches!(self.operands.as_slice(), [Operand::Register(_), Operand::Immediate(immediate)] if size == Size::Qword && !fits_i32(*immediate)) => {
+                "movabs".to_string()
+            }
+            mov => "mov".to_string(),
+            lea => "lea".to_string(),
+            add => "add".to_string(),
+            or => "or".to_string(),
+            and => "and".to_string(),
+            sub => "sub".to_string(),
+            xor => "xor".to_string(),
+            cmp => "cmp".to_string(),
+            test => "test".to_string(),
+            push => "push".to_string(),
+            pop => "pop".to_string(),
+            call => "call".to_string(),
+            ret => "ret".to_string(),
+            jmp => "jmp".to_string(),
+            j(condition) => format!("j{}", condition.name()),
+            imul => "imul".to_string(),
+            mul => "mul".to_string(),
+            idiv => "idiv".to_string(),
+            div => "div".to_string(),
+            neg => "neg".to_string(),
+            not => "not".to_string(),
+            shl => "shl".to_string(),
+            shr => "shr".to_string(),
+            sar => "sar".to_string(),
+            movzx(_) => "movzx".to_string(),
+            movsx(Size::Dword) => "movsxd".to_string(),
+            movsx(_) => "movsx".to_string(),
+            set(condition) => format!("set{}", condition.name()),
+            cqo if size == Size::Qword => "cqo".to_string(),
+            cqo => "cdq".to_string(),
+            leave => "leave".to_string(),
+            syscall => "syscall".to_string(),
+            int => "int".to_string(),
+            nop => "nop".to_string(),
+        };
+
+        self.display_intel_with(&mnemonic, len, mode)
+    }
+
+    fn display_intel_with(&self, mnemonic: &str, len: usize, mode: Mode) -> String {
+        use Mnemonic::*;
+
+        // the size is implied by a register operand, except for movzx and movsx
+        let implied = !matches!(self.mnemonic, movzx(_) | movsx(_))
+            && self
+                .operands
+                .iter()
+                .any(|operand| matches!(operand, Operand::Register(_)));
+
+        let operands: Vec<String> = self
+            .operands
+            .iter()
+            .enumerate()
+            .map(|(index, operand)| {
+                let size = match (self.mnemonic, index) {
+                    (movzx(from) | movsx(from), 1) => from,
+                    (shl | shr | sar, 1) => Size::Byte,
+                    (call | jmp, _) => mode.stack(),
+                    _ => self.size,
+                };
+
+                operand.display_intel(size, len, mode.stack(), !implied && self.mnemonic != lea)
+            })
+            .collect();
+
+        if operands.is_empty() {
+            mnemonic.to_string()
+        } else {
+            format!("{mnemonic} {}", operands.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Operand::{Immediate, Memory as Mem, Register as Reg};
+    use super::*;
+    use Register::*;
+
+    fn encode(mnemonic: Mnemonic, size: Size, operands: &[Operand]) -> Vec<u8> {
+        encode_mode(Mode::Long, mnemonic, size, operands).unwrap()
+    }
+
+    fn encode_mode(
+        mode: Mode,
+        mnemonic: Mnemonic,
+        size: Size,
+        operands: &[Operand],
+    ) -> Result<Vec<u8>, Error> {
+        Instruction::new(mnemonic, size, operands)
+            .encode_mode(mode)
+            .map(|opcode| opcode.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn r12_base_needs_sib() {
+        let bytes = encode(
+            Mnemonic::mov,
+            Size::Qword,
+            &[Reg(rax), Mem(Memory::base(r12))],
+        );
+
+        assert_eq!(bytes, [0x49, 0x8B, 0x04, 0x24]);
+    }
+
+    #[test]
+    fn rsp_base_needs_sib() {
+        let memory = Memory::base(rsp).displacement(8);
+        let bytes = encode(Mnemonic::mov, Size::Qword, &[Reg(rax), Mem(memory)]);
+
+        assert_eq!(bytes, [0x48, 0x8B, 0x44, 0x24, 0x08]);
+    }
+
+    #[test]
+    fn r13_and_rbp_base_need_disp8() {
+        let extended = encode(
+            Mnemonic::mov,
+            Size::Qword,
+            &[Reg(rax), Mem(Memory::base(r13))],
+        );
+        let frame = encode(
+            Mnemonic::mov,
+            Size::Qword,
+            &[Reg(rax), Mem(Memory::base(rbp))],
+        );
+
+        assert_eq!(extended, [0x49, 0x8B, 0x45, 0x00]);
+        assert_eq!(frame, [0x48, 0x8B, 0x45, 0x00]);
+    }
+
+    #[test]
+    fn extended_base_and_index() {
+        let memory = Memory::base(r12).index(r13, 8).displacement(0x100);
+        let bytes = encode(Mnemonic::mov, Size::Qword, &[Reg(rax), Mem(memory)]);
+
+        assert_eq!(bytes, [0x4B, 0x8B, 0x84, 0xEC, 0x00, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn displacement_past_i8_is_disp32() {
+        let memory = Memory::base(rbx).displacement(0x80);
+        let bytes = encode(Mnemonic::mov, Size::Qword, &[Reg(rax), Mem(memory)]);
+
+        assert_eq!(bytes, [0x48, 0x8B, 0x83, 0x80, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn index_without_base() {
+        let memory = Memory::absolute(16).index(rbx, 2);
+        let bytes = encode(Mnemonic::mov, Size::Dword, &[Reg(rax), Mem(memory)]);
+
+        assert_eq!(bytes, [0x8B, 0x04, 0x5D, 0x10, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn rip_relative() {
+        let bytes = encode(Mnemonic::lea, Size::Qword, &[Reg(rax), Mem(Memory::rip(0))]);
+
+        assert_eq!(bytes, [0x48, 0x8D, 0x05, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn rsp_index_is_rejected() {
+        let memory = Memory::base(rax).index(rsp, 1);
+        let result = encode_mode(
+            Mode::Long,
+            Mnemonic::mov,
+            Size::Qword,
+            &[Reg(rax), Mem(memory)],
+        );
+
+        assert_eq!(result, Err(Error::Index));
+    }
+
+    #[test]
+    fn invalid_scale_is_rejected() {
+        let memory = Memory::base(rax).index(rbx, 3);
+        let result = encode_mode(
+            Mode::Long,
+            Mnemonic::mov,
+            Size::Qword,
+            &[Reg(rax), Mem(memory)],
+        );
+
+        assert_eq!(result, Err(Error::Index));
+    }
+
+    #[test]
+    fn byte_registers_needing_rex() {
+        let spl = encode(Mnemonic::mov, Size::Byte, &[Reg(rsp), Immediate(1)]);
+        let sil = encode(
+            Mnemonic::mov,
+            Size::Byte,
+            &[Mem(Memory::base(rax)), Reg(rsi)],
+        );
+        let movzx = encode(
+            Mnemonic::movzx(Size::Byte),
+            Size::Dword,
+            &[Reg(rax), Reg(rsi)],
+        );
+
+        assert_eq!(spl, [0x40, 0xB4, 0x01]);
+        assert_eq!(sil, [0x40, 0x88, 0x30]);
+        assert_eq!(movzx, [0x40, 0x0F, 0xB6, 0xC6]);
+    }
+
+    #[test]
+    fn byte_registers_without_rex() {
+        let al = encode(Mnemonic::mov, Size::Byte, &[Reg(rax), Immediate(1)]);
+        // the destination of movzx is full sized, so esi doesn't need one
+        let movzx = encode(
+            Mnemonic::movzx(Size::Byte),
+            Size::Dword,
+            &[Reg(rsi), Reg(rax)],
+        );
+
+        assert_eq!(al, [0xB0, 0x01]);
+        assert_eq!(movzx, [0x0F, 0xB6, 0xF0]);
+    }
+
+    #[test]
+    fn extended_register_needs_rex_b() {
+        let bytes = encode(Mnemonic::push, Size::Qword, &[Reg(r8)]);
+
+        assert_eq!(bytes, [0x41, 0x50]);
+    }
+
+    #[test]
+    fn arithmetic_imm8_and_imm32() {
+        let imm8 = encode(Mnemonic::add, Size::Qword, &[Reg(rax), Immediate(8)]);
+        let negative = encode(Mnemonic::add, Size::Qword, &[Reg(rax), Immediate(-128)]);
+        let imm32 = encode(Mnemonic::add, Size::Qword, &[Reg(rax), Immediate(0x80)]);
+        let byte = encode(Mnemonic::add, Size::Byte, &[Reg(rax), Immediate(0x80)]);
+
+        assert_eq!(imm8, [0x48, 0x83, 0xC0, 0x08]);
+        assert_eq!(negative, [0x48, 0x83, 0xC0, 0x80]);
+        assert_eq!(imm32, [0x48, 0x81, 0xC0, 0x80, 0x00, 0x00, 0x00]);
+        assert_eq!(byte, [0x80, 0xC0, 0x80]);
+    }
+
+    #[test]
+    fn push_imm8_and_imm32() {
+        let imm8 = encode(Mnemonic::push, Size::Qword, &[Immediate(1)]);
+        let imm32 = encode(Mnemonic::push, Size::Qword, &[Immediate(0x100)]);
+
+        assert_eq!(imm8, [0x6A, 0x01]);
+        assert_eq!(imm32, [0x68, 0x00, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn mov_imm32_and_imm64() {
+        let negative = encode(Mnemonic::mov, Size::Qword, &[Reg(rax), Immediate(-1)]);
+        let imm64 = encode(Mnemonic::mov, Size::Qword, &[Reg(rax), Immediate(1 << 32)]);
+
+        assert_eq!(negative, [0x48, 0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(imm64, [0x48, 0xB8, 0, 0, 0, 0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn immediate_out_of_range() {
+        let operands = [Reg(rax), Immediate(1 << 32)];
+        let result = encode_mode(Mode::Long, Mnemonic::add, Size::Qword, &operands);
+
+        assert_eq!(result, Err(Error::Immediate));
+    }
+
+    #[test]
+    fn protected_mode() {
+        let memory = Memory::base(rbp).displacement(-4);
+        let bytes = encode_mode(
+            Mode::Protected,
+            Mnemonic::mov,
+            Size::Dword,
+            &[Reg(rax), Mem(memory)],
+        );
+        let qword = encode_mode(
+            Mode::Protected,
+            Mnemonic::mov,
+            Size::Qword,
+            &[Reg(rax), Reg(rbx)],
+        );
+        let sil = encode_mode(
+            Mode::Protected,
+            Mnemonic::mov,
+            Size::Byte,
+            &[Reg(rsi), Immediate(1)],
+        );
+        let extended = encode_mode(Mode::Protected, Mnemonic::push, Size::Dword, &[Reg(r8)]);
+
+        assert_eq!(bytes, Ok(vec![0x8B, 0x45, 0xFC]));
+        assert_eq!(qword, Err(Error::Mode));
+        assert_eq!(sil, Err(Error::Mode));
+        assert_eq!(extended, Err(Error::Mode));
+    }
+}
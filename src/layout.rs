@@ -0,0 +1,1341 @@
+use super::elf::{self, Class, Elf, Endian};
+use super::relocation::{self, Kind};
+use super::writer::Writer;
+use super::{program, section, symbol};
+
+#[inline]
+pub const fn align_up(value: u64, align: u64) -> u64 {
+    if align <= 1 {
+        value
+    } else {
+        value.div_ceil(align) * align
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Section {
+    /// name
+    name: String,
+    /// kind
+    kind: u32,
+    /// flags
+    flags: u64,
+    /// align
+    align: u64,
+    /// link to another section
+    link: u32,
+    /// additional information
+    info: u32,
+    /// entry size (if this section holds a table)
+    entry_len: u64,
+    /// contents
+    data: Vec<u8>,
+    /// size in memory of a section without contents
+    nobits: u64,
+    /// computed virtual address
+    address: u64,
+    /// computed file offset
+    offset: u64,
+}
+
+impl Section {
+    pub fn new(name: &str) -> Section {
+        Section {
+            name: name.to_string(),
+            kind: section::KIND_PROGBITS,
+            flags: 0,
+            align: 1,
+            link: 0,
+            info: 0,
+            entry_len: 0,
+            data: vec![],
+            nobits: 0,
+            address: 0,
+            offset: 0,
+        }
+    }
+
+    pub fn kind(&mut self, kind: u32) -> &mut Section {
+        self.kind = kind;
+        self
+    }
+
+    pub fn flags(&mut self, flags: u64) -> &mut Section {
+        self.flags = flags;
+        self
+    }
+
+    pub fn align(&mut self, align: u64) -> &mut Section {
+        self.align = align;
+        self
+    }
+
+    pub fn link(&mut self, link: u32) -> &mut Section {
+        self.link = link;
+        self
+    }
+
+    pub fn info(&mut self, info: u32) -> &mut Section {
+        self.info = info;
+        self
+    }
+
+    pub fn entry_len(&mut self, len: u64) -> &mut Section {
+        self.entry_len = len;
+        self
+    }
+
+    pub fn data(&mut self, data: Vec<u8>) -> &mut Section {
+        self.data = data;
+        self
+    }
+
+    /// Makes this a section without contents which is zero-filled in memory, like `.bss`.
+    pub fn nobits(&mut self, len: u64) -> &mut Section {
+        self.kind = section::KIND_NOBITS;
+        self.data = vec![];
+        self.nobits = len;
+        self
+    }
+
+    /// Size in memory.
+    pub fn len(&self) -> u64 {
+        self.file_len() + self.nobits
+    }
+
+    /// Whether it takes no space in memory.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Size in the file.
+    pub fn file_len(&self) -> u64 {
+        self.data.len() as u64
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Segment {
+    /// kind
+    kind: u32,
+    /// flags
+    flags: u32,
+    /// align
+    align: u64,
+    /// sections covered by this segment, in file order
+    sections: Vec<usize>,
+}
+
+impl Segment {
+    pub fn new(kind: u32) -> Segment {
+        Segment {
+            kind,
+            flags: 0,
+            align: 0x1000,
+            sections: vec![],
+        }
+    }
+
+    pub fn flags(&mut self, flags: u32) -> &mut Segment {
+        self.flags = flags;
+        self
+    }
+
+    pub fn align(&mut self, align: u64) -> &mut Segment {
+        self.align = align;
+        self
+    }
+
+    pub fn section(&mut self, section: usize) -> &mut Segment {
+        self.sections.push(section);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    /// name
+    name: String,
+    /// section the symbol is defined in, `None` if it's defined in another object
+    section: Option<usize>,
+    /// offset into the section
+    offset: u64,
+    /// size
+    len: u64,
+    /// binding
+    bind: u8,
+    /// kind
+    kind: u8,
+}
+
+impl Symbol {
+    pub fn new(name: &str, section: usize) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            section: Some(section),
+            offset: 0,
+            len: 0,
+            bind: symbol::BIND_LOCAL,
+            kind: symbol::KIND_NOTYPE,
+        }
+    }
+
+    /// A global symbol for the linker to find in another object.
+    pub fn undefined(name: &str) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            section: None,
+            offset: 0,
+            len: 0,
+            bind: symbol::BIND_GLOBAL,
+            kind: symbol::KIND_NOTYPE,
+        }
+    }
+
+    pub fn offset(&mut self, offset: u64) -> &mut Symbol {
+        self.offset = offset;
+        self
+    }
+
+    pub fn len(&mut self, len: u64) -> &mut Symbol {
+        self.len = len;
+        self
+    }
+
+    pub fn bind(&mut self, bind: u8) -> &mut Symbol {
+        self.bind = bind;
+        self
+    }
+
+    pub fn kind(&mut self, kind: u8) -> &mut Symbol {
+        self.kind = kind;
+        self
+    }
+}
+
+/// What a relocation refers to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Target {
+    /// the start of a section
+    Section(usize),
+    /// a symbol, by the id `push_symbol` returned
+    Symbol(usize),
+}
+
+/// A field in a section referring to an address in another, filled in by `relocate`.
+#[derive(Clone, Debug)]
+pub struct Relocation {
+    /// section holding the field
+    section: usize,
+    /// offset of the field in the section
+    offset: u64,
+    kind: Kind,
+    /// address referred to
+    target: Target,
+    addend: i64,
+}
+
+impl Relocation {
+    pub fn new(section: usize, offset: u64, kind: Kind, target: Target) -> Relocation {
+        Relocation {
+            section,
+            offset,
+            kind,
+            target,
+            addend: 0,
+        }
+    }
+
+    pub fn addend(&mut self, addend: i64) -> &mut Relocation {
+        self.addend = addend;
+        self
+    }
+}
+
+/// Places sections and segments in the output file, and computes every offset, address and
+/// size the ELF, program and section headers need.
+///
+/// When at least one segment is added, the file header and program headers are mapped by a
+/// `PT_PHDR` and a read-only `PT_LOAD` segment, and a `PT_GNU_STACK` segment is appended. A
+/// `PT_INTERP` segment is moved before every `PT_LOAD` one.
+/// Sections which aren't part of any `PT_LOAD` segment are placed after all loaded data.
+///
+/// Relocations are resolved once addresses are known, so sections can refer to each other.
+///
+/// When at least one symbol is added, `.symtab` and `.strtab` are emitted after them, with
+/// local symbols ordered before global ones as ELF requires.
+///
+/// A relocatable object has no segments and every address is 0. Its relocations are left
+/// to the linker in a `.rela` section per relocated section (`.rel` for ELF32), referring
+/// to section symbols or the symbols they name.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    class: Class,
+    relocatable: bool,
+    base: u64,
+    page: u64,
+    sections: Vec<Section>,
+    segments: Vec<Segment>,
+    symbols: Vec<Symbol>,
+    relocations: Vec<Relocation>,
+    entry: Option<(usize, u64)>,
+    /// symbols in `.symtab` order, with section symbols for relocatable objects
+    table: Vec<Symbol>,
+    /// index in `.symtab` of every added symbol
+    symbol_indices: Vec<u32>,
+    /// relocated section and offset of every relocation table
+    relocation_tables: Vec<(usize, u64)>,
+    names: Vec<u8>,
+    name_offsets: Vec<u32>,
+    names_offset: u64,
+    symbol_names: Vec<u8>,
+    symbol_name_offsets: Vec<u32>,
+    symbols_offset: u64,
+    symbol_names_offset: u64,
+    section_headers_address: u64,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Layout {
+    pub fn new() -> Layout {
+        Layout {
+            class: Class::Class64,
+            relocatable: false,
+            base: 0x200000,
+            page: 0x1000,
+            sections: vec![],
+            segments: vec![],
+            symbols: vec![],
+            relocations: vec![],
+            entry: None,
+            table: vec![],
+            symbol_indices: vec![],
+            relocation_tables: vec![],
+            names: vec![],
+            name_offsets: vec![],
+            names_offset: 0,
+            symbol_names: vec![],
+            symbol_name_offsets: vec![],
+            symbols_offset: 0,
+            symbol_names_offset: 0,
+            section_headers_address: 0,
+        }
+    }
+
+    /// Sets the class the headers are sized for, `write` takes it from the file header.
+    pub fn class(&mut self, class: Class) -> &mut Layout {
+        self.class = class;
+        self
+    }
+
+    /// Lays out a relocatable object rather than an executable.
+    pub fn relocatable(&mut self) -> &mut Layout {
+        self.relocatable = true;
+        self
+    }
+
+    pub fn base(&mut self, address: u64) -> &mut Layout {
+        self.base = address;
+        self
+    }
+
+    pub fn page(&mut self, page: u64) -> &mut Layout {
+        self.page = page;
+        self
+    }
+
+    /// Adds a section, returning its id.
+    pub fn push_section(&mut self, section: Section) -> usize {
+        self.sections.push(section);
+        self.sections.len() - 1
+    }
+
+    /// Adds a segment, returning its id.
+    pub fn push_segment(&mut self, segment: Segment) -> usize {
+        self.segments.push(segment);
+        self.segments.len() - 1
+    }
+
+    /// Adds a symbol, returning its id. Its value is the address it ends up at.
+    pub fn push_symbol(&mut self, symbol: Symbol) -> usize {
+        self.symbols.push(symbol);
+        self.symbols.len() - 1
+    }
+
+    pub fn push_relocation(&mut self, relocation: Relocation) {
+        self.relocations.push(relocation);
+    }
+
+    /// Sets the entry point to `offset` bytes into `section`.
+    pub fn entry(&mut self, section: usize, offset: u64) -> &mut Layout {
+        self.entry = Some((section, offset));
+        self
+    }
+
+    /// Index of a section in the section header table.
+    pub fn index(&self, section: usize) -> u16 {
+        (section + 1) as u16
+    }
+
+    /// Virtual address of a section, valid after `arrange`.
+    pub fn address(&self, section: usize) -> u64 {
+        self.sections[section].address
+    }
+
+    /// File offset of a section, valid after `arrange`.
+    pub fn offset(&self, section: usize) -> u64 {
+        self.sections[section].offset
+    }
+
+    /// Contents of a section, relocated after `relocate`.
+    pub fn data(&self, section: usize) -> &[u8] {
+        &self.sections[section].data
+    }
+
+    /// Contents of a section, to patch after `arrange`. The length must not change.
+    pub fn data_mut(&mut self, section: usize) -> &mut [u8] {
+        &mut self.sections[section].data
+    }
+
+    /// Size of an address.
+    fn word(&self) -> u64 {
+        match self.class {
+            Class::Class32 => 4,
+            Class::Class64 => 8,
+        }
+    }
+
+    fn elf_size(&self) -> u64 {
+        match self.class {
+            Class::Class32 => elf::SIZE32,
+            Class::Class64 => elf::SIZE,
+        }
+    }
+
+    fn program_size(&self) -> u64 {
+        match self.class {
+            Class::Class32 => program::SIZE32,
+            Class::Class64 => program::SIZE,
+        }
+    }
+
+    fn section_size(&self) -> u64 {
+        match self.class {
+            Class::Class32 => section::SIZE32,
+            Class::Class64 => section::SIZE,
+        }
+    }
+
+    fn symbol_size(&self) -> u64 {
+        match self.class {
+            Class::Class32 => symbol::SIZE32,
+            Class::Class64 => symbol::SIZE,
+        }
+    }
+
+    fn relocation_size(&self) -> u64 {
+        match self.class {
+            Class::Class32 => relocation::SIZE32,
+            Class::Class64 => relocation::SIZE,
+        }
+    }
+
+    /// Orders symbols for `.symtab`, locals first. A relocatable object gets a section
+    /// symbol for every section relocations refer to.
+    fn order_symbols(&mut self) {
+        let mut table: Vec<(Option<usize>, Symbol)> = self
+            .symbols
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(id, symbol)| (Some(id), symbol))
+            .collect();
+
+        if self.relocatable {
+            for (id, _) in self.sections.iter().enumerate() {
+                let target = Target::Section(id);
+
+                if !self
+                    .relocations
+                    .iter()
+                    .any(|relocation| relocation.target == target)
+                {
+                    continue;
+                }
+
+                let mut symbol = Symbol::new("", id);
+
+                symbol.kind(symbol::KIND_SECTION);
+                table.push((None, symbol));
+            }
+        }
+
+        table.sort_by_key(|(_, symbol)| symbol.bind != symbol::BIND_LOCAL);
+
+        self.symbol_indices = vec![0; self.symbols.len()];
+
+        // the first entry is the null symbol
+        for (index, (id, _)) in table.iter().enumerate() {
+            if let Some(id) = id {
+                self.symbol_indices[*id] = index as u32 + 1;
+            }
+        }
+
+        self.table = table.into_iter().map(|(_, symbol)| symbol).collect();
+    }
+
+    /// Index in `.symtab` of what a relocation refers to, valid after `arrange`.
+    fn symbol_index(&self, target: Target) -> u32 {
+        match target {
+            Target::Symbol(id) => self.symbol_indices[id],
+            Target::Section(id) => {
+                let index = self
+                    .table
+                    .iter()
+                    .position(|symbol| {
+                        symbol.kind == symbol::KIND_SECTION && symbol.section == Some(id)
+                    })
+                    .expect("section symbols are added for every relocation target");
+
+                index as u32 + 1
+            }
+        }
+    }
+
+    /// Number of entries in the section header table, including the null section, the
+    /// relocation and symbol tables and `.shstrtab`.
+    fn section_len(&self) -> u64 {
+        let tables = if self.table.is_empty() { 0 } else { 2 };
+
+        self.sections.len() as u64 + self.relocation_tables.len() as u64 + tables + 2
+    }
+
+    fn program_len(&self) -> u64 {
+        if self.segments.is_empty() || self.relocatable {
+            0
+        } else {
+            // PT_PHDR, headers PT_LOAD, ..., PT_GNU_STACK
+            self.segments.len() as u64 + 3
+        }
+    }
+
+    fn headers_len(&self) -> u64 {
+        self.elf_size() + self.program_size() * self.program_len()
+    }
+
+    /// Computes the file offset and virtual address of every section.
+    pub fn arrange(&mut self) {
+        let mut placed = vec![false; self.sections.len()];
+        let mut offset = self.headers_len();
+        let mut address = self.base + offset;
+        let page = self.page;
+
+        for segment in &self.segments {
+            if segment.kind != program::KIND_LOAD || self.relocatable {
+                continue;
+            }
+
+            let mut start = None;
+
+            for &id in &segment.sections {
+                let section = &mut self.sections[id];
+
+                offset = align_up(offset, section.align);
+
+                let (segment_offset, segment_address) =
+                    *start.get_or_insert_with(|| (offset, align_up(address, page) + offset % page));
+
+                section.offset = offset;
+                section.address = if section.kind == section::KIND_NOBITS {
+                    // nothing in the file, so it only follows the previous section in memory
+                    align_up(address.max(segment_address), section.align)
+                } else {
+                    segment_address + (offset - segment_offset)
+                };
+                placed[id] = true;
+
+                offset += section.file_len();
+                address = section.address + section.len();
+            }
+        }
+
+        for (id, section) in self.sections.iter_mut().enumerate() {
+            if placed[id] {
+                continue;
+            }
+
+            offset = align_up(offset, section.align);
+            section.offset = offset;
+            section.address = 0;
+            offset += section.file_len();
+        }
+
+        self.order_symbols();
+        self.relocation_tables = vec![];
+
+        if self.relocatable {
+            for id in 0..self.sections.len() {
+                let len = self
+                    .relocations
+                    .iter()
+                    .filter(|relocation| relocation.section == id)
+                    .count() as u64;
+
+                if len == 0 {
+                    continue;
+                }
+
+                offset = align_up(offset, self.word());
+                self.relocation_tables.push((id, offset));
+                offset += self.relocation_size() * len;
+            }
+        }
+
+        if !self.table.is_empty() {
+            let mut names = vec![0];
+            let mut name_offsets = vec![];
+
+            for symbol in &self.table {
+                name_offsets.push(names.len() as u32);
+                names.extend(symbol.name.as_bytes());
+                names.push(0);
+            }
+
+            self.symbol_names = names;
+            self.symbol_name_offsets = name_offsets;
+
+            offset = align_up(offset, self.word());
+            self.symbols_offset = offset;
+            offset += self.symbol_size() * (self.table.len() as u64 + 1);
+            self.symbol_names_offset = offset;
+            offset += self.symbol_names.len() as u64;
+        }
+
+        let prefix = match self.class {
+            Class::Class32 => ".rel",
+            Class::Class64 => ".rela",
+        };
+        let relocation_names: Vec<String> = self
+            .relocation_tables
+            .iter()
+            .map(|&(id, _)| format!("{prefix}{}", self.sections[id].name))
+            .collect();
+        let tables: &[&str] = if self.table.is_empty() {
+            &[]
+        } else {
+            &[".symtab", ".strtab"]
+        };
+
+        self.names = vec![0];
+        self.name_offsets = vec![];
+
+        for name in self
+            .sections
+            .iter()
+            .map(|section| section.name.as_str())
+            .chain(relocation_names.iter().map(String::as_str))
+            .chain(tables.iter().copied())
+            .chain(Some(".shstrtab"))
+        {
+            self.name_offsets.push(self.names.len() as u32);
+            self.names.extend(name.as_bytes());
+            self.names.push(0);
+        }
+
+        self.names_offset = offset;
+        offset += self.names.len() as u64;
+
+        self.section_headers_address = align_up(offset, 8);
+    }
+
+    /// Fills in every relocated field in the given byte order, valid after `arrange`. A
+    /// relocatable object leaves them to the linker, with the addend in the field for ELF32.
+    pub fn relocate(&mut self, endian: Endian) {
+        for relocation in &self.relocations {
+            let value = if self.relocatable {
+                match self.class {
+                    Class::Class32 => relocation.addend as u64,
+                    Class::Class64 => 0,
+                }
+            } else {
+                let target = match relocation.target {
+                    Target::Section(id) => self.sections[id].address,
+                    Target::Symbol(id) => {
+                        let symbol = &self.symbols[id];
+                        let section = symbol
+                            .section
+                            .expect("undefined symbols are only relocated by the linker");
+
+                        self.sections[section].address + symbol.offset
+                    }
+                };
+                let address = self.sections[relocation.section].address + relocation.offset;
+
+                // the layout keeps everything within a few pages of the base address
+                relocation
+                    .kind
+                    .value(target, relocation.addend, address)
+                    .expect("relocation out of range")
+            };
+
+            let start = relocation.offset as usize;
+            let mut writer = Writer::new(&mut self.sections[relocation.section].data, endian);
+
+            match relocation.kind.size() {
+                4 => writer.u32(start, value as u32),
+                _ => writer.u64(start, value),
+            };
+        }
+    }
+
+    /// Span of a segment as (offset, address, file size, memory size).
+    fn segment_span(&self, segment: &Segment) -> (u64, u64, u64, u64) {
+        let first = match segment.sections.first() {
+            Some(&id) => &self.sections[id],
+            None => return (0, 0, 0, 0),
+        };
+
+        let sections = segment.sections.iter().map(|&id| &self.sections[id]);
+
+        let file_end = sections
+            .clone()
+            .filter(|section| section.kind != section::KIND_NOBITS)
+            .map(|section| section.offset + section.file_len())
+            .max()
+            .unwrap_or(first.offset);
+
+        let memory_end = sections
+            .map(|section| section.address + section.len())
+            .max()
+            .unwrap_or(first.address);
+
+        (
+            first.offset,
+            first.address,
+            file_end - first.offset,
+            memory_end - first.address,
+        )
+    }
+
+    /// Writes the file, filling in every layout related field of `elf`.
+    pub fn write(&mut self, elf: &mut Elf) -> Vec<u8> {
+        self.class(elf.class).arrange();
+        self.relocate(elf.endian);
+
+        let elf_size = self.elf_size();
+        let program_size = self.program_size();
+        let section_size = self.section_size();
+        let program_len = self.program_len();
+        let headers_len = self.headers_len();
+        let section_len = self.section_len();
+        let entry = self
+            .entry
+            .filter(|_| !self.relocatable)
+            .map(|(id, offset)| self.sections[id].address + offset)
+            .unwrap_or(0);
+
+        elf.entry_address(entry)
+            .program_headers_address(if program_len > 0 { elf_size } else { 0 })
+            .section_headers_address(self.section_headers_address)
+            .program_size(program_size as u16)
+            .program_len(program_len as u16)
+            .section_size(section_size as u16)
+            .section_len(section_len as u16)
+            .section_index((section_len - 1) as u16);
+
+        match self.class {
+            Class::Class32 => elf.header32(),
+            Class::Class64 => elf.header64(),
+        };
+
+        let mut bytes =
+            vec![0; (self.section_headers_address + section_size * section_len) as usize];
+
+        bytes[..elf_size as usize].copy_from_slice(&elf.to_vec());
+
+        let mut headers = vec![];
+
+        if program_len > 0 {
+            let mut header = program::Header::new();
+
+            header
+                .kind(program::KIND_PHDR)
+                .flags(program::FLAG_READ)
+                .offset(elf_size)
+                .file_size(program_size * program_len)
+                .virtual_address(self.base + elf_size)
+                .memory_size(program_size * program_len)
+                .physical_address(self.base + elf_size)
+                .align(self.word());
+
+            headers.push(header);
+
+            let segment_header = |segment: &Segment| {
+                let (offset, address, file_size, memory_size) = self.segment_span(segment);
+                let mut header = program::Header::new();
+
+                header
+                    .kind(segment.kind)
+                    .flags(segment.flags)
+                    .offset(offset)
+                    .file_size(file_size)
+                    .virtual_address(address)
+                    .memory_size(memory_size)
+                    .physical_address(address)
+                    .align(segment.align);
+
+                header
+            };
+
+            // `PT_INTERP` must come before any `PT_LOAD`
+            let (interpreters, segments): (Vec<&Segment>, Vec<&Segment>) = self
+                .segments
+                .iter()
+                .partition(|segment| segment.kind == program::KIND_INTERP);
+
+            headers.extend(interpreters.into_iter().map(segment_header));
+
+            let mut header = program::Header::new();
+
+            header
+                .kind(program::KIND_LOAD)
+                .flags(program::FLAG_READ)
+                .offset(0)
+                .file_size(headers_len)
+                .virtual_address(self.base)
+                .memory_size(headers_len)
+                .physical_address(self.base)
+                .align(self.page);
+
+            headers.push(header);
+            headers.extend(segments.into_iter().map(segment_header));
+
+            let mut header = program::Header::new();
+
+            header
+                .kind(program::KIND_GNU_STACK)
+                .flags(program::FLAG_READ | program::FLAG_WRITE);
+
+            headers.push(header);
+        }
+
+        for (index, header) in headers.iter().enumerate() {
+            let start = (elf_size + program_size * index as u64) as usize;
+            let bytes = &mut bytes[start..start + program_size as usize];
+
+            match self.class {
+                Class::Class32 => bytes.copy_from_slice(header.to_array32(elf.endian).as_slice()),
+                Class::Class64 => bytes.copy_from_slice(header.to_array(elf.endian).as_slice()),
+            }
+        }
+
+        for section in &self.sections {
+            let start = section.offset as usize;
+
+            bytes[start..start + section.data.len()].copy_from_slice(&section.data);
+        }
+
+        let start = self.names_offset as usize;
+
+        bytes[start..start + self.names.len()].copy_from_slice(&self.names);
+
+        for &(id, offset) in &self.relocation_tables {
+            let relocation_size = self.relocation_size() as usize;
+            let relocations = self
+                .relocations
+                .iter()
+                .filter(|relocation| relocation.section == id);
+
+            for (index, relocation) in relocations.enumerate() {
+                let mut entry = relocation::Relocation::new();
+
+                entry
+                    .offset(relocation.offset)
+                    .symbol(self.symbol_index(relocation.target))
+                    .kind(relocation.kind.id(self.class))
+                    .addend(relocation.addend);
+
+                let start = offset as usize + relocation_size * index;
+                let bytes = &mut bytes[start..start + relocation_size];
+
+                match self.class {
+                    Class::Class32 => {
+                        bytes.copy_from_slice(entry.to_array32(elf.endian).as_slice())
+                    }
+                    Class::Class64 => bytes.copy_from_slice(entry.to_array(elf.endian).as_slice()),
+                }
+            }
+        }
+
+        if !self.table.is_empty() {
+            let symbol_size = self.symbol_size() as usize;
+
+            for (index, (symbol, &name)) in
+                self.table.iter().zip(&self.symbol_name_offsets).enumerate()
+            {
+                let mut entry = symbol::Symbol::new();
+                let (section, address) = match symbol.section {
+                    Some(id) => (self.index(id), self.sections[id].address),
+                    None => (symbol::INDEX_UNDEFINED, 0),
+                };
+
+                entry
+                    .name(name)
+                    .info(symbol.bind, symbol.kind)
+                    .section(section)
+                    .value(address + symbol.offset)
+                    .len(symbol.len);
+
+                // the first entry is the null symbol
+                let start = self.symbols_offset as usize + symbol_size * (index + 1);
+                let bytes = &mut bytes[start..start + symbol_size];
+
+                match self.class {
+                    Class::Class32 => {
+                        bytes.copy_from_slice(entry.to_array32(elf.endian).as_slice())
+                    }
+                    Class::Class64 => bytes.copy_from_slice(entry.to_array(elf.endian).as_slice()),
+                }
+            }
+
+            let start = self.symbol_names_offset as usize;
+
+            bytes[start..start + self.symbol_names.len()].copy_from_slice(&self.symbol_names);
+        }
+
+        let mut headers = vec![section::Header::new()];
+
+        for (section, &name) in self.sections.iter().zip(&self.name_offsets) {
+            let mut header = section::Header::new();
+
+            header
+                .name(name)
+                .kind(section.kind)
+                .flags(section.flags)
+                .address(section.address)
+                .offset(section.offset)
+                .len(section.len())
+                .link(section.link)
+                .info(section.info)
+                .align(section.align)
+                .entry_len(section.entry_len);
+
+            headers.push(header);
+        }
+
+        // `.symtab` follows the sections and relocation tables
+        let symbols = self.sections.len() + self.relocation_tables.len();
+
+        for (index, &(id, offset)) in self.relocation_tables.iter().enumerate() {
+            let len = self
+                .relocations
+                .iter()
+                .filter(|relocation| relocation.section == id)
+                .count() as u64;
+            let kind = match self.class {
+                Class::Class32 => section::KIND_REL,
+                Class::Class64 => section::KIND_RELA,
+            };
+            let mut header = section::Header::new();
+
+            // link to .symtab, info is the relocated section
+            header
+                .name(self.name_offsets[self.sections.len() + index])
+                .kind(kind)
+                .flags(section::FLAG_INFO_LINK)
+                .offset(offset)
+                .len(self.relocation_size() * len)
+                .link(self.index(symbols) as u32)
+                .info(self.index(id) as u32)
+                .align(self.word())
+                .entry_len(self.relocation_size());
+
+            headers.push(header);
+        }
+
+        if !self.table.is_empty() {
+            let locals = self
+                .table
+                .iter()
+                .filter(|symbol| symbol.bind == symbol::BIND_LOCAL)
+                .count();
+            let mut header = section::Header::new();
+
+            // link to .strtab, info is the index of the first global symbol
+            header
+                .name(self.name_offsets[symbols])
+                .kind(section::KIND_SYMTAB)
+                .offset(self.symbols_offset)
+                .len(self.symbol_size() * (self.table.len() as u64 + 1))
+                .link(self.index(symbols + 1) as u32)
+                .info(locals as u32 + 1)
+                .align(self.word())
+                .entry_len(self.symbol_size());
+
+            headers.push(header);
+
+            let mut header = section::Header::new();
+
+            header
+                .name(self.name_offsets[symbols + 1])
+                .kind(section::KIND_STRTAB)
+                .offset(self.symbol_names_offset)
+                .len(self.symbol_names.len() as u64)
+                .align(1);
+
+            headers.push(header);
+        }
+
+        let mut header = section::Header::new();
+
+        header
+            .name(self.name_offsets[self.name_offsets.len() - 1])
+            .kind(section::KIND_STRTAB)
+            .offset(self.names_offset)
+            .len(self.names.len() as u64)
+            .align(1);
+
+        headers.push(header);
+
+        for (index, header) in headers.iter().enumerate() {
+            let start = (self.section_headers_address + section_size * index as u64) as usize;
+            let bytes = &mut bytes[start..start + section_size as usize];
+
+            match self.class {
+                Class::Class32 => bytes.copy_from_slice(header.to_array32(elf.endian).as_slice()),
+                Class::Class64 => bytes.copy_from_slice(header.to_array(elf.endian).as_slice()),
+            }
+        }
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    const TEXT: usize = 0;
+    const RODATA: usize = 1;
+    const DATA: usize = 2;
+    const BSS: usize = 3;
+
+    fn u16_at(bytes: &[u8], offset: u64) -> u16 {
+        let offset = offset as usize;
+
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: u64) -> u32 {
+        let offset = offset as usize;
+
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: u64) -> u64 {
+        let offset = offset as usize;
+
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// An executable with `.text`, `.rodata`, `.data` and `.bss`, each with a size which
+    /// isn't a multiple of the next one's alignment.
+    fn program(page: u64) -> Layout {
+        let mut layout = Layout::new();
+
+        layout.page(page);
+
+        for (name, flags, align, len) in [
+            (".text", section::FLAG_ALLOC | section::FLAG_EXEC, 16, 3),
+            (".rodata", section::FLAG_ALLOC, 8, 5),
+            (".data", section::FLAG_ALLOC | section::FLAG_WRITE, 16, 1),
+        ] {
+            let mut section = Section::new(name);
+
+            section
+                .kind(section::KIND_PROGBITS)
+                .flags(flags)
+                .align(align)
+                .data(vec![0xAA; len]);
+            layout.push_section(section);
+        }
+
+        let mut section = Section::new(".bss");
+
+        section
+            .flags(section::FLAG_ALLOC | section::FLAG_WRITE)
+            .align(32)
+            .nobits(33);
+        layout.push_section(section);
+
+        for (sections, flags) in [
+            (&[TEXT][..], program::FLAG_READ | program::FLAG_EXEC),
+            (&[RODATA], program::FLAG_READ),
+            (&[DATA, BSS], program::FLAG_READ | program::FLAG_WRITE),
+        ] {
+            let mut segment = Segment::new(program::KIND_LOAD);
+
+            segment.flags(flags);
+
+            for &id in sections {
+                segment.section(id);
+            }
+
+            layout.push_segment(segment);
+        }
+
+        layout.entry(TEXT, 0);
+        layout
+    }
+
+    fn elf64() -> Elf {
+        let mut elf = Elf::new();
+
+        elf.class64()
+            .machine_x86_64()
+            .header64()
+            .endian_little()
+            .version(1)
+            .abi_sysv()
+            .abi_version()
+            .version2()
+            .flags(0)
+            .kind_exec();
+        elf
+    }
+
+    fn elf32() -> Elf {
+        let mut elf = elf64();
+
+        elf.class32().machine_i386().header32();
+        elf
+    }
+
+    /// Type, offset, address, file size and memory size of each program header.
+    fn segments(bytes: &[u8]) -> Vec<(u32, u64, u64, u64, u64)> {
+        let start = u64_at(bytes, 32);
+        let size = u16_at(bytes, 54) as u64;
+
+        (0..u16_at(bytes, 56) as u64)
+            .map(|index| start + size * index)
+            .map(|header| {
+                (
+                    u32_at(bytes, header),
+                    u64_at(bytes, header + 8),
+                    u64_at(bytes, header + 16),
+                    u64_at(bytes, header + 32),
+                    u64_at(bytes, header + 40),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sections_are_aligned() {
+        let mut layout = program(0x1000);
+
+        layout.write(&mut elf64());
+
+        let mut end = 0;
+
+        for (id, section) in layout.sections.iter().enumerate() {
+            assert_eq!(layout.offset(id) % section.align, 0, "{}", section.name);
+            assert_eq!(layout.address(id) % section.align, 0, "{}", section.name);
+            assert!(layout.offset(id) >= end, "{} overlaps", section.name);
+
+            end = layout.offset(id) + section.file_len();
+        }
+
+        // the file header and program headers come first
+        assert!(layout.offset(TEXT) >= elf::SIZE + program::SIZE * 6);
+        assert_eq!(layout.address(BSS), align_up(layout.address(DATA) + 1, 32));
+    }
+
+    #[test]
+    fn addresses_are_congruent_to_offsets() {
+        for page in [0x1000, 0x10000] {
+            let mut layout = program(page);
+            let bytes = layout.write(&mut elf64());
+
+            for id in [TEXT, RODATA, DATA] {
+                assert_eq!(layout.address(id) % page, layout.offset(id) % page);
+            }
+
+            for (kind, offset, address, ..) in segments(&bytes) {
+                if kind == program::KIND_LOAD {
+                    assert_eq!(address % page, offset % page);
+                }
+            }
+
+            // segments with different permissions never share a page
+            assert_ne!(layout.address(TEXT) / page, layout.address(RODATA) / page);
+            assert_ne!(layout.address(RODATA) / page, layout.address(DATA) / page);
+        }
+    }
+
+    #[test]
+    fn bss_takes_memory_but_not_file() {
+        let mut layout = program(0x1000);
+        let bytes = layout.write(&mut elf64());
+        let (_, offset, address, file, memory) = segments(&bytes)
+            .into_iter()
+            .find(|&(kind, offset, ..)| kind == program::KIND_LOAD && offset == layout.offset(DATA))
+            .unwrap();
+
+        assert_eq!(offset, layout.offset(DATA));
+        assert_eq!(address, layout.address(DATA));
+        assert_eq!(file, 1);
+        assert_eq!(memory, layout.address(BSS) + 33 - address);
+        assert!(memory > file);
+    }
+
+    #[test]
+    fn header_counts_match_tables() {
+        let mut layout = program(0x1000);
+        let bytes = layout.write(&mut elf64());
+        let kinds: Vec<u32> = segments(&bytes).iter().map(|segment| segment.0).collect();
+
+        assert_eq!(u16_at(&bytes, 54) as u64, program::SIZE);
+        assert_eq!(
+            kinds,
+            [
+                program::KIND_PHDR,
+                program::KIND_LOAD,
+                program::KIND_LOAD,
+                program::KIND_LOAD,
+                program::KIND_LOAD,
+                program::KIND_GNU_STACK,
+            ]
+        );
+
+        // null, `.text`, `.rodata`, `.data`, `.bss` and `.shstrtab`, which ends the file
+        let sections = u64_at(&bytes, 40);
+        let size = u16_at(&bytes, 58) as u64;
+        let len = u16_at(&bytes, 60) as u64;
+
+        assert_eq!(size, section::SIZE);
+        assert_eq!(len, 6);
+        assert_eq!(u16_at(&bytes, 62) as u64, len - 1);
+        assert_eq!(sections + size * len, bytes.len() as u64);
+        assert_eq!(
+            u32_at(&bytes, sections + size * (len - 1) + 4),
+            section::KIND_STRTAB
+        );
+
+        // symbols add `.symtab` and `.strtab`
+        let mut layout = program(0x1000);
+
+        layout.push_symbol(Symbol::new("_start", TEXT));
+
+        let bytes = layout.write(&mut elf64());
+
+        assert_eq!(u16_at(&bytes, 60), 8);
+        assert_eq!(u16_at(&bytes, 62), 7);
+    }
+
+    #[test]
+    fn big_endian_writes_every_field_big_endian() {
+        let write = |endian_big: bool| {
+            let mut layout = program(0x1000);
+            let mut elf = elf64();
+
+            if endian_big {
+                elf.endian_big();
+            }
+
+            let relocation = Relocation::new(RODATA, 0, Kind::Absolute32, Target::Section(DATA));
+
+            layout.push_relocation(relocation);
+
+            let bytes = layout.write(&mut elf);
+
+            (layout, bytes)
+        };
+        let (layout, little) = write(false);
+        let (_, big) = write(true);
+
+        let text_header = u64_at(&little, 40) + section::SIZE * layout.index(TEXT) as u64;
+        let fields = [
+            // e_entry, e_phoff, e_shoff, e_phnum and e_shnum
+            (24, 8),
+            (32, 8),
+            (40, 8),
+            (56, 2),
+            (60, 2),
+            // p_type, p_offset and p_vaddr of `PT_PHDR`
+            (elf::SIZE, 4),
+            (elf::SIZE + 8, 8),
+            (elf::SIZE + 16, 8),
+            // sh_type, sh_addr and sh_size of `.text`
+            (text_header + 4, 4),
+            (text_header + 16, 8),
+            (text_header + 32, 8),
+            // the relocated address of `.data`
+            (layout.offset(RODATA), 4),
+        ];
+
+        assert_eq!(big.len(), little.len());
+        assert_eq!(big[5], Endian::Big as u8);
+
+        for (offset, len) in fields {
+            let range = offset as usize..(offset + len) as usize;
+            let mut reversed = little[range.clone()].to_vec();
+
+            reversed.reverse();
+
+            assert!(little[range.clone()].iter().any(|&byte| byte != 0));
+            assert_eq!(big[range], reversed[..], "field at {offset:#x}");
+        }
+
+        assert_eq!(
+            u32_at(&little, layout.offset(RODATA)) as u64,
+            layout.address(DATA)
+        );
+    }
+
+    #[test]
+    fn elf32_fields_are_32_bits() {
+        let mut layout = program(0x1000);
+        let bytes = layout.write(&mut elf32());
+        let program_headers = u32_at(&bytes, 28) as u64;
+        let section_headers = u32_at(&bytes, 32) as u64;
+
+        assert_eq!(bytes[4], Class::Class32 as u8);
+        assert_eq!(u32_at(&bytes, 24) as u64, layout.address(TEXT));
+        assert_eq!(program_headers, elf::SIZE32);
+        assert_eq!(u16_at(&bytes, 40) as u64, elf::SIZE32);
+        assert_eq!(u16_at(&bytes, 42) as u64, program::SIZE32);
+        assert_eq!(u16_at(&bytes, 44), 6);
+        assert_eq!(u16_at(&bytes, 46) as u64, section::SIZE32);
+        assert_eq!(u16_at(&bytes, 48), 6);
+        assert_eq!(u16_at(&bytes, 50), 5);
+        assert_eq!(bytes.len() as u64, section_headers + section::SIZE32 * 6);
+
+        // the `PT_LOAD` of `.text` follows `PT_PHDR` and the one of the headers
+        let header = program_headers + program::SIZE32 * 2;
+
+        assert_eq!(u32_at(&bytes, header), program::KIND_LOAD);
+        assert_eq!(u32_at(&bytes, header + 4) as u64, layout.offset(TEXT));
+        assert_eq!(u32_at(&bytes, header + 8) as u64, layout.address(TEXT));
+        assert_eq!(u32_at(&bytes, header + 16), 3);
+        assert_eq!(u32_at(&bytes, header + 20), 3);
+        assert_eq!(
+            u32_at(&bytes, header + 24),
+            program::FLAG_READ | program::FLAG_EXEC
+        );
+
+        let header = section_headers + section::SIZE32 * layout.index(TEXT) as u64;
+
+        assert_eq!(u32_at(&bytes, header + 4), section::KIND_PROGBITS);
+        assert_eq!(u32_at(&bytes, header + 12) as u64, layout.address(TEXT));
+        assert_eq!(u32_at(&bytes, header + 16) as u64, layout.offset(TEXT));
+        assert_eq!(u32_at(&bytes, header + 20), 3);
+        assert_eq!(u32_at(&bytes, header + 32), 16);
+    }
+}
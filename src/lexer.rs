@@ -1,40 +1,198 @@
+use super::span::Span;
 use core::str::CharIndices;
 
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum Lexme<'input> {
     Fn,
+    Static,
+    Mut,
+    Extern,
+    Let,
+    If,
+    Else,
+    While,
+    Loop,
+    Break,
+    Continue,
+    Return,
     ParenLeft,
     ParenRight,
     BraceLeft,
     BraceRight,
+    BracketLeft,
+    BracketRight,
     DoubleColon,
+    Colon,
+    Ellipsis,
+    Arrow,
+    Semicolon,
+    Equals,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Bang,
+    Less,
+    Greater,
+    LessEquals,
+    GreaterEquals,
+    EqualsEquals,
+    BangEquals,
+    ShiftLeft,
+    ShiftRight,
+    AndAnd,
+    OrOr,
     Ident(&'input str),
     Newline,
     Space(&'input str),
     Integer(u64),
     String(&'input str),
     Comma,
+    Invalid(Invalid, &'input str),
+}
+
+/// Input the lexer couldn't make sense of.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Invalid {
+    Character,
+    IntegerOverflow,
+    UnterminatedString,
+}
+
+/// Character a string escape such as `\n` stands for, `None` if it's unknown.
+pub fn escape(char0: char) -> Option<char> {
+    match char0 {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        '0' => Some('\0'),
+        '\\' | '\'' | '"' => Some(char0),
+        _ => None,
+    }
 }
 
 impl<'input> Lexme<'input> {
     pub fn display(&self) -> String {
         match self {
-            Lexme::Fn => format!("\x1b[38;5;1mfn\x1b[m"),
-            Lexme::ParenLeft => format!("("),
-            Lexme::ParenRight => format!(")"),
-            Lexme::BraceLeft => format!("{{"),
-            Lexme::BraceRight => format!("}}"),
+            Lexme::Fn => "\x1b[38;5;1mfn\x1b[m".to_string(),
+            Lexme::Static => "\x1b[38;5;1mstatic\x1b[m".to_string(),
+            Lexme::Mut => "\x1b[38;5;1mmut\x1b[m".to_string(),
+            Lexme::Extern => "\x1b[38;5;1mextern\x1b[m".to_string(),
+            Lexme::Let => "\x1b[38;5;1mlet\x1b[m".to_string(),
+            Lexme::If => "\x1b[38;5;1mif\x1b[m".to_string(),
+            Lexme::Else => "\x1b[38;5;1melse\x1b[m".to_string(),
+            Lexme::While => "\x1b[38;5;1mwhile\x1b[m".to_string(),
+            Lexme::Loop => "\x1b[38;5;1mloop\x1b[m".to_string(),
+            Lexme::Break => "\x1b[38;5;1mbreak\x1b[m".to_string(),
+            Lexme::Continue => "\x1b[38;5;1mcontinue\x1b[m".to_string(),
+            Lexme::Return => "\x1b[38;5;1mreturn\x1b[m".to_string(),
+            Lexme::ParenLeft => "(".to_string(),
+            Lexme::ParenRight => ")".to_string(),
+            Lexme::BraceLeft => "{".to_string(),
+            Lexme::BraceRight => "}".to_string(),
+            Lexme::BracketLeft => "[".to_string(),
+            Lexme::BracketRight => "]".to_string(),
             Lexme::Ident(ident) => format!("\x1b[38;5;4m{ident}\x1b[m"),
-            Lexme::Newline => format!("\n"),
-            Lexme::Space(space) => format!("{space}"),
+            Lexme::Newline => "\n".to_string(),
+            Lexme::Space(space) => space.to_string(),
             Lexme::Integer(integer) => format!("\x1b[38;5;11m{integer}\x1b[m"),
-            Lexme::DoubleColon => format!("::"),
+            Lexme::DoubleColon => "::".to_string(),
+            Lexme::Colon => ":".to_string(),
+            Lexme::Ellipsis => "...".to_string(),
+            Lexme::Arrow => "->".to_string(),
+            Lexme::Semicolon => ";".to_string(),
+            Lexme::Equals => "=".to_string(),
+            Lexme::Plus => "+".to_string(),
+            Lexme::Minus => "-".to_string(),
+            Lexme::Star => "*".to_string(),
+            Lexme::Slash => "/".to_string(),
+            Lexme::Percent => "%".to_string(),
+            Lexme::Ampersand => "&".to_string(),
+            Lexme::Pipe => "|".to_string(),
+            Lexme::Caret => "^".to_string(),
+            Lexme::Bang => "!".to_string(),
+            Lexme::Less => "<".to_string(),
+            Lexme::Greater => ">".to_string(),
+            Lexme::LessEquals => "<=".to_string(),
+            Lexme::GreaterEquals => ">=".to_string(),
+            Lexme::EqualsEquals => "==".to_string(),
+            Lexme::BangEquals => "!=".to_string(),
+            Lexme::ShiftLeft => "<<".to_string(),
+            Lexme::ShiftRight => ">>".to_string(),
+            Lexme::AndAnd => "&&".to_string(),
+            Lexme::OrOr => "||".to_string(),
             Lexme::String(string) => format!("\x1b[38;5;2m{string}\x1b[m"),
-            Lexme::Comma => format!(","),
+            Lexme::Comma => ",".to_string(),
+            Lexme::Invalid(_, input) => format!("\x1b[4m{input}\x1b[m"),
+        }
+    }
+
+    /// Description used in diagnostics, such as "expected `(`, found identifier `foo`".
+    pub fn describe(&self) -> String {
+        match self {
+            Lexme::Fn => "keyword `fn`".to_string(),
+            Lexme::Static => "keyword `static`".to_string(),
+            Lexme::Mut => "keyword `mut`".to_string(),
+            Lexme::Extern => "keyword `extern`".to_string(),
+            Lexme::Let => "keyword `let`".to_string(),
+            Lexme::If => "keyword `if`".to_string(),
+            Lexme::Else => "keyword `else`".to_string(),
+            Lexme::While => "keyword `while`".to_string(),
+            Lexme::Loop => "keyword `loop`".to_string(),
+            Lexme::Break => "keyword `break`".to_string(),
+            Lexme::Continue => "keyword `continue`".to_string(),
+            Lexme::Return => "keyword `return`".to_string(),
+            Lexme::ParenLeft => "`(`".to_string(),
+            Lexme::ParenRight => "`)`".to_string(),
+            Lexme::BraceLeft => "`{`".to_string(),
+            Lexme::BraceRight => "`}`".to_string(),
+            Lexme::BracketLeft => "`[`".to_string(),
+            Lexme::BracketRight => "`]`".to_string(),
+            Lexme::DoubleColon => "`::`".to_string(),
+            Lexme::Colon => "`:`".to_string(),
+            Lexme::Ellipsis => "`...`".to_string(),
+            Lexme::Arrow => "`->`".to_string(),
+            Lexme::Semicolon => "`;`".to_string(),
+            Lexme::Equals => "`=`".to_string(),
+            Lexme::Plus => "`+`".to_string(),
+            Lexme::Minus => "`-`".to_string(),
+            Lexme::Star => "`*`".to_string(),
+            Lexme::Slash => "`/`".to_string(),
+            Lexme::Percent => "`%`".to_string(),
+            Lexme::Ampersand => "`&`".to_string(),
+            Lexme::Pipe => "`|`".to_string(),
+            Lexme::Caret => "`^`".to_string(),
+            Lexme::Bang => "`!`".to_string(),
+            Lexme::Less => "`<`".to_string(),
+            Lexme::Greater => "`>`".to_string(),
+            Lexme::LessEquals => "`<=`".to_string(),
+            Lexme::GreaterEquals => "`>=`".to_string(),
+            Lexme::EqualsEquals => "`==`".to_string(),
+            Lexme::BangEquals => "`!=`".to_string(),
+            Lexme::ShiftLeft => "`<<`".to_string(),
+            Lexme::ShiftRight => "`>>`".to_string(),
+            Lexme::AndAnd => "`&&`".to_string(),
+            Lexme::OrOr => "`||`".to_string(),
+            Lexme::Ident(ident) => format!("identifier `{ident}`"),
+            Lexme::Newline => "newline".to_string(),
+            Lexme::Space(_) => "whitespace".to_string(),
+            Lexme::Integer(integer) => format!("integer `{integer}`"),
+            Lexme::String(string) => format!("string {string}"),
+            Lexme::Comma => "`,`".to_string(),
+            Lexme::Invalid(_, input) => format!("`{input}`"),
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Token<'input> {
+    pub lexme: Lexme<'input>,
+    pub span: Span,
+}
+
 #[derive(Clone, Debug)]
 pub struct Lexer<'input> {
     input: &'input str,
@@ -76,6 +234,14 @@ impl<'input> Lexer<'input> {
         self.peek0
     }
 
+    /// Byte offset of the next character.
+    #[inline]
+    fn offset(&self) -> usize {
+        self.peek0
+            .map(|(start, _char0)| start)
+            .unwrap_or(self.input.len())
+    }
+
     #[inline]
     fn peek2(&self) -> Option<(usize, char, char)> {
         self.peek0
@@ -84,22 +250,24 @@ impl<'input> Lexer<'input> {
 
     #[inline]
     fn ident(&mut self, start: usize) -> Option<Lexme<'input>> {
-        let mut end = start;
-
-        loop {
-            match self.peek() {
-                Some((_start, 'a'..='z' | 'A'..='Z' | '_')) => self.step(),
-                Some((start, _char0)) => {
-                    end = start;
-                    break;
-                }
-                None => return None,
-            }
+        while let Some((_start, 'a'..='z' | 'A'..='Z' | '_' | '0'..='9')) = self.peek() {
+            self.step();
         }
 
-        let input = &self.input[start..end];
+        let input = &self.input[start..self.offset()];
         let lexme = match input {
             "fn" => Lexme::Fn,
+            "static" => Lexme::Static,
+            "mut" => Lexme::Mut,
+            "extern" => Lexme::Extern,
+            "let" => Lexme::Let,
+            "if" => Lexme::If,
+            "else" => Lexme::Else,
+            "while" => Lexme::While,
+            "loop" => Lexme::Loop,
+            "break" => Lexme::Break,
+            "continue" => Lexme::Continue,
+            "return" => Lexme::Return,
             ident => Lexme::Ident(ident),
         };
 
@@ -108,73 +276,89 @@ impl<'input> Lexer<'input> {
 
     #[inline]
     fn integer(&mut self, start: usize) -> Option<Lexme<'input>> {
-        let mut end = start;
-
-        loop {
-            match self.peek() {
-                Some((_start, '0'..='9')) => self.step(),
-                Some((start, _char0)) => {
-                    end = start;
-                    break;
-                }
-                None => return None,
-            }
+        while let Some((_start, '0'..='9')) = self.peek() {
+            self.step();
         }
 
-        let input = &self.input[start..end];
+        let input = &self.input[start..self.offset()];
 
-        Some(Lexme::Integer(input.parse::<u64>().unwrap()))
+        match input.parse::<u64>() {
+            Ok(integer) => Some(Lexme::Integer(integer)),
+            Err(_) => Some(Lexme::Invalid(Invalid::IntegerOverflow, input)),
+        }
     }
 
     #[inline]
     fn space(&mut self, start: usize) -> Option<Lexme<'input>> {
-        let mut end = start;
-
-        loop {
-            match self.peek() {
-                Some((_start, char0)) if char0.is_whitespace() => self.step(),
-                Some((start, _char0)) => {
-                    end = start;
-                    break;
-                }
-                None => return None,
+        while let Some((_start, char0)) = self.peek() {
+            if !char0.is_whitespace() || char0 == '\n' {
+                break;
             }
+
+            self.step();
         }
 
-        Some(Lexme::Space(&self.input[start..end]))
+        Some(Lexme::Space(&self.input[start..self.offset()]))
     }
 
+    /// A string up to the closing `quote`, which is either `'` or `"`. A backslash escapes
+    /// the character after it, so it never closes the string.
     #[inline]
-    fn string(&mut self, start: usize) -> Option<Lexme<'input>> {
-        let mut end = start;
-
+    fn string(&mut self, start: usize, quote: char) -> Option<Lexme<'input>> {
         loop {
             match self.peek() {
-                Some((_start, char0)) if char0 != '\'' => self.step(),
-                Some((_start, _char0)) => match self.peek() {
-                    Some((start, '\'')) => {
-                        end = start + 1;
+                Some((_start, char0)) if char0 == quote => {
+                    self.step();
+                    break;
+                }
+                Some((_start, '\\')) => {
+                    self.step();
+
+                    if self.peek().is_some() {
                         self.step();
-                        break;
                     }
-                    _ => return None,
-                },
-                None => return None,
+                }
+                Some(_) => self.step(),
+                None => {
+                    return Some(Lexme::Invalid(
+                        Invalid::UnterminatedString,
+                        &self.input[start..],
+                    ))
+                }
             }
         }
 
-        Some(Lexme::String(&self.input[start..end]))
+        Some(Lexme::String(&self.input[start..self.offset()]))
     }
-}
-
-impl<'input> Iterator for Lexer<'input> {
-    type Item = Lexme<'input>;
 
     #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
+    fn lexme(&mut self) -> Option<Lexme<'input>> {
         let lexme = match self.peek2() {
-            Some((_start, char0, char1)) => match (char0, char1) {
+            Some((start, char0, char1)) => match (char0, char1) {
                 (':', ':') => Some(Lexme::DoubleColon),
+                ('-', '>') => Some(Lexme::Arrow),
+                ('<', '=') => Some(Lexme::LessEquals),
+                ('>', '=') => Some(Lexme::GreaterEquals),
+                ('=', '=') => Some(Lexme::EqualsEquals),
+                ('!', '=') => Some(Lexme::BangEquals),
+                ('<', '<') => Some(Lexme::ShiftLeft),
+                ('>', '>') => Some(Lexme::ShiftRight),
+                ('&', '&') => Some(Lexme::AndAnd),
+                ('|', '|') => Some(Lexme::OrOr),
+                ('.', '.') => {
+                    self.stepn(2);
+
+                    return match self.peek() {
+                        Some((_start, '.')) => {
+                            self.step();
+                            Some(Lexme::Ellipsis)
+                        }
+                        _ => Some(Lexme::Invalid(
+                            Invalid::Character,
+                            &self.input[start..self.offset()],
+                        )),
+                    };
+                }
                 _ => None,
             },
             _ => None,
@@ -189,14 +373,30 @@ impl<'input> Iterator for Lexer<'input> {
         let lexme = match self.peek() {
             Some((start, char0)) => match char0 {
                 ',' => Some(Lexme::Comma),
+                ':' => Some(Lexme::Colon),
+                ';' => Some(Lexme::Semicolon),
+                '=' => Some(Lexme::Equals),
+                '+' => Some(Lexme::Plus),
+                '-' => Some(Lexme::Minus),
+                '*' => Some(Lexme::Star),
+                '/' => Some(Lexme::Slash),
+                '%' => Some(Lexme::Percent),
+                '&' => Some(Lexme::Ampersand),
+                '|' => Some(Lexme::Pipe),
+                '^' => Some(Lexme::Caret),
+                '!' => Some(Lexme::Bang),
+                '<' => Some(Lexme::Less),
+                '>' => Some(Lexme::Greater),
                 '(' => Some(Lexme::ParenLeft),
                 ')' => Some(Lexme::ParenRight),
                 '{' => Some(Lexme::BraceLeft),
                 '}' => Some(Lexme::BraceRight),
+                '[' => Some(Lexme::BracketLeft),
+                ']' => Some(Lexme::BracketRight),
                 '\n' => Some(Lexme::Newline),
-                '\'' => {
+                '\'' | '"' => {
                     self.step();
-                    return self.string(start);
+                    return self.string(start, char0);
                 }
                 '0'..='9' => {
                     self.step();
@@ -210,7 +410,10 @@ impl<'input> Iterator for Lexer<'input> {
                     self.step();
                     return self.space(start);
                 }
-                _ => None,
+                char0 => Some(Lexme::Invalid(
+                    Invalid::Character,
+                    &self.input[start..start + char0.len_utf8()],
+                )),
             },
             _ => None,
         };
@@ -224,3 +427,16 @@ impl<'input> Iterator for Lexer<'input> {
         None
     }
 }
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Token<'input>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset();
+        let lexme = self.lexme()?;
+        let span = Span::new(start, self.offset());
+
+        Some(Token { lexme, span })
+    }
+}
@@ -0,0 +1,705 @@
+use super::elf::{Class, Elf};
+use super::layout::{self, align_up, Layout, Target};
+use super::reader::{self, File};
+use super::relocation::{self, Kind};
+use super::{program, section, symbol};
+use std::collections::BTreeMap;
+
+/// Output sections, in the order their contents are merged into.
+const TEXT: usize = 0;
+const RODATA: usize = 1;
+const DATA: usize = 2;
+const BSS: usize = 3;
+
+/// Output section and offset into it.
+type Location = (usize, u64);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// the named input couldn't be parsed
+    Read(String, reader::Error),
+    /// the named input isn't an x86-64 relocatable object
+    Object(String),
+    /// the named input has a relocation of an unsupported kind
+    Relocation(String, u32),
+    /// a symbol which isn't in a loaded section, such as a common or absolute symbol
+    Symbol(String, String),
+    /// a symbol referred to by the named input, which no input defines
+    Undefined(String, String),
+    /// a symbol defined by both named inputs
+    Duplicate(String, String, String),
+    /// no input defines `_start`
+    Entry,
+}
+
+impl Error {
+    pub fn message(&self) -> String {
+        match self {
+            Error::Read(path, error) => format!("`{path}`: {}", error.message()),
+            Error::Object(path) => format!("`{path}` isn't an x86-64 relocatable object"),
+            Error::Relocation(path, kind) => {
+                format!("`{path}` has a relocation of unsupported kind {kind}")
+            }
+            Error::Symbol(path, name) => {
+                format!("`{name}` in `{path}` isn't defined in a loaded section")
+            }
+            Error::Undefined(path, name) => {
+                format!("undefined symbol `{name}`, referred to by `{path}`")
+            }
+            Error::Duplicate(name, first, second) => {
+                format!("`{name}` is defined by both `{first}` and `{second}`")
+            }
+            Error::Entry => "no input defines the entry point `_start`".to_string(),
+        }
+    }
+
+    /// How to fix the error, if it's not obvious from the message.
+    pub fn note(&self) -> Option<&'static str> {
+        match self {
+            Error::Entry => Some(
+                "objects built with `-c` have no `_start` stub, define `fn _start()` in one of \
+                 them and end it with `sys::syscall(60, status)`",
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Contents of an output section, merged from input sections.
+#[derive(Clone, Debug)]
+struct Output {
+    name: &'static str,
+    flags: u64,
+    align: u64,
+    data: Vec<u8>,
+    /// size, which for `.bss` isn't backed by `data`
+    len: u64,
+    /// whether any input section was merged in
+    used: bool,
+}
+
+impl Output {
+    fn new(name: &'static str, flags: u64) -> Output {
+        Output {
+            name,
+            flags,
+            align: 1,
+            data: vec![],
+            len: 0,
+            used: false,
+        }
+    }
+
+    /// Appends an input section, returning its offset.
+    fn merge(&mut self, header: &section::Header, data: &[u8]) -> u64 {
+        let offset = align_up(self.len, header.align);
+
+        self.align = self.align.max(header.align);
+        self.len = offset + header.len;
+        self.used = true;
+
+        if header.kind != section::KIND_NOBITS {
+            self.data.resize(offset as usize, 0);
+            self.data.extend(data);
+        }
+
+        offset
+    }
+}
+
+/// A parsed input with its symbols, and where each of its sections was merged to.
+#[derive(Clone, Debug)]
+struct Object<'a> {
+    path: &'a str,
+    file: File<'a>,
+    symbols: Vec<(&'a str, symbol::Symbol)>,
+    locations: Vec<Option<Location>>,
+}
+
+impl<'a> Object<'a> {
+    fn parse(path: &'a str, bytes: &'a [u8], outputs: &mut [Output]) -> Result<Object<'a>, Error> {
+        let read = |error| Error::Read(path.to_string(), error);
+        let file = File::parse(bytes).map_err(read)?;
+
+        // ET_REL, EM_X86_64
+        if file.elf.class != Class::Class64 || file.elf.kind != 1 || file.elf.machine != 0x3E {
+            return Err(Error::Object(path.to_string()));
+        }
+
+        let mut symbols = vec![];
+
+        if let Some(table) = file
+            .sections
+            .iter()
+            .find(|section| section.header.kind == section::KIND_SYMTAB)
+        {
+            let names = file
+                .sections
+                .get(table.header.link as usize)
+                .ok_or(read(reader::Error::Bounds("symbol name table")))?
+                .data;
+
+            for chunk in table.data.chunks(symbol::SIZE as usize) {
+                let symbol = symbol::Symbol::from_bytes(chunk, file.elf.endian).map_err(read)?;
+                let name = names
+                    .get(symbol.name as usize..)
+                    .and_then(|names| names.split(|&byte| byte == 0).next())
+                    .and_then(|name| core::str::from_utf8(name).ok())
+                    .ok_or(read(reader::Error::Bounds("symbol name")))?;
+
+                symbols.push((name, symbol));
+            }
+        }
+
+        let mut locations = vec![];
+
+        for section in &file.sections {
+            let header = &section.header;
+
+            if header.flags & section::FLAG_ALLOC == 0 {
+                locations.push(None);
+                continue;
+            }
+
+            let output = if header.kind == section::KIND_NOBITS {
+                BSS
+            } else if header.flags & section::FLAG_EXEC != 0 {
+                TEXT
+            } else if header.flags & section::FLAG_WRITE != 0 {
+                DATA
+            } else {
+                RODATA
+            };
+
+            locations.push(Some((output, outputs[output].merge(header, section.data))));
+        }
+
+        Ok(Object {
+            path,
+            file,
+            symbols,
+            locations,
+        })
+    }
+
+    /// Where a symbol defined by this object ends up.
+    fn locate(&self, name: &str, symbol: &symbol::Symbol) -> Result<Location, Error> {
+        match self.locations.get(symbol.section as usize) {
+            Some(Some((output, offset))) if symbol.section != symbol::INDEX_ABSOLUTE => {
+                Ok((*output, offset + symbol.value))
+            }
+            _ => Err(Error::Symbol(self.path.to_string(), name.to_string())),
+        }
+    }
+}
+
+/// Links x86-64 relocatable objects, given with their paths, into an executable. Every
+/// allocated section is merged into `.text`, `.rodata`, `.data` or `.bss` by its flags, and
+/// `_start` is the entry point. Objects built with `-c` leave `_start` to what they're linked
+/// with, so linking only those needs one of them to define `fn _start()`, which exits with
+/// the exit syscall since there's nothing to return to.
+pub fn link(inputs: &[(&str, &[u8])]) -> Result<Vec<u8>, Vec<Error>> {
+    let mut outputs = [
+        Output::new(".text", section::FLAG_ALLOC | section::FLAG_EXEC),
+        Output::new(".rodata", section::FLAG_ALLOC),
+        Output::new(".data", section::FLAG_ALLOC | section::FLAG_WRITE),
+        Output::new(".bss", section::FLAG_ALLOC | section::FLAG_WRITE),
+    ];
+    let mut objects = vec![];
+    let mut errors = vec![];
+
+    for &(path, bytes) in inputs {
+        match Object::parse(path, bytes, &mut outputs) {
+            Ok(object) => objects.push(object),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // a global definition overrides a weak one, two global ones are an error
+    let mut globals: BTreeMap<&str, (Location, symbol::Symbol, &str)> = BTreeMap::new();
+
+    for object in &objects {
+        for (name, symbol) in &object.symbols {
+            if symbol.bind() == symbol::BIND_LOCAL || symbol.section == symbol::INDEX_UNDEFINED {
+                continue;
+            }
+
+            let location = match object.locate(name, symbol) {
+                Ok(location) => location,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+
+            let global = symbol.bind() == symbol::BIND_GLOBAL;
+
+            match globals
+                .get(name)
+                .map(|(_, first, path)| (first.bind(), *path))
+            {
+                Some((symbol::BIND_GLOBAL, path)) if global => errors.push(Error::Duplicate(
+                    name.to_string(),
+                    path.to_string(),
+                    object.path.to_string(),
+                )),
+                Some((symbol::BIND_GLOBAL, _)) => {}
+                Some(_) if !global => {}
+                _ => {
+                    globals.insert(name, (location, *symbol, object.path));
+                }
+            }
+        }
+    }
+
+    // output section and offset of the field, kind, target and addend of each relocation
+    let mut relocations = vec![];
+
+    for object in &objects {
+        let read = |error| Error::Read(object.path.to_string(), error);
+
+        for section in &object.file.sections {
+            if section.header.kind != section::KIND_RELA {
+                continue;
+            }
+
+            // relocations of unloaded sections, such as debug information, aren't applied
+            let (output, start) = match object.locations.get(section.header.info as usize) {
+                Some(Some(location)) => *location,
+                _ => continue,
+            };
+
+            for chunk in section.data.chunks(relocation::SIZE as usize) {
+                let entry = match relocation::Relocation::from_bytes(chunk, object.file.elf.endian)
+                {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        errors.push(read(error));
+                        break;
+                    }
+                };
+
+                let kind = match entry.kind {
+                    relocation::KIND_X86_64_64 => Kind::Absolute64,
+                    relocation::KIND_X86_64_PC32 => Kind::Relative32,
+                    // every function is linked in, so calls go straight to it
+                    relocation::KIND_X86_64_PLT32 => Kind::Plt32,
+                    // addresses are below 2 GiB, where sign and zero extension agree
+                    relocation::KIND_X86_64_32 | relocation::KIND_X86_64_32S => Kind::Absolute32,
+                    kind => {
+                        errors.push(Error::Relocation(object.path.to_string(), kind));
+                        continue;
+                    }
+                };
+
+                let (name, symbol) = match object.symbols.get(entry.symbol as usize) {
+                    Some(symbol) => symbol,
+                    None => {
+                        errors.push(read(reader::Error::Bounds("relocation symbol")));
+                        continue;
+                    }
+                };
+
+                let target = if symbol.bind() == symbol::BIND_LOCAL {
+                    object.locate(name, symbol)
+                } else {
+                    globals
+                        .get(name)
+                        .map(|(location, ..)| *location)
+                        .ok_or(Error::Undefined(object.path.to_string(), name.to_string()))
+                };
+
+                match target {
+                    Ok(target) => {
+                        relocations.push((output, start + entry.offset, kind, target, entry.addend))
+                    }
+                    Err(error) if !errors.contains(&error) => errors.push(error),
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+
+    let entry = globals.get("_start").map(|(location, ..)| *location);
+
+    if entry.is_none() {
+        errors.push(Error::Entry);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut layout = Layout::new();
+    let mut ids = [None; 4];
+
+    for (index, output) in outputs.iter().enumerate() {
+        if !output.used {
+            continue;
+        }
+
+        let mut section = layout::Section::new(output.name);
+
+        section.flags(output.flags).align(output.align);
+
+        if index == BSS {
+            section.nobits(output.len);
+        } else {
+            section.data(output.data.clone());
+        }
+
+        ids[index] = Some(layout.push_section(section));
+    }
+
+    // `.bss` follows `.data` in the same writable segment
+    let segments: [(&[usize], u32); 3] = [
+        (&[TEXT], program::FLAG_READ | program::FLAG_EXEC),
+        (&[RODATA], program::FLAG_READ),
+        (&[DATA, BSS], program::FLAG_READ | program::FLAG_WRITE),
+    ];
+
+    for (outputs, flags) in &segments {
+        let mut segment = layout::Segment::new(program::KIND_LOAD);
+        let mut used = false;
+
+        segment.flags(*flags);
+
+        for id in outputs.iter().filter_map(|&output| ids[output]) {
+            segment.section(id);
+            used = true;
+        }
+
+        if used {
+            layout.push_segment(segment);
+        }
+    }
+
+    for (output, offset, kind, (target, target_offset), addend) in relocations {
+        let mut relocation = layout::Relocation::new(
+            ids[output].unwrap(),
+            offset,
+            kind,
+            Target::Section(ids[target].unwrap()),
+        );
+
+        relocation.addend(target_offset as i64 + addend);
+        layout.push_relocation(relocation);
+    }
+
+    for object in &objects {
+        for (name, symbol) in &object.symbols {
+            let kind = symbol.kind();
+
+            if symbol.bind() != symbol::BIND_LOCAL
+                || name.is_empty()
+                || kind == symbol::KIND_SECTION
+                || kind == symbol::KIND_FILE
+            {
+                continue;
+            }
+
+            if let Ok((output, offset)) = object.locate(name, symbol) {
+                let mut local = layout::Symbol::new(name, ids[output].unwrap());
+
+                local.kind(kind).offset(offset).len(symbol.len);
+                layout.push_symbol(local);
+            }
+        }
+    }
+
+    for (name, ((output, offset), symbol, _)) in &globals {
+        let mut global = layout::Symbol::new(name, ids[*output].unwrap());
+
+        global
+            .bind(symbol::BIND_GLOBAL)
+            .kind(symbol.kind())
+            .offset(*offset)
+            .len(symbol.len);
+        layout.push_symbol(global);
+    }
+
+    let (output, offset) = entry.unwrap();
+
+    layout.entry(ids[output].unwrap(), offset);
+
+    let mut elf = Elf::new();
+
+    elf.class64()
+        .machine_x86_64()
+        .header64()
+        .endian_little()
+        .version(1)
+        .abi_sysv()
+        .abi_version()
+        .kind_exec()
+        .version2()
+        .flags(0);
+
+    Ok(layout.write(&mut elf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decoder::Decoder;
+    use super::super::diagnostic::Diagnostics;
+    use super::super::encoder::{Instruction, Mnemonic, Mode, Operand};
+    use super::super::{compile, Options};
+    use super::*;
+
+    /// `_start` exits with the address of `VALUE`, which another object defines.
+    const START: &str = "extern static VALUE: u64;\n\
+                         fn _start() {\n    sys::syscall(60, VALUE)\n}\n";
+
+    /// `helper` returns 7.
+    const LIBRARY: &str = "fn helper() -> u64 {\n    return 7\n}\n";
+
+    /// `_start` exits with what `helper`, which another object defines, returns.
+    const CALLER: &str = "extern \"C\" fn helper();\n\
+                          fn _start() {\n    sys::syscall(60, helper())\n}\n";
+
+    /// Compiles `input` with `-c`.
+    fn object(input: &str) -> Vec<u8> {
+        let options = Options {
+            mode: Mode::Long,
+            object: true,
+            pie: false,
+            debug: false,
+        };
+        let mut diagnostics = Diagnostics::new();
+        let mut compiled = compile("test.em", input, options, &mut diagnostics)
+            .unwrap_or_else(|| panic!("{}", diagnostics.render("test.em", input)));
+
+        compiled.layout.write(&mut compiled.elf)
+    }
+
+    /// An x86-64 object with `.data` holding `data`, as `as` would write it.
+    fn data_object(data: Vec<u8>, build: impl FnOnce(&mut Layout, usize)) -> Vec<u8> {
+        let mut layout = Layout::new();
+        let mut section = layout::Section::new(".data");
+
+        section
+            .kind(section::KIND_PROGBITS)
+            .flags(section::FLAG_ALLOC | section::FLAG_WRITE)
+            .align(8)
+            .data(data);
+
+        let id = layout.push_section(section);
+
+        build(&mut layout, id);
+        layout.relocatable();
+
+        let mut elf = Elf::new();
+
+        elf.class64()
+            .machine_x86_64()
+            .header64()
+            .endian_little()
+            .version(1)
+            .abi_sysv()
+            .abi_version()
+            .kind_rel()
+            .version2()
+            .flags(0);
+
+        layout.write(&mut elf)
+    }
+
+    /// Defines `VALUE`, holding 5.
+    fn value_object() -> Vec<u8> {
+        data_object(5u64.to_le_bytes().to_vec(), |layout, data| {
+            let mut value = layout::Symbol::new("VALUE", data);
+
+            value
+                .kind(symbol::KIND_OBJECT)
+                .bind(symbol::BIND_GLOBAL)
+                .len(8);
+            layout.push_symbol(value);
+        })
+    }
+
+    /// Kind of each relocation in an object.
+    fn relocations(bytes: &[u8]) -> Vec<u32> {
+        let file = File::parse(bytes).unwrap();
+
+        file.sections
+            .iter()
+            .filter(|section| section.header.kind == section::KIND_RELA)
+            .flat_map(|section| section.data.chunks(relocation::SIZE as usize))
+            .map(|chunk| {
+                relocation::Relocation::from_bytes(chunk, file.elf.endian)
+                    .unwrap()
+                    .kind
+            })
+            .collect()
+    }
+
+    /// Address of a symbol in a linked executable.
+    fn address(file: &File, name: &str) -> u64 {
+        let table = file
+            .sections
+            .iter()
+            .find(|section| section.header.kind == section::KIND_SYMTAB)
+            .unwrap();
+        let names = file.sections[table.header.link as usize].data;
+
+        table
+            .data
+            .chunks(symbol::SIZE as usize)
+            .map(|chunk| symbol::Symbol::from_bytes(chunk, file.elf.endian).unwrap())
+            .find(|symbol| {
+                names[symbol.name as usize..]
+                    .split(|&byte| byte == 0)
+                    .next()
+                    == Some(name.as_bytes())
+            })
+            .unwrap_or_else(|| panic!("no symbol `{}`", name))
+            .value
+    }
+
+    /// Address and decoded form of each instruction in `.text`.
+    fn instructions(file: &File) -> Vec<(u64, Instruction, usize)> {
+        let text = file.section(".text").unwrap();
+
+        Decoder::with_mode(text.data, Mode::Long)
+            .map(|(offset, result)| {
+                let (instruction, len) = result.unwrap();
+
+                (text.header.address + offset as u64, instruction, len)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn relative_reference_across_objects() {
+        let start = object(START);
+
+        assert_eq!(relocations(&start), [relocation::KIND_X86_64_PC32]);
+
+        let value = value_object();
+        let bytes = link(&[("start.o", &start), ("value.o", &value)]).unwrap();
+        let file = File::parse(&bytes).unwrap();
+
+        // ET_EXEC
+        assert_eq!(file.elf.kind, 2);
+        assert_eq!(file.elf.entry_address, address(&file, "_start"));
+
+        let references: Vec<u64> = instructions(&file)
+            .into_iter()
+            .filter_map(
+                |(address, instruction, len)| match instruction.operands[..] {
+                    [_, Operand::Memory(memory)] if memory.rip => {
+                        Some((address + len as u64).wrapping_add(memory.displacement as u64))
+                    }
+                    _ => None,
+                },
+            )
+            .collect();
+
+        assert_eq!(references, [address(&file, "VALUE")]);
+        assert_eq!(
+            file.section(".data").unwrap().data,
+            5u64.to_le_bytes().as_slice()
+        );
+    }
+
+    #[test]
+    fn absolute_reference_across_objects() {
+        let start = object(START);
+        let value = value_object();
+
+        // `.quad _start`
+        let pointer = data_object(vec![0; 8], |layout, data| {
+            let entry = layout.push_symbol(layout::Symbol::undefined("_start"));
+
+            layout.push_relocation(layout::Relocation::new(
+                data,
+                0,
+                Kind::Absolute64,
+                Target::Symbol(entry),
+            ));
+        });
+
+        assert_eq!(relocations(&pointer), [relocation::KIND_X86_64_64]);
+
+        let inputs = [
+            ("start.o", start.as_slice()),
+            ("value.o", &value),
+            ("pointer.o", &pointer),
+        ];
+        let bytes = link(&inputs).unwrap();
+        let file = File::parse(&bytes).unwrap();
+        let data = &file.section(".data").unwrap().data;
+
+        assert_eq!(data[8..], address(&file, "_start").to_le_bytes());
+    }
+
+    #[test]
+    fn duplicate_symbol() {
+        let start = object(START);
+        let value = value_object();
+
+        assert_eq!(
+            link(&[("a.o", &start), ("b.o", &start), ("value.o", &value)]),
+            Err(vec![Error::Duplicate(
+                "_start".to_string(),
+                "a.o".to_string(),
+                "b.o".to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn undefined_symbol() {
+        let start = object(START);
+
+        assert_eq!(
+            link(&[("start.o", &start)]),
+            Err(vec![Error::Undefined(
+                "start.o".to_string(),
+                "VALUE".to_string()
+            )])
+        );
+    }
+
+    /// The documented way to link objects built with `-c`, one of them defining `_start`.
+    #[test]
+    fn call_across_objects() {
+        let library = object(LIBRARY);
+        let caller = object(CALLER);
+
+        assert_eq!(relocations(&caller), [relocation::KIND_X86_64_PLT32]);
+
+        let bytes = link(&[("library.o", &library), ("caller.o", &caller)]).unwrap();
+        let file = File::parse(&bytes).unwrap();
+
+        assert_eq!(file.elf.entry_address, address(&file, "_start"));
+
+        let calls: Vec<u64> = instructions(&file)
+            .into_iter()
+            .filter(|(_, instruction, _)| instruction.mnemonic == Mnemonic::call)
+            .map(
+                |(address, instruction, len)| match instruction.operands[..] {
+                    [Operand::Relative(relative)] => {
+                        (address + len as u64).wrapping_add(relative as u64)
+                    }
+                    _ => panic!("call without a relative target"),
+                },
+            )
+            .collect();
+
+        assert_eq!(calls, [address(&file, "helper")]);
+    }
+
+    #[test]
+    fn missing_entry_point() {
+        // objects leave `_start` to whatever they're linked with
+        let library = object(LIBRARY);
+
+        assert_eq!(link(&[("library.o", &library)]), Err(vec![Error::Entry]));
+        assert!(Error::Entry.note().unwrap().contains("fn _start()"));
+    }
+}
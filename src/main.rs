@@ -1,309 +1,621 @@
 #![feature(array_methods)]
 #![feature(const_mut_refs)]
 
+pub mod assembler;
+pub mod codegen;
+pub mod decoder;
+pub mod diagnostic;
+pub mod dwarf;
+pub mod dynamic;
 pub mod elf;
+pub mod encoder;
+pub mod layout;
 pub mod lexer;
+pub mod linker;
 pub mod op;
 pub mod parser;
+pub mod plt;
 pub mod program;
+pub mod reader;
+pub mod relocation;
 pub mod section;
-
-use elf::Elf;
+pub mod span;
+pub mod symbol;
+pub mod writer;
+
+use assembler::Assembled;
+use codegen::Code;
+use diagnostic::{Diagnostics, Severity};
+use elf::{Class, Elf};
+use encoder::{Mode, Syntax};
+use layout::{Layout, Target};
 use lexer::Lexer;
-use op::{Op, Opcode};
-use parser::{Argument, Parser};
-use std::{env, fs};
+use op::Opcode;
+use parser::Parser;
+use reader::File;
+use span::{Lines, Span};
+use std::{env, fs, process};
+
+/// Prints the headers of an ELF file and disassembles its `.text` section.
+fn disassemble(path: &str, syntax: Syntax) {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            eprintln!(
+                "{}: couldn't read `{path}`: {error}",
+                Severity::Error.display()
+            );
+            process::exit(1);
+        }
+    };
 
-fn main() {
-    let path = env::args().nth(1).unwrap();
-    let code = fs::read_to_string(path).unwrap();
+    let file = match File::parse(&bytes) {
+        Ok(file) => file,
+        Err(error) => {
+            eprintln!(
+                "{}: `{path}`: {}",
+                Severity::Error.display(),
+                error.message()
+            );
+            process::exit(1);
+        }
+    };
 
+    println!("entry {:#x}", file.elf.entry_address);
     println!();
 
-    let mut lexer = Lexer::new(&code);
+    for header in &file.programs {
+        println!(
+            "segment {:#010x} offset {:#08x} address {:#010x} file {:#08x} memory {:#08x} flags {}",
+            header.kind,
+            header.offset,
+            header.virtual_address,
+            header.file_size,
+            header.memory_size,
+            header.flags
+        );
+    }
 
-    for lexme in lexer {
-        print!("{}", lexme.display());
+    println!();
+
+    for section in &file.sections {
+        println!(
+            "section {:20} kind {:2} address {:#010x} offset {:#08x} len {:#08x}",
+            section.name,
+            section.header.kind,
+            section.header.address,
+            section.header.offset,
+            section.header.len
+        );
     }
 
+    let text = match file.section(".text") {
+        Some(text) => text,
+        None => return,
+    };
+
+    let mode = match file.elf.class {
+        Class::Class32 => Mode::Protected,
+        Class::Class64 => Mode::Long,
+    };
+
     println!();
 
-    let mut parser = Parser::new(&code);
-    let source = parser.parse();
-    let mut bytes = vec![];
-    let mut elf = Elf::new();
+    for (offset, result) in decoder::Decoder::with_mode(text.data, mode) {
+        let address = text.header.address + offset as u64;
 
-    let lea = Opcode::load_effective_address(69);
+        match result {
+            Ok((instruction, len)) => {
+                print!("{address:8x}:  ");
 
-    //println!("{:02x?}", lea);
+                for byte in &text.data[offset..offset + len] {
+                    print!("{byte:02x?} ");
+                }
 
-    elf.class64()
-        .endian_little()
-        .version(1)
-        .abi_sysv()
-        .abi_version()
-        .kind_exec()
-        .machine_x86_64()
-        .version2()
-        .entry_address(0x201120u64)
-        .program_headers_address(64)
-        .section_headers_address(352)
-        .flags(0)
-        .header64()
-        .program_size(56)
-        .program_len(4)
-        .section_size(64)
-        .section_len(3)
-        .section_index(2);
-
-    //println!("{:?} -> {:?}", &elf, elf.to_array());
-
-    bytes.extend(elf.to_array().as_slice());
-
-    let mut header = program::Header::new();
-
-    header
-        .kind(6)
-        .flags(1 << 2)
-        .offset(0x0000000000000040)
-        .file_size(0x00000000000000e0)
-        .virtual_address(0x0000000000200040)
-        .memory_size(0x00000000000000e0)
-        .physical_address(0x0000000000200040)
-        .align(0x8);
-
-    //println!("{:?} -> {:?}", &header, header.to_array());
-
-    bytes.extend(header.to_array().as_slice());
-
-    let mut header = program::Header::new();
-
-    header
-        .kind(1)
-        .flags(1 << 2)
-        .offset(0x0000000000000000)
-        .file_size(0x0000000000000120)
-        .virtual_address(0x0000000000200000)
-        .memory_size(0x0000000000000120)
-        .physical_address(0x0000000000200000)
-        .align(0x1000);
-
-    //println!("{:?} -> {:?}", &header, header.to_array());
-
-    bytes.extend(header.to_array().as_slice());
-
-    let mut header = program::Header::new();
-
-    header
-        .kind(1)
-        .flags(1 | (1 << 2))
-        .offset(0x0000000000000120)
-        .file_size(0x000000000000002c)
-        .virtual_address(0x0000000000201120)
-        .memory_size(0x000000000000002c)
-        .physical_address(0x0000000000201120)
-        .align(0x1000);
-
-    //println!("{:?} -> {:?}", &header, header.to_array());
-
-    bytes.extend(header.to_array().as_slice());
-
-    let mut header = program::Header::new();
-
-    header.kind(0x6474_e551).flags((1 << 1) | (1 << 2));
-
-    //println!("{:?} -> {:?}", &header, header.to_array());
-
-    bytes.extend(header.to_array().as_slice());
-
-    #[derive(Debug, Clone, Eq, PartialEq)]
-    pub enum Intermediate {
-        lea64_str_rdi_rsi(usize),
-        mov64_int_rax(u8),
-        mov64_int_rdi(u8),
-        mov64_rax_rdi,
-        pop64_rax,
-        pop64_rdx,
-        push64(u8),
-        syscall,
-        xor64_rdi_rdi,
-    }
+                for _ in len..10 {
+                    print!("   ");
+                }
 
-    impl Intermediate {
-        pub fn len(&self) -> usize {
-            use Intermediate::*;
-
-            match self {
-                lea64_str_rdi_rsi(_) => 7,
-                mov64_int_rax(_) => 7,
-                mov64_int_rdi(_) => 7,
-                mov64_rax_rdi => 3,
-                pop64_rax => 1,
-                pop64_rdx => 1,
-                push64(_) => 2,
-                syscall => 2,
-                xor64_rdi_rdi => 3,
+                println!("{}", instruction.display_syntax(syntax, mode));
             }
+            Err(error) => println!(
+                "{address:8x}:  {:02x?}                            ({})",
+                text.data[offset],
+                error.message()
+            ),
         }
     }
+}
 
-    #[derive(Debug)]
-    pub struct Code {
-        pub ops: Vec<Intermediate>,
-        pub strings: Vec<String>,
+/// Links relocatable objects into `bin.elf`.
+fn link(paths: &[String]) {
+    let mut inputs = vec![];
+
+    for path in paths {
+        match fs::read(path) {
+            Ok(bytes) => inputs.push((path.as_str(), bytes)),
+            Err(error) => {
+                eprintln!(
+                    "{}: couldn't read `{path}`: {error}",
+                    Severity::Error.display()
+                );
+                process::exit(1);
+            }
+        }
     }
 
-    let mut code = Code {
-        ops: vec![],
-        strings: vec![],
-    };
-
-    for function in &source.functions {
-        for syscall in &function.body {
-            let mut reqstack = false;
+    let inputs: Vec<(&str, &[u8])> = inputs
+        .iter()
+        .map(|(path, bytes)| (*path, bytes.as_slice()))
+        .collect();
 
-            match &syscall.arg0 {
-                Argument::String(_) => reqstack = true,
-                _ => {}
-            }
+    let bytes = match linker::link(&inputs) {
+        Ok(bytes) => bytes,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}: {}", Severity::Error.display(), error.message());
 
-            if reqstack {
-                code.ops.push(Intermediate::push64(syscall.id as u8));
-                code.ops.push(Intermediate::pop64_rax);
-            } else {
-                code.ops.push(Intermediate::mov64_int_rax(syscall.id as u8));
-            }
-
-            match &syscall.arg0 {
-                Argument::U64(int) => {
-                    if reqstack {
-                        code.ops.push(Intermediate::push64(*int as u8));
-                        code.ops.push(Intermediate::pop64_rax);
-                    } else {
-                        if *int == 0 {
-                            code.ops.push(Intermediate::xor64_rdi_rdi);
-                        } else {
-                            code.ops.push(Intermediate::mov64_int_rdi(*int as u8));
-                        }
-                    }
-                }
-                Argument::String(string) => {
-                    code.ops
-                        .push(Intermediate::lea64_str_rdi_rsi(code.strings.len()));
-                    code.strings
-                        .push(string[1..string.len() - 1].replace("\\n", "\n").to_string());
+                if let Some(note) = error.note() {
+                    eprintln!("{}: {note}", Severity::Note.display());
                 }
-                _ => {}
             }
 
-            code.ops.push(Intermediate::syscall);
+            process::exit(1);
         }
+    };
+
+    if let Err(error) = fs::write("bin.elf", &bytes[..]) {
+        eprintln!(
+            "{}: couldn't write `bin.elf`: {error}",
+            Severity::Error.display()
+        );
+        process::exit(1);
     }
+}
 
-    use std::collections::BTreeMap;
+fn main() {
+    let mut syntax = Syntax::Att;
+    let mut mode = Mode::Long;
+    let mut object = false;
+    let mut pie = false;
+    let mut debug = false;
+    let mut disassemble = false;
+    let mut link = false;
+    let mut paths = vec![];
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--intel" => syntax = Syntax::Intel,
+            "--att" => syntax = Syntax::Att,
+            "-m32" => mode = Mode::Protected,
+            "-m64" => mode = Mode::Long,
+            "-c" => object = true,
+            "--pie" => pie = true,
+            "-g" => debug = true,
+            "--disassemble" | "-d" => disassemble = true,
+            "--link" => link = true,
+            _ => paths.push(arg),
+        }
+    }
 
-    let mut map = BTreeMap::new();
-    let mut off = 0;
+    if link && !paths.is_empty() {
+        return self::link(&paths);
+    }
 
-    for op in &code.ops {
-        map.insert(off, op);
+    let path = match paths.pop() {
+        Some(path) => path,
+        None => {
+            eprintln!("{}: no input file", Severity::Error.display());
+            eprintln!("usage: empiric [--att | --intel] [-m32 | -m64] [-c | --pie] [-g] <file.em>");
+            eprintln!("       empiric [--att | --intel] --disassemble <file.elf>");
+            eprintln!("       empiric --link <file.o>...");
+            process::exit(1);
+        }
+    };
 
-        off += op.len();
+    if disassemble {
+        return self::disassemble(&path, syntax);
     }
 
-    let mut coden = vec![];
+    // i386 code refers to data by absolute address
+    if pie && mode == Mode::Protected {
+        eprintln!(
+            "{}: position-independent executables need `-m64`",
+            Severity::Error.display()
+        );
+        process::exit(1);
+    }
 
-    for (o, op) in map {
-        match op {
-            Intermediate::lea64_str_rdi_rsi(i) => {
-                coden.push(Op::push64((code.strings[*i].len() + 1) as u8));
-                coden.push(Op::pop64_rdx);
-                coden.push(Op::lea64_rip_rsi((off - o - 5) as u8));
-                coden.push(Op::mov64_rax_rdi);
-            }
-            Intermediate::mov64_int_rax(n) => {
-                coden.push(Op::mov64_int_rax(*n));
-            }
-            Intermediate::mov64_int_rdi(n) => {
-                coden.push(Op::mov64_int_rdi(*n));
-            }
-            Intermediate::mov64_rax_rdi => {
-                coden.push(Op::mov64_rax_rdi);
-            }
-            Intermediate::pop64_rax => {
-                coden.push(Op::pop64_rax);
-            }
-            Intermediate::pop64_rdx => {
-                coden.push(Op::pop64_rdx);
-            }
-            Intermediate::push64(n) => {
-                coden.push(Op::push64(*n));
-            }
-            Intermediate::syscall => {
-                coden.push(Op::syscall);
-            }
-            Intermediate::xor64_rdi_rdi => {
-                coden.push(Op::xor64_rdi_rdi);
-            }
+    let input = match fs::read_to_string(&path) {
+        Ok(input) => input,
+        Err(error) => {
+            eprintln!(
+                "{}: couldn't read `{path}`: {error}",
+                Severity::Error.display()
+            );
+            process::exit(1);
         }
+    };
+
+    println!();
+
+    let lexer = Lexer::new(&input);
+
+    for token in lexer {
+        print!("{}", token.lexme.display());
     }
 
-    for op in coden {
-        let bytes2 = op.to_bytes();
+    println!();
+
+    let options = Options {
+        mode,
+        object,
+        pie,
+        debug,
+    };
+    let mut diagnostics = Diagnostics::new();
+    let compiled = compile(&path, &input, options, &mut diagnostics);
+
+    eprint!("{}", diagnostics.render(&path, &input));
+
+    let Compiled {
+        mut elf,
+        mut layout,
+        assembled,
+        text,
+    } = match compiled {
+        Some(compiled) => compiled,
+        None => process::exit(1),
+    };
+
+    // arranges and relocates the layout, so the listing shows what the cpu will see
+    let bytes = layout.write(&mut elf);
+
+    for line in &assembled.lines {
+        if line.instruction.is_none() {
+            continue;
+        }
+
+        let bytes = &layout.data(text)[line.offset..line.offset + line.len];
 
-        for byte in &bytes2 {
+        for byte in bytes {
             print!("{byte:02x?} ");
         }
 
-        for _ in bytes2.len()..7 {
+        for _ in line.len..10 {
             print!("   ");
         }
 
-        println!("{}", op.display());
+        match decoder::decode_mode(bytes, mode) {
+            Ok((instruction, _len)) => println!("{}", instruction.display_syntax(syntax, mode)),
+            Err(error) => println!("{}", error.message()),
+        }
+    }
+
+    let output = if object { "bin.o" } else { "bin.elf" };
+
+    //println!("{bytes:02x?}");
 
-        bytes.extend(bytes2);
+    if let Err(error) = fs::write(output, &bytes[..]) {
+        eprintln!(
+            "{}: couldn't write `{output}`: {error}",
+            Severity::Error.display()
+        );
+        process::exit(1);
     }
+}
+
+/// What `-c`, `--pie`, `-m32` and `-g` ask of `compile`.
+#[derive(Clone, Copy, Debug)]
+struct Options {
+    mode: Mode,
+    /// a relocatable object rather than an executable
+    object: bool,
+    pie: bool,
+    debug: bool,
+}
 
-    for string in &code.strings {
-        bytes.extend(string.as_bytes());
+/// A compiled program or object, ready to be written, with the code its listing is decoded
+/// from.
+struct Compiled {
+    elf: Elf,
+    layout: Layout,
+    assembled: Assembled,
+    /// the `.text` section
+    text: usize,
+}
+
+/// Compiles a source file, reporting errors to `diagnostics`.
+fn compile(
+    path: &str,
+    input: &str,
+    options: Options,
+    diagnostics: &mut Diagnostics,
+) -> Option<Compiled> {
+    let Options {
+        mode,
+        object,
+        pie,
+        debug,
+    } = options;
+
+    let mut parser = Parser::new(input);
+    let source = parser.parse();
+
+    diagnostics.extend(parser.take_diagnostics());
+    let mut elf = Elf::new();
+
+    let lea = Opcode::load_effective_address(69);
+
+    //println!("{:02x?}", lea);
+
+    match mode {
+        Mode::Long => elf.class64().machine_x86_64().header64(),
+        Mode::Protected => elf.class32().machine_i386().header32(),
+    };
+
+    elf.endian_little()
+        .version(1)
+        .abi_sysv()
+        .abi_version()
+        .version2()
+        .flags(0);
+
+    if object {
+        elf.kind_rel();
+    } else if pie {
+        elf.kind_dyn();
+    } else {
+        elf.kind_exec();
     }
 
-    bytes.extend(b"\0\0");
-    bytes.extend(b".text\0");
-    bytes.extend(b".shstrtab\0");
-    bytes.extend(b"\0\0\0");
+    let mut code = Code::new();
 
-    let mut section_header = section::Header::new();
+    if object {
+        code.relocatable();
+    }
+
+    code.mode(mode).lower(&source, diagnostics);
 
-    bytes.extend(section_header.to_array().as_slice());
+    let assembled = code.assemble(diagnostics);
+
+    if diagnostics.has_errors() {
+        return None;
+    }
 
-    // progbits
-    let mut section_header = section::Header::new();
+    let mut layout = Layout::new();
 
-    section_header
-        .name(1)
-        .len(0x2c)
-        .kind(1)
+    if object {
+        layout.relocatable();
+    } else if pie {
+        layout.base(0);
+    }
+
+    let mut section = layout::Section::new(".text");
+
+    section
+        .kind(section::KIND_PROGBITS)
+        .flags(section::FLAG_ALLOC | section::FLAG_EXEC)
         .align(16)
-        .offset(0x00000120u64)
-        .address(0x0000000000201120u64)
-        .flags(0x2 | 0x4);
+        .data(assembled.bytes.clone());
 
-    bytes.extend(section_header.to_array().as_slice());
+    let text = layout.push_section(section);
+    let mut segment = layout::Segment::new(program::KIND_LOAD);
 
-    // strtab
-    let mut section_header = section::Header::new();
+    segment
+        .flags(program::FLAG_READ | program::FLAG_EXEC)
+        .section(text);
 
-    section_header
-        .name(7)
-        .len(0x11)
-        .kind(3)
-        .align(1)
-        .offset(0x0000014Cu64);
+    layout.push_segment(segment);
 
-    bytes.extend(section_header.to_array().as_slice());
+    layout.entry(text, 0);
 
-    //println!("{bytes:02x?}");
+    // an empty literal or static still needs an address, so check those rather than the bytes
+    let has_statics = |target| code.statics.iter().any(|(_, kind, ..)| *kind == target);
+    let rodata = if code.strings.is_empty() && !has_statics(codegen::RODATA) {
+        None
+    } else {
+        let mut section = layout::Section::new(".rodata");
+
+        section
+            .kind(section::KIND_PROGBITS)
+            .flags(section::FLAG_ALLOC)
+            .align(16)
+            .data(code.rodata.clone());
+
+        let rodata = layout.push_section(section);
+        let mut segment = layout::Segment::new(program::KIND_LOAD);
+
+        segment.flags(program::FLAG_READ).section(rodata);
+        layout.push_segment(segment);
+
+        Some(rodata)
+    };
+
+    let data = if has_statics(codegen::DATA) {
+        let mut section = layout::Section::new(".data");
+
+        section
+            .kind(section::KIND_PROGBITS)
+            .flags(section::FLAG_ALLOC | section::FLAG_WRITE)
+            .align(16)
+            .data(code.data.clone());
+
+        Some(layout.push_section(section))
+    } else {
+        None
+    };
+
+    let bss = if has_statics(codegen::BSS) {
+        let mut section = layout::Section::new(".bss");
+
+        section
+            .flags(section::FLAG_ALLOC | section::FLAG_WRITE)
+            .align(16)
+            .nobits(code.bss);
+
+        Some(layout.push_section(section))
+    } else {
+        None
+    };
+
+    // `.bss` follows `.data` in the same writable segment, past the end of the file
+    if data.is_some() || bss.is_some() {
+        let mut segment = layout::Segment::new(program::KIND_LOAD);
+
+        segment.flags(program::FLAG_READ | program::FLAG_WRITE);
+
+        for section in data.iter().chain(&bss) {
+            segment.section(*section);
+        }
+
+        layout.push_segment(segment);
+    }
+
+    // an object's counterpart of `PT_GNU_STACK`, asking the linker for a non-executable stack
+    if object {
+        layout.push_section(layout::Section::new(".note.GNU-stack"));
+    }
+
+    // outside of objects, every external is an import since external statics are an error,
+    // and calls go through its PLT entry
+    let externs: Vec<usize> = if object {
+        code.externs
+            .iter()
+            .map(|name| layout.push_symbol(layout::Symbol::undefined(name)))
+            .collect()
+    } else if pie || !code.externs.is_empty() {
+        plt::push(&mut layout, &code.externs, pie, elf.endian)
+    } else {
+        vec![]
+    };
+
+    for reference in &assembled.relocations {
+        let target = match reference.target {
+            codegen::RODATA => Target::Section(rodata.unwrap()),
+            codegen::DATA => Target::Section(data.unwrap()),
+            codegen::BSS => Target::Section(bss.unwrap()),
+            target => Target::Symbol(externs[target - codegen::EXTERN]),
+        };
+
+        let mut relocation =
+            layout::Relocation::new(text, reference.offset as u64, reference.kind, target);
+
+        relocation.addend(reference.addend);
+        layout.push_relocation(relocation);
+    }
+
+    for (name, _, start, _, end) in &code.functions {
+        let start = assembled.offset(*start) as u64;
+        let end = assembled.offset(*end) as u64;
+        // an object's functions are for other objects to call
+        let bind = if object || name == "entry" {
+            symbol::BIND_GLOBAL
+        } else {
+            symbol::BIND_LOCAL
+        };
+        let mut symbol = layout::Symbol::new(name, text);
+
+        symbol
+            .kind(symbol::KIND_FUNC)
+            .bind(bind)
+            .offset(start)
+            .len(end - start);
+
+        layout.push_symbol(symbol);
+    }
+
+    for (index, (offset, string)) in code.strings.iter().enumerate() {
+        let mut symbol = layout::Symbol::new(&format!("str.{index}"), rodata.unwrap());
+
+        symbol
+            .kind(symbol::KIND_OBJECT)
+            .offset(*offset)
+            .len(string.len() as u64);
+
+        layout.push_symbol(symbol);
+    }
+
+    for (name, target, offset, len) in &code.statics {
+        let section = match *target {
+            codegen::RODATA => rodata.unwrap(),
+            codegen::DATA => data.unwrap(),
+            codegen::BSS => bss.unwrap(),
+            // already added as undefined symbols
+            _ => continue,
+        };
+        let mut symbol = layout::Symbol::new(name, section);
+
+        symbol.kind(symbol::KIND_OBJECT).offset(*offset).len(*len);
+        layout.push_symbol(symbol);
+    }
+
+    if debug {
+        let directory = env::current_dir()
+            .map(|directory| directory.display().to_string())
+            .unwrap_or_default();
+        let lines = Lines::new(input);
+        let line = |span: Span| lines.location(span.start).line as u64;
+        let mut info = dwarf::Debug::new(path, &directory);
+
+        for line in &assembled.lines {
+            let location = lines.location(line.span.start);
+
+            info.row(
+                line.offset as u64,
+                location.line as u64,
+                location.column as u64,
+            );
+        }
+
+        for ((name, span, start, prologue, end), locals) in code.functions.iter().zip(&code.locals)
+        {
+            info.function(
+                name,
+                line(*span),
+                assembled.offset(*start) as u64,
+                assembled.offset(*prologue) as u64,
+                assembled.offset(*end) as u64,
+                object || name == "entry",
+            );
+
+            for (name, span, kind, offset) in locals {
+                info.local(name, line(*span), kind, *offset as i64);
+            }
+        }
+
+        for item in source.statics() {
+            let found = code.statics.iter().find(|(name, ..)| *name == item.ident);
+
+            if let Some((_, target, offset, _)) = found {
+                let section = match *target {
+                    codegen::RODATA => rodata.unwrap(),
+                    codegen::DATA => data.unwrap(),
+                    _ => bss.unwrap(),
+                };
+
+                info.variable(&item.ident, line(item.span), &item.kind, section, *offset);
+            }
+        }
+
+        info.push(&mut layout, elf.class, text, assembled.bytes.len() as u64);
+    }
+
+    // the stub calling `entry` begins `.text`, objects have none
+    if let Some(end) = code.start {
+        let mut symbol = layout::Symbol::new("_start", text);
+
+        symbol
+            .kind(symbol::KIND_FUNC)
+            .bind(symbol::BIND_GLOBAL)
+            .len(assembled.offset(end) as u64);
+        layout.push_symbol(symbol);
+    }
 
-    std::fs::write("bin.elf", &bytes[..]).unwrap();
+    Some(Compiled {
+        elf,
+        layout,
+        assembled,
+        text,
+    })
 }
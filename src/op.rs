@@ -1,56 +1,330 @@
+use super::encoder::{Instruction, Memory, Mnemonic, Mode, Operand, Size};
+
+// named like the registers and instructions they stand for
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Register {
+    rax,
+    rcx,
+    rdx,
+    rbx,
+    rsp,
+    rbp,
+    rsi,
+    rdi,
+    r8,
+    r9,
+    r10,
+    r11,
+    r12,
+    r13,
+    r14,
+    r15,
+}
+
+impl Register {
+    /// Registers used for syscall arguments, in order.
+    pub const SYSCALL: [Register; 6] = [
+        Register::rdi,
+        Register::rsi,
+        Register::rdx,
+        Register::r10,
+        Register::r8,
+        Register::r9,
+    ];
+
+    /// Registers used for function arguments by the SysV x86-64 ABI, in order.
+    pub const CALL: [Register; 6] = [
+        Register::rdi,
+        Register::rsi,
+        Register::rdx,
+        Register::rcx,
+        Register::r8,
+        Register::r9,
+    ];
+
+    /// Registers used for syscall arguments on i386, in order.
+    pub const SYSCALL32: [Register; 6] = [
+        Register::rbx,
+        Register::rcx,
+        Register::rdx,
+        Register::rsi,
+        Register::rdi,
+        Register::rbp,
+    ];
+
+    /// Callee-saved registers on i386 which syscall arguments clobber, every function saves
+    /// them.
+    pub const SAVED32: [Register; 3] = [Register::rbx, Register::rsi, Register::rdi];
+
+    /// Encoding of this register, 0 to 15.
+    pub const fn index(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Low 3 bits of the encoding, as used in ModRM and opcode bytes.
+    pub const fn low(&self) -> u8 {
+        self.index() & 0b111
+    }
+
+    /// Whether this register needs a REX extension bit.
+    pub const fn is_extended(&self) -> bool {
+        self.index() >= 8
+    }
+
+    /// Name of the low 8 bits of this register.
+    pub fn name8(&self) -> &'static str {
+        use Register::*;
+
+        match self {
+            rax => "al",
+            rcx => "cl",
+            rdx => "dl",
+            rbx => "bl",
+            rsp => "spl",
+            rbp => "bpl",
+            rsi => "sil",
+            rdi => "dil",
+            r8 => "r8b",
+            r9 => "r9b",
+            r10 => "r10b",
+            r11 => "r11b",
+            r12 => "r12b",
+            r13 => "r13b",
+            r14 => "r14b",
+            r15 => "r15b",
+        }
+    }
+
+    /// Name of the low 16 bits of this register.
+    pub fn name16(&self) -> &'static str {
+        use Register::*;
+
+        match self {
+            rax => "ax",
+            rcx => "cx",
+            rdx => "dx",
+            rbx => "bx",
+            rsp => "sp",
+            rbp => "bp",
+            rsi => "si",
+            rdi => "di",
+            r8 => "r8w",
+            r9 => "r9w",
+            r10 => "r10w",
+            r11 => "r11w",
+            r12 => "r12w",
+            r13 => "r13w",
+            r14 => "r14w",
+            r15 => "r15w",
+        }
+    }
+
+    /// Name of the low 32 bits of this register.
+    pub fn name32(&self) -> &'static str {
+        use Register::*;
+
+        match self {
+            rax => "eax",
+            rcx => "ecx",
+            rdx => "edx",
+            rbx => "ebx",
+            rsp => "esp",
+            rbp => "ebp",
+            rsi => "esi",
+            rdi => "edi",
+            r8 => "r8d",
+            r9 => "r9d",
+            r10 => "r10d",
+            r11 => "r11d",
+            r12 => "r12d",
+            r13 => "r13d",
+            r14 => "r14d",
+            r15 => "r15d",
+        }
+    }
+
+    /// Name of the register accessed with the given operand size.
+    pub fn sized_name(&self, size: Size) -> &'static str {
+        match size {
+            Size::Byte => self.name8(),
+            Size::Word => self.name16(),
+            Size::Dword => self.name32(),
+            Size::Qword => self.name(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        use Register::*;
+
+        match self {
+            rax => "rax",
+            rcx => "rcx",
+            rdx => "rdx",
+            rbx => "rbx",
+            rsp => "rsp",
+            rbp => "rbp",
+            rsi => "rsi",
+            rdi => "rdi",
+            r8 => "r8",
+            r9 => "r9",
+            r10 => "r10",
+            r11 => "r11",
+            r12 => "r12",
+            r13 => "r13",
+            r14 => "r14",
+            r15 => "r15",
+        }
+    }
+}
+
+/// Whether `value` survives sign extension from 8 bits.
+pub const fn fits_i8(value: i64) -> bool {
+    value >= i8::MIN as i64 && value <= i8::MAX as i64
+}
+
+/// Whether `value` survives sign extension from 32 bits.
+pub const fn fits_i32(value: i64) -> bool {
+    value >= i32::MIN as i64 && value <= i32::MAX as i64
+}
+
+/// Whether `value` survives zero extension from 32 bits.
+pub const fn fits_u32(value: i64) -> bool {
+    value >= 0 && value <= u32::MAX as i64
+}
+
+#[allow(non_camel_case_types)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Op {
-    /// leaq <int>(%rip), %rsi
-    lea64_rip_rsi(u8),
-    /// movq <int>, %rax
-    mov64_int_rax(u8),
-    /// movq <int>, %rdi
-    mov64_int_rdi(u8),
-    /// movq %rax, %rdi
-    mov64_rax_rdi,
-    /// popq %rax
-    pop64_rax,
-    /// popq %rdx
-    pop64_rdx,
-    /// pushq <int>
-    push64(u8),
+    /// addl $<int>, %reg
+    add32_int(Register, i32),
+    /// addq $<int>, %reg
+    add64_int(Register, i32),
+    /// andl $<int>, %reg
+    and32_int(Register, i32),
+    /// andq $<int>, %reg
+    and64_int(Register, i32),
+    /// call <rel>, relative to the end of the call
+    call(i32),
+    /// int $<int>
+    int(u8),
+    /// leave, sized so it's valid in both modes
+    leave,
+    /// leaq <int>(%rip), %reg
+    lea64_rip(Register, i32),
+    /// movl %src, %dst
+    mov32(Register, Register),
+    /// movl $<int>, %reg
+    mov32_int(Register, u32),
+    /// movq $<int>, %reg
+    ///
+    /// Encoded as `movl` when the value zero extends from 32 bits, `movq` when it sign
+    /// extends from 32 bits, and `movabsq` otherwise.
+    mov64_int(Register, i64),
+    /// movq %src, %dst
+    mov64(Register, Register),
+    /// popl %reg, 32-bit mode only
+    pop32(Register),
+    /// popq %reg
+    pop64(Register),
+    /// pushl %reg, 32-bit mode only
+    push32(Register),
+    /// pushq %reg
+    push64(Register),
+    /// pushq $<int>
+    push64_int(i32),
+    /// ret, sized so it's valid in both modes
+    ret,
+    /// subl $<int>, %reg
+    sub32_int(Register, i32),
+    /// subq $<int>, %reg
+    sub64_int(Register, i32),
     /// syscall
     syscall,
-    /// xorq %rdi, %rdi
-    xor64_rdi_rdi,
+    /// xorl %src, %dst
+    xor32(Register, Register),
+    /// xorq %src, %dst
+    xor64(Register, Register),
 }
 
 impl Op {
-    pub fn to_bytes(&self) -> std::vec::Vec<u8> {
+    pub fn instruction(&self) -> Instruction {
         use Op::*;
+        use Operand::{Immediate, Memory as Mem, Register as Reg, Relative};
 
+        match *self {
+            add32_int(reg, n) => {
+                Instruction::new(Mnemonic::add, Size::Dword, &[Reg(reg), Immediate(n as i64)])
+            }
+            add64_int(reg, n) => {
+                Instruction::new(Mnemonic::add, Size::Qword, &[Reg(reg), Immediate(n as i64)])
+            }
+            and32_int(reg, n) => {
+                Instruction::new(Mnemonic::and, Size::Dword, &[Reg(reg), Immediate(n as i64)])
+            }
+            and64_int(reg, n) => {
+                Instruction::new(Mnemonic::and, Size::Qword, &[Reg(reg), Immediate(n as i64)])
+            }
+            call(n) => Instruction::new(Mnemonic::call, Size::Dword, &[Relative(n)]),
+            int(n) => Instruction::new(Mnemonic::int, Size::Byte, &[Immediate(n as i64)]),
+            leave => Instruction::new(Mnemonic::leave, Size::Dword, &[]),
+            lea64_rip(reg, n) => {
+                Instruction::new(Mnemonic::lea, Size::Qword, &[Reg(reg), Mem(Memory::rip(n))])
+            }
+            mov32(src, dst) => Instruction::new(Mnemonic::mov, Size::Dword, &[Reg(dst), Reg(src)]),
+            mov32_int(reg, n) => {
+                Instruction::new(Mnemonic::mov, Size::Dword, &[Reg(reg), Immediate(n as i64)])
+            }
+            mov64_int(reg, n) if fits_u32(n) => {
+                Instruction::new(Mnemonic::mov, Size::Dword, &[Reg(reg), Immediate(n)])
+            }
+            mov64_int(reg, n) => {
+                Instruction::new(Mnemonic::mov, Size::Qword, &[Reg(reg), Immediate(n)])
+            }
+            mov64(src, dst) => Instruction::new(Mnemonic::mov, Size::Qword, &[Reg(dst), Reg(src)]),
+            pop32(reg) => Instruction::new(Mnemonic::pop, Size::Dword, &[Reg(reg)]),
+            pop64(reg) => Instruction::new(Mnemonic::pop, Size::Qword, &[Reg(reg)]),
+            push32(reg) => Instruction::new(Mnemonic::push, Size::Dword, &[Reg(reg)]),
+            push64(reg) => Instruction::new(Mnemonic::push, Size::Qword, &[Reg(reg)]),
+            push64_int(n) => Instruction::new(Mnemonic::push, Size::Qword, &[Immediate(n as i64)]),
+            ret => Instruction::new(Mnemonic::ret, Size::Dword, &[]),
+            sub32_int(reg, n) => {
+                Instruction::new(Mnemonic::sub, Size::Dword, &[Reg(reg), Immediate(n as i64)])
+            }
+            sub64_int(reg, n) => {
+                Instruction::new(Mnemonic::sub, Size::Qword, &[Reg(reg), Immediate(n as i64)])
+            }
+            syscall => Instruction::new(Mnemonic::syscall, Size::Qword, &[]),
+            xor32(src, dst) => Instruction::new(Mnemonic::xor, Size::Dword, &[Reg(dst), Reg(src)]),
+            xor64(src, dst) => Instruction::new(Mnemonic::xor, Size::Qword, &[Reg(dst), Reg(src)]),
+        }
+    }
+
+    /// Mode the op is encoded for, 32-bit pushes and pops don't exist in 64-bit mode.
+    pub fn mode(&self) -> Mode {
         match self {
-            lea64_rip_rsi(n) => vec![0x48, 0x8D, 0x35, *n, 0x00, 0x00, 0x00],
-            mov64_int_rax(n) => vec![0x48, 0xC7, 0xC0, *n, 0x00, 0x00, 0x00],
-            mov64_int_rdi(n) => vec![0x48, 0xC7, 0xC7, *n, 0x00, 0x00, 0x00],
-            mov64_rax_rdi => vec![0x48, 0x89, 0xC7],
-            pop64_rax => vec![0x58],
-            pop64_rdx => vec![0x5A],
-            push64(n) => vec![0x6A, *n],
-            syscall => vec![0x0F, 0x05],
-            xorq_rdi_rdi => vec![0x48, 0x31, 0xFF],
+            Op::pop32(_) | Op::push32(_) => Mode::Protected,
+            _ => Mode::Long,
         }
     }
 
+    pub fn to_bytes(&self) -> std::vec::Vec<u8> {
+        // operands are typed so that every op has an encoding
+        self.instruction()
+            .encode_mode(self.mode())
+            .unwrap()
+            .as_bytes()
+            .to_vec()
+    }
+
     pub fn display(&self) -> String {
-        use Op::*;
+        self.instruction().display()
+    }
+}
 
-        match self {
-            lea64_rip_rsi(n) => format!("leaq \x1b[38;5;11m{n}\x1b[m(%rip), %rsi"),
-            mov64_int_rax(n) => format!("movq \x1b[38;5;11m${n}\x1b[m, %rax"),
-            mov64_int_rdi(n) => format!("movq \x1b[38;5;11m${n}\x1b[m, %rdi"),
-            mov64_rax_rdi => format!("movq %rax, %rdi"),
-            pop64_rax => format!("popq %rax"),
-            pop64_rdx => format!("popq %rdx"),
-            push64(n) => format!("pushq \x1b[38;5;11m${n}\x1b[m"),
-            syscall => format!("syscall"),
-            xorq_rdi_rdi => format!("xorq $rdi, $rdi"),
-        }
+impl From<Op> for Instruction {
+    fn from(op: Op) -> Instruction {
+        op.instruction()
     }
 }
 
@@ -79,6 +353,13 @@ impl Opcode {
         self.data.as_slice()
     }
 
+    /// Appends a byte, the encoder never produces more than 15.
+    pub(crate) fn push(&mut self, byte: u8) {
+        assert!(self.len() < 15, "opcode longer than 15 bytes");
+
+        unsafe { self.push_bytes_unchecked(&[byte]) }
+    }
+
     pub(crate) const unsafe fn push_bytes_unchecked(&mut self, bytes: &[u8]) {
         self.data.extend_from_slice_unchecked(bytes)
     }
@@ -1,89 +1,499 @@
-use super::lexer::{Lexer, Lexme};
+use super::diagnostic::Diagnostics;
+use super::lexer::{escape, Invalid, Lexer, Lexme, Token};
+use super::span::Span;
 
 #[derive(Clone, Debug)]
 pub enum Argument {
-    None,
     U64(u64),
     String(String),
+    /// the return value of a nested syscall
+    Syscall(Box<Syscall>),
+    /// the return value of a nested call
+    Call(Box<Call>),
+    /// the value of a local, or the address of a static
+    Name(String, Span),
+    Unary(Box<Unary>),
+    Binary(Box<Binary>),
 }
 
-impl From<Option<u64>> for Argument {
-    #[inline]
-    fn from(from: Option<u64>) -> Self {
-        match from {
-            Some(arg) => Argument::U64(arg),
-            None => Argument::None,
-        }
-    }
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnaryOp {
+    /// `-`, two's complement negation
+    Neg,
+    /// `!`, 1 if the value is 0 and 0 otherwise
+    Not,
 }
 
-impl From<Option<String>> for Argument {
-    #[inline]
-    fn from(from: Option<String>) -> Self {
-        match from {
-            Some(arg) => Argument::String(arg),
-            None => Argument::None,
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    /// The operator a token stands for, if any.
+    pub fn from_lexme(lexme: Lexme) -> Option<BinaryOp> {
+        let op = match lexme {
+            Lexme::Star => BinaryOp::Mul,
+            Lexme::Slash => BinaryOp::Div,
+            Lexme::Percent => BinaryOp::Rem,
+            Lexme::Plus => BinaryOp::Add,
+            Lexme::Minus => BinaryOp::Sub,
+            Lexme::ShiftLeft => BinaryOp::Shl,
+            Lexme::ShiftRight => BinaryOp::Shr,
+            Lexme::Ampersand => BinaryOp::BitAnd,
+            Lexme::Caret => BinaryOp::BitXor,
+            Lexme::Pipe => BinaryOp::BitOr,
+            Lexme::EqualsEquals => BinaryOp::Eq,
+            Lexme::BangEquals => BinaryOp::Ne,
+            Lexme::Less => BinaryOp::Lt,
+            Lexme::LessEquals => BinaryOp::Le,
+            Lexme::Greater => BinaryOp::Gt,
+            Lexme::GreaterEquals => BinaryOp::Ge,
+            Lexme::AndAnd => BinaryOp::And,
+            Lexme::OrOr => BinaryOp::Or,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    /// How tightly the operator binds, higher first. Every level is left associative except
+    /// comparisons, which don't chain.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 9,
+            BinaryOp::Add | BinaryOp::Sub => 8,
+            BinaryOp::Shl | BinaryOp::Shr => 7,
+            BinaryOp::BitAnd => 6,
+            BinaryOp::BitXor => 5,
+            BinaryOp::BitOr => 4,
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Le
+            | BinaryOp::Gt
+            | BinaryOp::Ge => 3,
+            BinaryOp::And => 2,
+            BinaryOp::Or => 1,
         }
     }
+
+    pub fn is_comparison(&self) -> bool {
+        self.precedence() == 3
+    }
+
+    /// Whether the result is 0 or 1 rather than an integer of the operands' type.
+    pub fn is_boolean(&self) -> bool {
+        self.precedence() <= 3
+    }
+}
+
+/// `-VALUE` or `!VALUE`.
+#[derive(Clone, Debug)]
+pub struct Unary {
+    pub op: UnaryOp,
+    pub value: Argument,
+    pub span: Span,
+}
+
+/// `LEFT OP RIGHT`.
+#[derive(Clone, Debug)]
+pub struct Binary {
+    pub op: BinaryOp,
+    pub left: Argument,
+    pub right: Argument,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug)]
 pub struct Syscall {
     pub id: u64,
-    pub arg0: Argument,
-    pub arg1: Argument,
-    pub arg2: Argument,
+    pub args: Vec<Argument>,
+    pub span: Span,
+}
+
+/// A call to a function, defined with `fn` or imported with `extern "C" fn`.
+#[derive(Clone, Debug)]
+pub struct Call {
+    pub ident: String,
+    pub args: Vec<Argument>,
+    pub span: Span,
+}
+
+/// `let [mut] NAME[: TYPE] = VALUE`.
+#[derive(Clone, Debug)]
+pub struct Let {
+    pub ident: String,
+    pub mutable: bool,
+    /// the declared type, if any
+    pub kind: Option<Type>,
+    pub value: Argument,
+    pub span: Span,
+}
+
+/// `NAME = VALUE`, where `NAME` is a mutable local.
+#[derive(Clone, Debug)]
+pub struct Assign {
+    pub ident: String,
+    pub value: Argument,
+    pub span: Span,
+}
+
+/// `if CONDITION { ... } else { ... }`, where `else if` is an `if` alone in the else block.
+#[derive(Clone, Debug)]
+pub struct If {
+    pub condition: Argument,
+    pub then: Vec<Statement>,
+    pub otherwise: Vec<Statement>,
+    pub span: Span,
+}
+
+/// `while CONDITION { ... }`.
+#[derive(Clone, Debug)]
+pub struct While {
+    pub condition: Argument,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum Statement {
+    Syscall(Syscall),
+    Call(Call),
+    Let(Let),
+    Assign(Assign),
+    If(If),
+    While(While),
+    /// `loop { ... }`
+    Loop(Vec<Statement>, Span),
+    Break(Span),
+    Continue(Span),
+    /// `return [VALUE]`
+    Return(Option<Argument>, Span),
+    /// a statement which failed to parse, already reported
+    Error(Span),
 }
 
 #[derive(Clone, Debug)]
 pub struct Function {
     pub ident: String,
-    pub body: Vec<Syscall>,
+    pub params: Vec<(String, Type)>,
+    /// the type after `->`, if any
+    pub ret: Option<Type>,
+    pub body: Vec<Statement>,
+    pub span: Span,
 }
 
 impl Function {
-    pub fn new(ident: String) -> Self {
+    pub fn new(ident: String, span: Span) -> Self {
         Self {
             ident,
+            params: vec![],
+            ret: None,
             body: vec![],
+            span,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Type {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    /// `[T; N]`
+    Array(Box<Type>, u64),
+}
+
+impl Type {
+    /// Size in bytes.
+    pub fn size(&self) -> u64 {
+        match self {
+            Type::U8 | Type::I8 => 1,
+            Type::U16 | Type::I16 => 2,
+            Type::U32 | Type::I32 => 4,
+            Type::U64 | Type::I64 => 8,
+            Type::Array(element, len) => element.size().saturating_mul(*len),
+        }
+    }
+
+    /// Alignment in bytes, that of the element for arrays.
+    pub fn align(&self) -> u64 {
+        match self {
+            Type::Array(element, _) => element.align(),
+            integer => integer.size(),
         }
     }
+
+    /// Whether the type is a signed integer, which is sign extended, divided and compared as
+    /// such.
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type::I8 | Type::I16 | Type::I32 | Type::I64)
+    }
+
+    pub fn display(&self) -> String {
+        match self {
+            Type::U8 => "u8".to_string(),
+            Type::U16 => "u16".to_string(),
+            Type::U32 => "u32".to_string(),
+            Type::U64 => "u64".to_string(),
+            Type::I8 => "i8".to_string(),
+            Type::I16 => "i16".to_string(),
+            Type::I32 => "i32".to_string(),
+            Type::I64 => "i64".to_string(),
+            Type::Array(element, len) => format!("[{}; {len}]", element.display()),
+        }
+    }
+}
+
+/// Initializer of a static.
+#[derive(Clone, Debug)]
+pub enum Init {
+    Integer(u64),
+    String(String),
+    /// `[value; N]`
+    Repeat(u64, u64),
+}
+
+#[derive(Clone, Debug)]
+pub struct Static {
+    pub ident: String,
+    pub mutable: bool,
+    pub kind: Type,
+    pub init: Init,
+    pub span: Span,
+}
+
+/// A static defined in another object, `extern static NAME: T;`.
+#[derive(Clone, Debug)]
+pub struct Extern {
+    pub ident: String,
+    pub kind: Type,
+    pub span: Span,
+}
+
+/// A function from a shared library, `extern "C" fn NAME(PARAM: T, ...) -> T;`.
+#[derive(Clone, Debug)]
+pub struct Import {
+    pub ident: String,
+    pub params: Vec<(String, Type)>,
+    /// whether the parameters end with `...`
+    pub variadic: bool,
+    /// the type after `->`, if any
+    pub ret: Option<Type>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum Item {
+    Function(Function),
+    Static(Static),
+    Extern(Extern),
+    Import(Import),
+    /// an item which failed to parse, already reported
+    Error(Span),
 }
 
 #[derive(Clone, Debug)]
 pub struct Source {
-    pub functions: Vec<Function>,
+    pub items: Vec<Item>,
 }
 
 impl Source {
     pub fn new() -> Self {
-        Self { functions: vec![] }
+        Self { items: vec![] }
+    }
+
+    /// Functions which parsed, skipping error items.
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Function(function) => Some(function),
+            _ => None,
+        })
+    }
+
+    /// Statics which parsed, skipping error items.
+    pub fn statics(&self) -> impl Iterator<Item = &Static> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Static(item) => Some(item),
+            _ => None,
+        })
+    }
+
+    /// External statics which parsed, skipping error items.
+    pub fn externs(&self) -> impl Iterator<Item = &Extern> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Extern(item) => Some(item),
+            _ => None,
+        })
+    }
+
+    /// Imported functions which parsed, skipping error items.
+    pub fn imports(&self) -> impl Iterator<Item = &Import> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Import(item) => Some(item),
+            _ => None,
+        })
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Parser<'input> {
     lexer: Lexer<'input>,
-    lexme0: Option<Lexme<'input>>,
+    token0: Option<Token<'input>>,
+    /// end of the last consumed token, other than whitespace
+    end: usize,
+    diagnostics: Diagnostics,
 }
 
 impl<'input> Parser<'input> {
     #[inline]
     pub fn new(input: &'input str) -> Self {
-        let mut lexer = Lexer::new(input);
-        let lexme0 = lexer.next();
+        let mut this = Self {
+            lexer: Lexer::new(input),
+            token0: None,
+            end: 0,
+            diagnostics: Diagnostics::new(),
+        };
+
+        this.token0 = this.next_token();
+        this
+    }
+
+    /// Takes the diagnostics reported so far.
+    #[inline]
+    pub fn take_diagnostics(&mut self) -> Diagnostics {
+        core::mem::take(&mut self.diagnostics)
+    }
+
+    /// Next token from the lexer, reporting and skipping invalid input.
+    #[inline]
+    fn next_token(&mut self) -> Option<Token<'input>> {
+        loop {
+            let token = self.lexer.next()?;
+            let (invalid, input) = match token.lexme {
+                Lexme::Invalid(invalid, input) => (invalid, input),
+                Lexme::String(string) => {
+                    self.check_escapes(string, token.span.start);
+                    return Some(token);
+                }
+                _ => return Some(token),
+            };
+
+            match invalid {
+                Invalid::Character => {
+                    self.diagnostics
+                        .error(token.span, format!("unknown character `{input}`"));
+                }
+                Invalid::IntegerOverflow => {
+                    self.diagnostics
+                        .error(token.span, format!("integer `{input}` is too large"))
+                        .note(format!("the largest integer is {}", u64::MAX));
+                }
+                Invalid::UnterminatedString => {
+                    self.diagnostics
+                        .error(token.span, "unterminated string")
+                        .label("string starts here");
+                }
+            }
+        }
+    }
+
+    /// Reports every unknown escape in a string literal starting at `start`.
+    fn check_escapes(&mut self, string: &str, start: usize) {
+        let mut chars = string.char_indices();
 
-        Self { lexer, lexme0 }
+        while let Some((offset, char0)) = chars.next() {
+            if char0 != '\\' {
+                continue;
+            }
+
+            match chars.next() {
+                Some((_, char1)) if escape(char1).is_none() => {
+                    let end = offset + 1 + char1.len_utf8();
+
+                    self.diagnostics
+                        .error(
+                            Span::new(start + offset, start + end),
+                            format!("unknown character escape `{}`", &string[offset..end]),
+                        )
+                        .note("the escapes are `\\n`, `\\t`, `\\0`, `\\\\`, `\\'` and `\\\"`");
+                }
+                _ => {}
+            }
+        }
     }
 
     #[inline]
     fn step(&mut self) {
-        self.lexme0 = self.lexer.next();
+        match self.token0 {
+            Some(Token {
+                lexme: Lexme::Space(_) | Lexme::Newline,
+                ..
+            }) => {}
+            Some(token) => self.end = token.span.end,
+            None => {}
+        }
+
+        self.token0 = self.next_token();
+    }
+
+    /// Reports that `what` was expected at the next token, always returns `None`.
+    #[inline]
+    pub fn expected<T>(&mut self, what: &str) -> Option<T> {
+        let found = match self.peek() {
+            Some(lexme) => lexme.describe(),
+            None => "end of file".to_string(),
+        };
+
+        let span = self.span();
+
+        self.diagnostics
+            .error(span, format!("expected {what}, found {found}"))
+            .label(format!("expected {what}"));
+
+        None
     }
 
     #[inline]
     fn peek(&self) -> Option<Lexme<'input>> {
-        self.lexme0
+        self.token0.map(|token| token.lexme)
+    }
+
+    /// Span of the next token, or an empty span at the end of input.
+    #[inline]
+    pub fn span(&self) -> Span {
+        self.token0
+            .map(|token| token.span)
+            .unwrap_or(Span::new(self.end, self.end))
+    }
+
+    /// Span from `start` to the end of the last consumed token.
+    #[inline]
+    pub fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.end.max(start))
     }
 
     #[inline]
@@ -171,180 +581,1066 @@ impl<'input> Parser<'input> {
     }
 
     #[inline]
-    pub fn do_ident(&mut self) -> Option<String> {
+    pub fn do_colon(&mut self) -> Option<()> {
         match self.peek() {
-            Some(Lexme::Ident(ident)) => {
+            Some(Lexme::Colon) => {
                 self.step();
 
-                Some(ident.to_string())
+                Some(())
             }
             _ => None,
         }
     }
 
     #[inline]
-    pub fn do_string(&mut self) -> Option<String> {
+    pub fn do_semicolon(&mut self) -> Option<()> {
         match self.peek() {
-            Some(Lexme::String(string)) => {
+            Some(Lexme::Semicolon) => {
                 self.step();
 
-                Some(string.to_string())
+                Some(())
             }
             _ => None,
         }
     }
 
     #[inline]
-    pub fn do_integer(&mut self) -> Option<u64> {
+    pub fn do_equals(&mut self) -> Option<()> {
         match self.peek() {
-            Some(Lexme::Integer(integer)) => {
+            Some(Lexme::Equals) => {
                 self.step();
 
-                Some(integer)
+                Some(())
             }
             _ => None,
         }
     }
 
     #[inline]
-    pub fn do_argument(&mut self) -> Argument {
-        match Argument::from(self.do_integer()) {
-            Argument::None => Argument::from(self.do_string()),
-            argument => argument,
+    pub fn do_bracket_left(&mut self) -> Option<()> {
+        match self.peek() {
+            Some(Lexme::BracketLeft) => {
+                self.step();
+
+                Some(())
+            }
+            _ => None,
         }
     }
 
     #[inline]
-    pub fn do_path(&mut self) -> Vec<String> {
-        let mut path = vec![];
+    pub fn do_bracket_right(&mut self) -> Option<()> {
+        match self.peek() {
+            Some(Lexme::BracketRight) => {
+                self.step();
 
-        if self.do_double_colon().is_some() {
-            path.push("::".to_string());
+                Some(())
+            }
+            _ => None,
         }
+    }
 
-        loop {
-            if let Some(ident) = self.do_ident() {
-                path.push(ident);
-                self.do_double_colon();
-            } else {
-                break;
+    #[inline]
+    pub fn do_ident(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Lexme::Ident(ident)) => {
+                self.step();
+
+                Some(ident.to_string())
             }
+            _ => None,
         }
+    }
 
-        path
+    #[inline]
+    pub fn do_string(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Lexme::String(string)) => {
+                self.step();
+
+                Some(string.to_string())
+            }
+            _ => None,
+        }
     }
 
     #[inline]
-    pub fn do_parameters(&mut self) -> Option<()> {
-        self.do_space();
-        self.do_paren_left()?;
-        self.do_space();
-        self.do_paren_right()?;
+    pub fn do_ellipsis(&mut self) -> Option<()> {
+        match self.peek() {
+            Some(Lexme::Ellipsis) => {
+                self.step();
 
-        Some(())
+                Some(())
+            }
+            _ => None,
+        }
     }
 
     #[inline]
-    pub fn do_body(&mut self) -> Vec<Syscall> {
-        #[inline]
-        pub fn do_body(parser: &mut Parser) -> Option<Vec<Syscall>> {
-            parser.do_space();
-            parser.do_brace_left()?;
+    pub fn do_integer(&mut self) -> Option<u64> {
+        match self.peek() {
+            Some(Lexme::Integer(integer)) => {
+                self.step();
+
+                Some(integer)
+            }
+            _ => None,
+        }
+    }
 
-            let mut syscalls = vec![];
+    /// An integer, a string, a name, a call or a parenthesized expression, with any unary
+    /// operators before it.
+    #[inline]
+    pub fn do_operand(&mut self) -> Option<Argument> {
+        let start = self.span().start;
 
-            loop {
-                parser.do_space();
-                let path = parser.do_path();
+        let op = match self.peek() {
+            Some(Lexme::Minus) => Some(UnaryOp::Neg),
+            Some(Lexme::Bang) => Some(UnaryOp::Not),
+            _ => None,
+        };
 
-                if path.is_empty() {
-                    break;
-                }
+        if let Some(op) = op {
+            self.step();
+            self.do_space();
 
-                if path.get(0) == Some(&"sys".to_string())
-                    && path.get(1) == Some(&"syscall".to_string())
-                {
-                    parser.do_space();
-                    parser.do_paren_left()?;
+            let value = self.do_operand()?;
+            let span = self.span_from(start);
 
-                    let id = parser.do_integer()?;
+            return Some(Argument::Unary(Box::new(Unary { op, value, span })));
+        }
 
-                    parser.do_space();
-                    parser.do_comma();
-                    parser.do_space();
+        if self.do_paren_left().is_some() {
+            self.do_space();
 
-                    let arg0 = parser.do_argument();
+            let value = self.do_argument()?;
 
-                    parser.do_space();
-                    parser.do_comma();
-                    parser.do_space();
+            self.do_space();
+            self.do_paren_right().or_else(|| self.expected("`)`"))?;
 
-                    let arg1 = parser.do_argument();
+            return Some(value);
+        }
 
-                    parser.do_space();
-                    parser.do_comma();
-                    parser.do_space();
+        if let Some(integer) = self.do_integer() {
+            return Some(Argument::U64(integer));
+        }
 
-                    let arg2 = parser.do_argument();
+        if let Some(string) = self.do_string() {
+            return Some(Argument::String(string));
+        }
 
-                    parser.do_space();
-                    parser.do_comma();
+        match self.peek() {
+            Some(Lexme::Ident(_)) | Some(Lexme::DoubleColon) => {
+                let start = self.span().start;
+                let path = self.do_path();
+                let span = self.span_from(start);
 
-                    syscalls.push(Syscall {
-                        id,
-                        arg0,
-                        arg1,
-                        arg2,
-                    });
+                self.do_space();
 
-                    parser.do_space();
-                    parser.do_paren_right()?;
+                // a lone name which isn't called refers to a local or a static
+                match path.as_slice() {
+                    [ident] if ident != "::" && self.peek() != Some(Lexme::ParenLeft) => {
+                        Some(Argument::Name(ident.clone(), span))
+                    }
+                    _ => self.do_call_path(start, path),
                 }
             }
+            _ => self.expected("expression"),
+        }
+    }
+
+    /// An expression, parsed by precedence climbing.
+    #[inline]
+    pub fn do_argument(&mut self) -> Option<Argument> {
+        self.do_binary(0)
+    }
+
+    /// An operand followed by every binary operator binding at least as tightly as `min`.
+    #[inline]
+    pub fn do_binary(&mut self, min: u8) -> Option<Argument> {
+        let start = self.span().start;
+        let mut left = self.do_operand()?;
+        let mut compared = false;
+
+        loop {
+            self.do_space();
+
+            let op = match self.peek().and_then(BinaryOp::from_lexme) {
+                Some(op) if op.precedence() >= min => op,
+                _ => break,
+            };
+
+            if op.is_comparison() && compared {
+                let span = self.span();
+
+                self.diagnostics
+                    .error(span, "comparison operators cannot be chained")
+                    .note("compare each pair instead: `a < b && b < c`");
+
+                return None;
+            }
+
+            self.step();
+            self.do_space();
 
-            parser.do_space();
-            parser.do_brace_right()?;
+            let right = self.do_binary(op.precedence() + 1)?;
+            let span = self.span_from(start);
 
-            Some(syscalls)
+            compared = op.is_comparison();
+            left = Argument::Binary(Box::new(Binary {
+                op,
+                left,
+                right,
+                span,
+            }));
         }
 
-        do_body(self).unwrap_or_else(Vec::new)
+        Some(left)
     }
 
     #[inline]
-    pub fn do_fn(&mut self) -> Option<Function> {
-        self.do_space();
+    pub fn do_path(&mut self) -> Vec<String> {
+        let mut path = vec![];
 
-        let ident = self.do_ident()?;
+        if self.do_double_colon().is_some() {
+            path.push("::".to_string());
+        }
 
-        self.do_parameters()?;
-        let body = self.do_body();
+        loop {
+            if let Some(ident) = self.do_ident() {
+                path.push(ident);
+                self.do_double_colon();
+            } else {
+                break;
+            }
+        }
 
-        Some(Function { ident, body })
+        path
     }
 
+    /// `(NAME: TYPE, ...)`, the parameters of a function.
     #[inline]
-    pub fn parse(&mut self) -> Source {
-        let mut source = Source::new();
+    pub fn do_parameters(&mut self) -> Option<Vec<(String, Type)>> {
+        self.do_space();
+        self.do_paren_left().or_else(|| self.expected("`(`"))?;
+
+        let mut params = vec![];
 
         loop {
             self.do_space();
 
-            match self.peek() {
-                Some(Lexme::Fn) => {
-                    self.step();
-
-                    if let Some(function) = self.do_fn() {
-                        source.functions.push(function);
-                    }
-                }
-                None => break,
-                _ => {
-                    self.step();
-                }
+            if self.do_paren_right().is_some() {
+                break;
             }
-        }
 
-        source
+            let name = self
+                .do_ident()
+                .or_else(|| self.expected("parameter name"))?;
+
+            self.do_space();
+            self.do_colon().or_else(|| self.expected("`:`"))?;
+            self.do_space();
+
+            params.push((name, self.do_type()?));
+            self.do_space();
+
+            if self.do_comma().is_none() {
+                self.do_paren_right()
+                    .or_else(|| self.expected("`,` or `)`"))?;
+                break;
+            }
+        }
+
+        Some(params)
+    }
+
+    /// Skips to the next statement boundary: after a newline, or before `}`, `fn` or the end
+    /// of input.
+    #[inline]
+    pub fn recover_statement(&mut self) {
+        loop {
+            match self.peek() {
+                Some(Lexme::Newline) => {
+                    self.step();
+                    break;
+                }
+                Some(Lexme::BraceRight)
+                | Some(Lexme::Fn)
+                | Some(Lexme::Static)
+                | Some(Lexme::Extern)
+                | None => break,
+                _ => self.step(),
+            }
+        }
+    }
+
+    /// Skips to the next item boundary: before a `fn`, `static` or `extern` outside of braces,
+    /// or the end of input.
+    #[inline]
+    pub fn recover_item(&mut self) {
+        let mut depth = 0_usize;
+
+        loop {
+            match self.peek() {
+                Some(Lexme::Fn) | Some(Lexme::Static) | Some(Lexme::Extern) if depth == 0 => break,
+                Some(Lexme::BraceLeft) => depth += 1,
+                Some(Lexme::BraceRight) => depth = depth.saturating_sub(1),
+                None => break,
+                _ => {}
+            }
+
+            self.step();
+        }
+    }
+
+    #[inline]
+    pub fn do_syscall(&mut self, start: usize) -> Option<Syscall> {
+        self.do_space();
+        self.do_paren_left().or_else(|| self.expected("`(`"))?;
+        self.do_space();
+
+        let id = self
+            .do_integer()
+            .or_else(|| self.expected("syscall number"))?;
+
+        let mut args = vec![];
+
+        loop {
+            self.do_space();
+
+            if self.do_paren_right().is_some() {
+                break;
+            }
+
+            self.do_comma().or_else(|| self.expected("`,` or `)`"))?;
+            self.do_space();
+
+            // trailing comma
+            if self.do_paren_right().is_some() {
+                break;
+            }
+
+            args.push(self.do_argument()?);
+        }
+
+        Some(Syscall {
+            id,
+            args,
+            span: self.span_from(start),
+        })
+    }
+
+    /// `(ARG, ...)`, the arguments of a call.
+    #[inline]
+    pub fn do_arguments(&mut self) -> Option<Vec<Argument>> {
+        self.do_space();
+        self.do_paren_left().or_else(|| self.expected("`(`"))?;
+
+        let mut args = vec![];
+
+        loop {
+            self.do_space();
+
+            if self.do_paren_right().is_some() {
+                break;
+            }
+
+            if !args.is_empty() {
+                self.do_comma().or_else(|| self.expected("`,` or `)`"))?;
+                self.do_space();
+
+                // trailing comma
+                if self.do_paren_right().is_some() {
+                    break;
+                }
+            }
+
+            args.push(self.do_argument()?);
+        }
+
+        Some(args)
+    }
+
+    /// Parses the arguments of a call to an already parsed path, either `sys::syscall` or a
+    /// function. The result is an `Argument::Syscall` or an `Argument::Call`.
+    #[inline]
+    pub fn do_call_path(&mut self, start: usize, path: Vec<String>) -> Option<Argument> {
+        match path.as_slice() {
+            [sys, syscall] if sys == "sys" && syscall == "syscall" => self
+                .do_syscall(start)
+                .map(|syscall| Argument::Syscall(Box::new(syscall))),
+            [ident] if ident != "::" => {
+                let ident = ident.clone();
+                let args = self.do_arguments()?;
+                let span = self.span_from(start);
+
+                Some(Argument::Call(Box::new(Call { ident, args, span })))
+            }
+            _ => {
+                let span = self.span_from(start);
+
+                self.diagnostics
+                    .error(span, format!("unknown function `{}`", path.join("::")))
+                    .note(
+                        "callable functions are `sys::syscall`, `fn`s and `extern \"C\"` imports",
+                    );
+
+                None
+            }
+        }
+    }
+
+    /// `let [mut] NAME[: TYPE] = VALUE`, after the `let` keyword.
+    #[inline]
+    pub fn do_let(&mut self, start: usize) -> Option<Let> {
+        self.do_space();
+
+        let mutable = match self.peek() {
+            Some(Lexme::Mut) => {
+                self.step();
+                self.do_space();
+                true
+            }
+            _ => false,
+        };
+
+        let ident = self.do_ident().or_else(|| self.expected("variable name"))?;
+
+        self.do_space();
+
+        let kind = match self.do_colon() {
+            Some(()) => {
+                self.do_space();
+
+                let kind = self.do_type()?;
+
+                self.do_space();
+                self.do_equals().or_else(|| self.expected("`=`"))?;
+
+                Some(kind)
+            }
+            None => {
+                self.do_equals().or_else(|| self.expected("`:` or `=`"))?;
+
+                None
+            }
+        };
+
+        self.do_space();
+
+        let value = self.do_argument()?;
+
+        Some(Let {
+            ident,
+            mutable,
+            kind,
+            value,
+            span: self.span_from(start),
+        })
+    }
+
+    /// `if CONDITION { ... }` with an optional `else { ... }` or `else if`, after the `if`
+    /// keyword.
+    #[inline]
+    pub fn do_if(&mut self, start: usize) -> Option<If> {
+        self.do_space();
+
+        let condition = self.do_argument()?;
+        let then = self.do_body()?;
+        let span = self.span_from(start);
+
+        self.do_space();
+
+        if self.peek() != Some(Lexme::Else) {
+            return Some(If {
+                condition,
+                then,
+                otherwise: vec![],
+                span,
+            });
+        }
+
+        self.step();
+        self.do_space();
+
+        let otherwise = match self.peek() {
+            Some(Lexme::If) => {
+                let start = self.span().start;
+
+                self.step();
+                vec![Statement::If(self.do_if(start)?)]
+            }
+            _ => self.do_body()?,
+        };
+
+        Some(If {
+            condition,
+            then,
+            otherwise,
+            span: self.span_from(start),
+        })
+    }
+
+    /// A `let`, an assignment, control flow, or a call to a syscall or a function.
+    #[inline]
+    pub fn do_statement(&mut self) -> Option<Statement> {
+        let start = self.span().start;
+
+        match self.peek() {
+            Some(Lexme::Let) => {
+                self.step();
+
+                return self.do_let(start).map(Statement::Let);
+            }
+            Some(Lexme::If) => {
+                self.step();
+
+                return self.do_if(start).map(Statement::If);
+            }
+            Some(Lexme::While) => {
+                self.step();
+                self.do_space();
+
+                let condition = self.do_argument()?;
+                let body = self.do_body()?;
+                let span = self.span_from(start);
+
+                return Some(Statement::While(While {
+                    condition,
+                    body,
+                    span,
+                }));
+            }
+            Some(Lexme::Loop) => {
+                self.step();
+
+                let body = self.do_body()?;
+
+                return Some(Statement::Loop(body, self.span_from(start)));
+            }
+            Some(Lexme::Break) => {
+                self.step();
+
+                return Some(Statement::Break(self.span_from(start)));
+            }
+            Some(Lexme::Continue) => {
+                self.step();
+
+                return Some(Statement::Continue(self.span_from(start)));
+            }
+            Some(Lexme::Return) => {
+                self.step();
+
+                // the value is on the same line, a newline ends a bare `return`
+                while let Some(Lexme::Space(_)) = self.peek() {
+                    self.step();
+                }
+
+                let value = match self.peek() {
+                    Some(Lexme::Newline) | Some(Lexme::BraceRight) | None => None,
+                    _ => Some(self.do_argument()?),
+                };
+
+                return Some(Statement::Return(value, self.span_from(start)));
+            }
+            _ => {}
+        }
+
+        let path = self.do_path();
+
+        if path.is_empty() {
+            return self.expected("statement");
+        }
+
+        self.do_space();
+
+        match path.as_slice() {
+            [ident] if ident != "::" && self.do_equals().is_some() => {
+                let ident = ident.clone();
+
+                self.do_space();
+
+                let value = self.do_argument()?;
+
+                Some(Statement::Assign(Assign {
+                    ident,
+                    value,
+                    span: self.span_from(start),
+                }))
+            }
+            _ => self.do_call_path(start, path).map(|call| match call {
+                Argument::Syscall(syscall) => Statement::Syscall(*syscall),
+                Argument::Call(call) => Statement::Call(*call),
+                _ => unreachable!("calls parse to syscalls or imported calls"),
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn do_body(&mut self) -> Option<Vec<Statement>> {
+        self.do_space();
+        self.do_brace_left().or_else(|| self.expected("`{`"))?;
+
+        let mut statements = vec![];
+
+        loop {
+            self.do_space();
+
+            match self.peek() {
+                Some(Lexme::BraceRight) => {
+                    self.step();
+                    break;
+                }
+                // the closing brace is missing, keep what was parsed
+                Some(Lexme::Fn) | Some(Lexme::Static) | Some(Lexme::Extern) | None => {
+                    self.expected::<()>("`}`");
+                    break;
+                }
+                _ => {
+                    let start = self.span().start;
+
+                    match self.do_statement() {
+                        Some(statement) => statements.push(statement),
+                        None => {
+                            self.recover_statement();
+                            statements.push(Statement::Error(self.span_from(start)));
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(statements)
+    }
+
+    #[inline]
+    pub fn do_fn(&mut self, start: usize) -> Option<Function> {
+        self.do_space();
+
+        let ident = self.do_ident().or_else(|| self.expected("function name"))?;
+
+        let params = self.do_parameters()?;
+
+        self.do_space();
+
+        let ret = match self.peek() {
+            Some(Lexme::Arrow) => {
+                self.step();
+                self.do_space();
+
+                Some(self.do_type()?)
+            }
+            _ => None,
+        };
+
+        let body = self.do_body()?;
+        let span = self.span_from(start);
+
+        Some(Function {
+            ident,
+            params,
+            ret,
+            body,
+            span,
+        })
+    }
+
+    /// `u8`, `u16`, `u32`, `u64`, `i8`, `i16`, `i32`, `i64` or `[T; N]`.
+    #[inline]
+    pub fn do_type(&mut self) -> Option<Type> {
+        if self.do_bracket_left().is_some() {
+            self.do_space();
+
+            let element = self.do_type()?;
+
+            self.do_space();
+            self.do_semicolon().or_else(|| self.expected("`;`"))?;
+            self.do_space();
+
+            let len = self
+                .do_integer()
+                .or_else(|| self.expected("array length"))?;
+
+            self.do_space();
+            self.do_bracket_right().or_else(|| self.expected("`]`"))?;
+
+            return Some(Type::Array(Box::new(element), len));
+        }
+
+        let kind = match self.peek() {
+            Some(Lexme::Ident("u8")) => Type::U8,
+            Some(Lexme::Ident("u16")) => Type::U16,
+            Some(Lexme::Ident("u32")) => Type::U32,
+            Some(Lexme::Ident("u64")) => Type::U64,
+            Some(Lexme::Ident("i8")) => Type::I8,
+            Some(Lexme::Ident("i16")) => Type::I16,
+            Some(Lexme::Ident("i32")) => Type::I32,
+            Some(Lexme::Ident("i64")) => Type::I64,
+            _ => return self.expected("type"),
+        };
+
+        self.step();
+
+        Some(kind)
+    }
+
+    /// An integer, a string or `[value; N]`.
+    #[inline]
+    pub fn do_init(&mut self) -> Option<Init> {
+        if let Some(integer) = self.do_integer() {
+            return Some(Init::Integer(integer));
+        }
+
+        if let Some(string) = self.do_string() {
+            return Some(Init::String(string));
+        }
+
+        self.do_bracket_left()
+            .or_else(|| self.expected("initializer"))?;
+        self.do_space();
+
+        let value = self.do_integer().or_else(|| self.expected("integer"))?;
+
+        self.do_space();
+        self.do_semicolon().or_else(|| self.expected("`;`"))?;
+        self.do_space();
+
+        let len = self
+            .do_integer()
+            .or_else(|| self.expected("array length"))?;
+
+        self.do_space();
+        self.do_bracket_right().or_else(|| self.expected("`]`"))?;
+
+        Some(Init::Repeat(value, len))
+    }
+
+    /// `static [mut] NAME: TYPE = INIT`, after the `static` keyword.
+    #[inline]
+    pub fn do_static(&mut self, start: usize) -> Option<Static> {
+        self.do_space();
+
+        let mutable = match self.peek() {
+            Some(Lexme::Mut) => {
+                self.step();
+                self.do_space();
+                true
+            }
+            _ => false,
+        };
+
+        let ident = self.do_ident().or_else(|| self.expected("static name"))?;
+
+        self.do_space();
+        self.do_colon().or_else(|| self.expected("`:`"))?;
+        self.do_space();
+
+        let kind = self.do_type()?;
+
+        self.do_space();
+        self.do_equals().or_else(|| self.expected("`=`"))?;
+        self.do_space();
+
+        let init = self.do_init()?;
+        let span = self.span_from(start);
+
+        self.do_space();
+        self.do_semicolon().or_else(|| self.expected("`;`"))?;
+
+        Some(Static {
+            ident,
+            mutable,
+            kind,
+            init,
+            span,
+        })
+    }
+
+    /// `extern static NAME: TYPE;` after the `extern` keyword, or an imported function.
+    #[inline]
+    pub fn do_extern(&mut self, start: usize) -> Option<Item> {
+        self.do_space();
+
+        match self.peek() {
+            Some(Lexme::Static) => self.step(),
+            Some(Lexme::String(_)) => return self.do_import(start).map(Item::Import),
+            _ => return self.expected("`static` or `\"C\"`"),
+        }
+
+        self.do_space();
+
+        let ident = self.do_ident().or_else(|| self.expected("static name"))?;
+
+        self.do_space();
+        self.do_colon().or_else(|| self.expected("`:`"))?;
+        self.do_space();
+
+        let kind = self.do_type()?;
+        let span = self.span_from(start);
+
+        self.do_space();
+        self.do_semicolon().or_else(|| self.expected("`;`"))?;
+
+        Some(Item::Extern(Extern { ident, kind, span }))
+    }
+
+    /// `"C" fn NAME(PARAM: TYPE, ...) [-> TYPE];` after the `extern` keyword.
+    #[inline]
+    pub fn do_import(&mut self, start: usize) -> Option<Import> {
+        let abi_span = self.span();
+        let abi = self.do_string().or_else(|| self.expected("ABI"))?;
+
+        let supported = &abi[1..abi.len() - 1] == "C";
+
+        // the rest is still parsed, so recovery doesn't stop at its `fn`
+        if !supported {
+            self.diagnostics
+                .error(abi_span, format!("unsupported ABI {abi}"))
+                .note("the only supported ABI is `\"C\"`");
+        }
+
+        self.do_space();
+
+        match self.peek() {
+            Some(Lexme::Fn) => self.step(),
+            _ => return self.expected("`fn`"),
+        }
+
+        self.do_space();
+
+        let ident = self.do_ident().or_else(|| self.expected("function name"))?;
+
+        self.do_space();
+        self.do_paren_left().or_else(|| self.expected("`(`"))?;
+
+        let mut params = vec![];
+        let mut variadic = false;
+
+        loop {
+            self.do_space();
+
+            if self.do_paren_right().is_some() {
+                break;
+            }
+
+            if variadic {
+                return self.expected("`)`");
+            }
+
+            if self.do_ellipsis().is_some() {
+                variadic = true;
+            } else {
+                let name = self
+                    .do_ident()
+                    .or_else(|| self.expected("parameter name"))?;
+
+                self.do_space();
+                self.do_colon().or_else(|| self.expected("`:`"))?;
+                self.do_space();
+
+                params.push((name, self.do_type()?));
+            }
+
+            self.do_space();
+
+            if self.do_comma().is_none() {
+                self.do_paren_right()
+                    .or_else(|| self.expected("`,` or `)`"))?;
+                break;
+            }
+        }
+
+        self.do_space();
+
+        let ret = match self.peek() {
+            Some(Lexme::Arrow) => {
+                self.step();
+                self.do_space();
+
+                Some(self.do_type()?)
+            }
+            _ => None,
+        };
+
+        let span = self.span_from(start);
+
+        self.do_space();
+        self.do_semicolon().or_else(|| self.expected("`;`"))?;
+
+        if !supported {
+            return None;
+        }
+
+        Some(Import {
+            ident,
+            params,
+            variadic,
+            ret,
+            span,
+        })
+    }
+
+    /// Parses every item in the input. Syntax errors are reported, and replaced with error
+    /// nodes in the returned tree.
+    #[inline]
+    pub fn parse(&mut self) -> Source {
+        let mut source = Source::new();
+
+        loop {
+            self.do_space();
+
+            let start = self.span().start;
+
+            match self.peek() {
+                Some(Lexme::Fn) => {
+                    self.step();
+
+                    match self.do_fn(start) {
+                        Some(function) => source.items.push(Item::Function(function)),
+                        None => {
+                            self.recover_item();
+                            source.items.push(Item::Error(self.span_from(start)));
+                        }
+                    }
+                }
+                Some(Lexme::Static) => {
+                    self.step();
+
+                    match self.do_static(start) {
+                        Some(item) => source.items.push(Item::Static(item)),
+                        None => {
+                            self.recover_item();
+                            source.items.push(Item::Error(self.span_from(start)));
+                        }
+                    }
+                }
+                Some(Lexme::Extern) => {
+                    self.step();
+
+                    match self.do_extern(start) {
+                        Some(item) => source.items.push(item),
+                        None => {
+                            self.recover_item();
+                            source.items.push(Item::Error(self.span_from(start)));
+                        }
+                    }
+                }
+                None => break,
+                _ => {
+                    self.expected::<()>("`fn`, `static` or `extern`");
+                    self.recover_item();
+                    source.items.push(Item::Error(self.span_from(start)));
+                }
+            }
+        }
+
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `input`, returning the tree and the message of every error.
+    fn parse(input: &str) -> (Source, Vec<String>) {
+        let mut parser = Parser::new(input);
+        let source = parser.parse();
+        let messages = parser
+            .take_diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.message.clone())
+            .collect();
+
+        (source, messages)
+    }
+
+    #[test]
+    fn reports_every_item_error() {
+        let (source, messages) = parse(
+            "static X u64 = 1;\n\
+             fn entry( {\n}\n\
+             static Y: u64 = 2;\n\
+             42\n\
+             fn ok() {\n}\n",
+        );
+
+        assert_eq!(
+            messages,
+            [
+                "expected `:`, found identifier `u64`",
+                "expected parameter name, found `{`",
+                "expected `fn`, `static` or `extern`, found integer `42`",
+            ]
+        );
+
+        let items: Vec<&str> = source
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Function(function) => function.ident.as_str(),
+                Item::Static(item) => item.ident.as_str(),
+                Item::Error(_) => "error",
+                _ => "other",
+            })
+            .collect();
+
+        assert_eq!(items, ["error", "error", "Y", "error", "ok"]);
+    }
+
+    #[test]
+    fn reports_every_statement_error() {
+        let (source, messages) = parse(
+            "fn entry() {\n    \
+                 sys::syscall(1, 1 'a')\n    \
+                 let = 5\n    \
+                 sys::syscall(60, 0)\n\
+             }\n",
+        );
+
+        assert_eq!(
+            messages,
+            [
+                "expected `,` or `)`, found string 'a'",
+                "expected variable name, found `=`",
+            ]
+        );
+
+        let function = source.functions().next().unwrap();
+
+        assert!(matches!(
+            function.body[..],
+            [
+                Statement::Error(_),
+                Statement::Error(_),
+                Statement::Syscall(Syscall { id: 60, .. }),
+            ]
+        ));
+    }
+
+    #[test]
+    fn reports_unknown_escapes() {
+        let input = "static S: [u8; 8] = \"\\q\\\"\\e\";\n";
+        let mut parser = Parser::new(input);
+        let source = parser.parse();
+        let diagnostics = parser.take_diagnostics();
+        let errors: Vec<(&str, &str)> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let span = diagnostic.span;
+
+                (diagnostic.message.as_str(), &input[span.start..span.end])
+            })
+            .collect();
+
+        // the escaped quote doesn't end the string
+        assert_eq!(
+            errors,
+            [
+                ("unknown character escape `\\q`", "\\q"),
+                ("unknown character escape `\\e`", "\\e"),
+            ]
+        );
+        assert!(matches!(source.items[..], [Item::Static(_)]));
     }
 }
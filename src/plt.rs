@@ -0,0 +1,316 @@
+use super::dynamic::{self, Entry};
+use super::elf::Endian;
+use super::layout::{self, Layout, Target};
+use super::relocation::{self, Kind};
+use super::{program, section, symbol};
+
+/// Dynamic linker of x86-64 glibc.
+pub const INTERPRETER: &str = "/lib64/ld-linux-x86-64.so.2";
+
+/// Shared library imported functions are looked up in.
+pub const LIBRARY: &str = "libc.so.6";
+
+/// Size of a PLT entry.
+const ENTRY: u64 = 16;
+
+/// `.got.plt` entries before the first function's, the address of `.dynamic` and two the
+/// dynamic linker fills in.
+const RESERVED: u64 = 3;
+
+/// Pushes the link map and jumps to the resolver, the `rip`-relative displacements are
+/// relocated.
+const PLT0: [u8; 16] = [
+    0xff, 0x35, 0, 0, 0, 0, // push [rip + got + 8]
+    0xff, 0x25, 0, 0, 0, 0, // jmp [rip + got + 16]
+    0x0f, 0x1f, 0x40, 0x00, // nop
+];
+
+/// Adds `.dynamic` for a position-independent executable or one importing functions. With
+/// imports it also adds `.interp`, `.dynsym`, `.dynstr`, `.rela.plt`, `.plt` and `.got.plt`,
+/// so the dynamic linker loads `libc.so.6` and resolves each import the first time it's
+/// called.
+///
+/// Returns the id of the `NAME@plt` symbol of each import, which calls are relocated to.
+pub fn push(layout: &mut Layout, imports: &[String], pie: bool, endian: Endian) -> Vec<usize> {
+    let mut entries = vec![];
+    let mut symbols = vec![];
+
+    // address fields of `.dynamic`, as the entry's index and the section it refers to
+    let mut addresses = vec![];
+    let mut strings = None;
+    let mut got = None;
+
+    if !imports.is_empty() {
+        let mut section = layout::Section::new(".interp");
+
+        section
+            .flags(section::FLAG_ALLOC)
+            .data(format!("{INTERPRETER}\0").into_bytes());
+
+        let interp = layout.push_section(section);
+        let mut names = vec![0];
+        let mut table = vec![0; symbol::SIZE as usize];
+        let library = names.len() as u64;
+
+        names.extend(LIBRARY.as_bytes());
+        names.push(0);
+
+        for name in imports {
+            let mut entry = symbol::Symbol::new();
+
+            entry
+                .name(names.len() as u32)
+                .info(symbol::BIND_GLOBAL, symbol::KIND_FUNC)
+                .section(symbol::INDEX_UNDEFINED);
+            table.extend(entry.to_array(endian).as_slice());
+            names.extend(name.as_bytes());
+            names.push(0);
+        }
+
+        let names_len = names.len() as u64;
+        let mut section = layout::Section::new(".dynstr");
+
+        section
+            .kind(section::KIND_STRTAB)
+            .flags(section::FLAG_ALLOC)
+            .data(names);
+
+        let dynstr = layout.push_section(section);
+        let mut section = layout::Section::new(".dynsym");
+
+        // link to .dynstr, info is the index of the first global symbol
+        section
+            .kind(section::KIND_DYNSYM)
+            .flags(section::FLAG_ALLOC)
+            .align(8)
+            .link(layout.index(dynstr) as u32)
+            .info(1)
+            .entry_len(symbol::SIZE)
+            .data(table);
+
+        let dynsym = layout.push_section(section);
+        let mut table = vec![];
+
+        for index in 0..imports.len() {
+            let mut entry = relocation::Relocation::new();
+
+            // the offset of the `.got.plt` entry is relocated below
+            entry
+                .symbol(index as u32 + 1)
+                .kind(relocation::KIND_X86_64_JUMP_SLOT);
+            table.extend(entry.to_array(endian).as_slice());
+        }
+
+        let mut section = layout::Section::new(".rela.plt");
+
+        // link to .dynsym, info is the section the relocations apply to
+        section
+            .kind(section::KIND_RELA)
+            .flags(section::FLAG_ALLOC | section::FLAG_INFO_LINK)
+            .align(8)
+            .link(layout.index(dynsym) as u32)
+            .entry_len(relocation::SIZE)
+            .data(table);
+
+        let rela = layout.push_section(section);
+        let mut segment = layout::Segment::new(program::KIND_INTERP);
+
+        segment.flags(program::FLAG_READ).align(1).section(interp);
+        layout.push_segment(segment);
+
+        let mut segment = layout::Segment::new(program::KIND_LOAD);
+
+        segment
+            .flags(program::FLAG_READ)
+            .section(interp)
+            .section(dynstr)
+            .section(dynsym)
+            .section(rela);
+        layout.push_segment(segment);
+
+        let mut code = PLT0.to_vec();
+
+        for index in 0..imports.len() as u64 {
+            let start = ENTRY * (index + 1);
+
+            // jmp [rip + got entry], push index, jmp plt0
+            code.extend([0xff, 0x25, 0, 0, 0, 0]);
+            code.push(0x68);
+            code.extend((index as u32).to_le_bytes());
+            code.push(0xe9);
+            code.extend((-((start + ENTRY) as i32)).to_le_bytes());
+        }
+
+        let mut section = layout::Section::new(".plt");
+
+        section
+            .flags(section::FLAG_ALLOC | section::FLAG_EXEC)
+            .align(16)
+            .entry_len(ENTRY)
+            .data(code);
+
+        let plt = layout.push_section(section);
+        let mut segment = layout::Segment::new(program::KIND_LOAD);
+
+        segment
+            .flags(program::FLAG_READ | program::FLAG_EXEC)
+            .section(plt);
+        layout.push_segment(segment);
+
+        let mut section = layout::Section::new(".got.plt");
+
+        section
+            .flags(section::FLAG_ALLOC | section::FLAG_WRITE)
+            .align(8)
+            .entry_len(8)
+            .data(vec![0; (8 * (RESERVED + imports.len() as u64)) as usize]);
+
+        let got_plt = layout.push_section(section);
+
+        for (offset, addend) in [(2, 8), (8, 16)].iter() {
+            let mut relocation =
+                layout::Relocation::new(plt, *offset, Kind::Relative32, Target::Section(got_plt));
+
+            relocation.addend(addend - 4);
+            layout.push_relocation(relocation);
+        }
+
+        for (index, name) in imports.iter().enumerate() {
+            let index = index as u64;
+            let start = ENTRY * (index + 1);
+            let slot = 8 * (RESERVED + index);
+
+            // the entry jumps through its slot, which first points back at its push
+            let mut relocation =
+                layout::Relocation::new(plt, start + 2, Kind::Relative32, Target::Section(got_plt));
+
+            relocation.addend(slot as i64 - 4);
+            layout.push_relocation(relocation);
+
+            let mut relocation =
+                layout::Relocation::new(got_plt, slot, Kind::Absolute64, Target::Section(plt));
+
+            relocation.addend(start as i64 + 6);
+            layout.push_relocation(relocation);
+
+            let mut relocation = layout::Relocation::new(
+                rela,
+                relocation::SIZE * index,
+                Kind::Absolute64,
+                Target::Section(got_plt),
+            );
+
+            relocation.addend(slot as i64);
+            layout.push_relocation(relocation);
+
+            let mut symbol = layout::Symbol::new(&format!("{name}@plt"), plt);
+
+            symbol.kind(symbol::KIND_FUNC).offset(start).len(ENTRY);
+            symbols.push(layout.push_symbol(symbol));
+        }
+
+        entries.push(Entry::new(dynamic::TAG_NEEDED, library));
+
+        for (tag, section) in [
+            (dynamic::TAG_STRTAB, dynstr),
+            (dynamic::TAG_SYMTAB, dynsym),
+            (dynamic::TAG_PLTGOT, got_plt),
+            (dynamic::TAG_JMPREL, rela),
+        ]
+        .iter()
+        {
+            addresses.push((entries.len(), *section));
+            entries.push(Entry::new(*tag, 0));
+        }
+
+        entries.extend([
+            Entry::new(dynamic::TAG_STRSZ, names_len),
+            Entry::new(dynamic::TAG_SYMENT, symbol::SIZE),
+            Entry::new(
+                dynamic::TAG_PLTRELSZ,
+                relocation::SIZE * imports.len() as u64,
+            ),
+            Entry::new(dynamic::TAG_PLTREL, dynamic::TAG_RELA),
+            Entry::new(dynamic::TAG_DEBUG, 0),
+        ]);
+
+        strings = Some(dynstr);
+        got = Some(got_plt);
+    }
+
+    if pie {
+        entries.push(Entry::new(dynamic::TAG_FLAGS, dynamic::FLAG_BIND_NOW));
+        entries.push(Entry::new(
+            dynamic::TAG_FLAGS_1,
+            dynamic::FLAG_1_NOW | dynamic::FLAG_1_PIE,
+        ));
+    }
+
+    entries.push(Entry::new(dynamic::TAG_NULL, 0));
+
+    let mut section = layout::Section::new(".dynamic");
+
+    section
+        .kind(section::KIND_DYNAMIC)
+        .flags(section::FLAG_ALLOC | section::FLAG_WRITE)
+        .align(8)
+        .entry_len(dynamic::SIZE)
+        .data(
+            entries
+                .iter()
+                .flat_map(|entry| entry.to_array(endian).to_vec())
+                .collect(),
+        );
+
+    if let Some(dynstr) = strings {
+        section.link(layout.index(dynstr) as u32);
+    }
+
+    let dynamic = layout.push_section(section);
+    let mut segment = layout::Segment::new(program::KIND_LOAD);
+
+    segment
+        .flags(program::FLAG_READ | program::FLAG_WRITE)
+        .section(dynamic);
+
+    for (index, section) in addresses {
+        let relocation = layout::Relocation::new(
+            dynamic,
+            dynamic::SIZE * index as u64 + 8,
+            Kind::Absolute64,
+            Target::Section(section),
+        );
+
+        layout.push_relocation(relocation);
+    }
+
+    // the first `.got.plt` entry is the address of `.dynamic`
+    if let Some(got) = got {
+        let relocation =
+            layout::Relocation::new(got, 0, Kind::Absolute64, Target::Section(dynamic));
+
+        layout.push_relocation(relocation);
+        segment.section(got);
+    }
+
+    layout.push_segment(segment);
+
+    let mut segment = layout::Segment::new(program::KIND_DYNAMIC);
+
+    segment
+        .flags(program::FLAG_READ | program::FLAG_WRITE)
+        .align(8)
+        .section(dynamic);
+    layout.push_segment(segment);
+
+    // `.dynamic` is only written while loading, and the flags tell loaders and checkers what
+    // kind of file this is
+    if pie {
+        let mut segment = layout::Segment::new(program::KIND_GNU_RELRO);
+
+        segment.flags(program::FLAG_READ).align(1).section(dynamic);
+        layout.push_segment(segment);
+    }
+
+    symbols
+}
@@ -1,22 +1,45 @@
+use super::elf::Endian;
+use super::reader::{Error, Reader};
+use super::writer::Writer;
+
+/// Size of an ELF64 program header.
+pub const SIZE: u64 = 56;
+
+/// Size of an ELF32 program header.
+pub const SIZE32: u64 = 32;
+
+pub const KIND_NULL: u32 = 0;
+pub const KIND_LOAD: u32 = 1;
+pub const KIND_DYNAMIC: u32 = 2;
+/// Path of the dynamic linker.
+pub const KIND_INTERP: u32 = 3;
+pub const KIND_PHDR: u32 = 6;
+pub const KIND_GNU_STACK: u32 = 0x6474_e551;
+/// made read-only once relocated
+pub const KIND_GNU_RELRO: u32 = 0x6474_e552;
+
+pub const FLAG_EXEC: u32 = 1 << 0;
+pub const FLAG_WRITE: u32 = 1 << 1;
+pub const FLAG_READ: u32 = 1 << 2;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(C)]
 pub struct Header {
     /// kind
-    kind: u32,
+    pub kind: u32,
     /// flags
-    flags: u32,
+    pub flags: u32,
     /// offset
-    offset: u64,
+    pub offset: u64,
     /// virtual address
-    virtual_address: u64,
+    pub virtual_address: u64,
     /// physical address
-    physical_address: u64,
+    pub physical_address: u64,
     /// physical length
-    file_size: u64,
+    pub file_size: u64,
     /// virtual length
-    memory_size: u64,
+    pub memory_size: u64,
     /// align
-    align: u64,
+    pub align: u64,
 }
 
 impl Header {
@@ -73,7 +96,70 @@ impl Header {
         self
     }
 
-    pub const fn to_array(self) -> [u8; 56] {
-        unsafe { core::mem::transmute(self) }
+    /// Reads an ELF64 program header.
+    pub fn from_bytes(bytes: &[u8], endian: Endian) -> Result<Header, Error> {
+        let reader = Reader::new(bytes, endian, "program header");
+
+        Ok(Header {
+            kind: reader.u32(0)?,
+            flags: reader.u32(4)?,
+            offset: reader.u64(8)?,
+            virtual_address: reader.u64(16)?,
+            physical_address: reader.u64(24)?,
+            file_size: reader.u64(32)?,
+            memory_size: reader.u64(40)?,
+            align: reader.u64(48)?,
+        })
+    }
+
+    /// Reads an ELF32 program header, where the flags follow the sizes.
+    pub fn from_bytes32(bytes: &[u8], endian: Endian) -> Result<Header, Error> {
+        let reader = Reader::new(bytes, endian, "program header");
+
+        Ok(Header {
+            kind: reader.u32(0)?,
+            offset: reader.u32(4)? as u64,
+            virtual_address: reader.u32(8)? as u64,
+            physical_address: reader.u32(12)? as u64,
+            file_size: reader.u32(16)? as u64,
+            memory_size: reader.u32(20)? as u64,
+            flags: reader.u32(24)?,
+            align: reader.u32(28)? as u64,
+        })
+    }
+
+    /// Serializes an ELF32 program header in the given byte order, truncating every field to
+    /// 32 bits.
+    pub fn to_array32(&self, endian: Endian) -> [u8; 32] {
+        let mut bytes = [0; 32];
+
+        Writer::new(&mut bytes, endian)
+            .u32(0, self.kind)
+            .u32(4, self.offset as u32)
+            .u32(8, self.virtual_address as u32)
+            .u32(12, self.physical_address as u32)
+            .u32(16, self.file_size as u32)
+            .u32(20, self.memory_size as u32)
+            .u32(24, self.flags)
+            .u32(28, self.align as u32);
+
+        bytes
+    }
+
+    /// Serializes an ELF64 program header in the given byte order.
+    pub fn to_array(&self, endian: Endian) -> [u8; 56] {
+        let mut bytes = [0; 56];
+
+        Writer::new(&mut bytes, endian)
+            .u32(0, self.kind)
+            .u32(4, self.flags)
+            .u64(8, self.offset)
+            .u64(16, self.virtual_address)
+            .u64(24, self.physical_address)
+            .u64(32, self.file_size)
+            .u64(40, self.memory_size)
+            .u64(48, self.align);
+
+        bytes
     }
 }
@@ -0,0 +1,431 @@
+use super::elf::{Class, Elf, Endian};
+use super::{program, section};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// the file ends before the named structure does
+    Truncated(&'static str),
+    /// not `\x7fELF`
+    Magic,
+    /// neither ELF32 nor ELF64
+    Class(u8),
+    Endian(u8),
+    Version(u32),
+    /// the named structure has an unexpected entry size
+    Size(&'static str, u16),
+    /// the named range lies outside of the file
+    Bounds(&'static str),
+    /// the section name string table index is out of range
+    Names(u16),
+    /// a section name isn't terminated or isn't UTF-8
+    Name(u32),
+}
+
+impl Error {
+    pub fn message(&self) -> String {
+        match self {
+            Error::Truncated(what) => format!("file ends inside the {what}"),
+            Error::Magic => "not an ELF file".to_string(),
+            Error::Class(class) => {
+                format!("unsupported ELF class {class}, expected ELF32 or ELF64")
+            }
+            Error::Endian(endian) => format!("invalid data encoding {endian}"),
+            Error::Version(version) => format!("unsupported ELF version {version}"),
+            Error::Size(what, size) => format!("unexpected {what} size {size}"),
+            Error::Bounds(what) => format!("{what} lies outside of the file"),
+            Error::Names(index) => format!("section name table index {index} is out of range"),
+            Error::Name(offset) => format!("invalid section name at offset {offset}"),
+        }
+    }
+}
+
+/// Reads fields in the file's byte order, checking every access against the end of the
+/// structure being read.
+#[derive(Clone, Copy, Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    endian: Endian,
+    /// what is being read, for errors
+    what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8], endian: Endian, what: &'static str) -> Reader<'a> {
+        Reader {
+            bytes,
+            endian,
+            what,
+        }
+    }
+
+    pub fn array<const N: usize>(&self, offset: usize) -> Result<[u8; N], Error> {
+        let bytes = self
+            .bytes
+            .get(offset..offset + N)
+            .ok_or(Error::Truncated(self.what))?;
+
+        let mut array = [0; N];
+
+        array.copy_from_slice(bytes);
+
+        Ok(array)
+    }
+
+    pub fn u8(&self, offset: usize) -> Result<u8, Error> {
+        Ok(self.array::<1>(offset)?[0])
+    }
+
+    pub fn u16(&self, offset: usize) -> Result<u16, Error> {
+        let array = self.array(offset)?;
+
+        Ok(match self.endian {
+            Endian::Little => u16::from_le_bytes(array),
+            Endian::Big => u16::from_be_bytes(array),
+        })
+    }
+
+    pub fn u32(&self, offset: usize) -> Result<u32, Error> {
+        let array = self.array(offset)?;
+
+        Ok(match self.endian {
+            Endian::Little => u32::from_le_bytes(array),
+            Endian::Big => u32::from_be_bytes(array),
+        })
+    }
+
+    pub fn u64(&self, offset: usize) -> Result<u64, Error> {
+        let array = self.array(offset)?;
+
+        Ok(match self.endian {
+            Endian::Little => u64::from_le_bytes(array),
+            Endian::Big => u64::from_be_bytes(array),
+        })
+    }
+}
+
+/// `len` bytes at `offset`, if they lie within `bytes`.
+fn range<'a>(
+    bytes: &'a [u8],
+    offset: u64,
+    len: u64,
+    what: &'static str,
+) -> Result<&'a [u8], Error> {
+    let end = offset.checked_add(len).ok_or(Error::Bounds(what))?;
+
+    if end > bytes.len() as u64 {
+        return Err(Error::Bounds(what));
+    }
+
+    Ok(&bytes[offset as usize..end as usize])
+}
+
+#[derive(Clone, Debug)]
+pub struct Section<'a> {
+    /// name resolved through the section name string table
+    pub name: &'a str,
+    pub header: section::Header,
+    /// contents, empty for `SHT_NOBITS`
+    pub data: &'a [u8],
+}
+
+/// A parsed ELF32 or ELF64 file, borrowing section contents from the input.
+#[derive(Clone, Debug)]
+pub struct File<'a> {
+    pub elf: Elf,
+    pub programs: Vec<program::Header>,
+    pub sections: Vec<Section<'a>>,
+}
+
+impl<'a> File<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<File<'a>, Error> {
+        let elf = Elf::from_bytes(bytes)?;
+        let endian = elf.endian;
+        let (program_size, section_size) = match elf.class {
+            Class::Class32 => (program::SIZE32, section::SIZE32),
+            Class::Class64 => (program::SIZE, section::SIZE),
+        };
+        let mut programs = vec![];
+
+        if elf.program_len > 0 {
+            if elf.program_size != program_size as u16 {
+                return Err(Error::Size("program header", elf.program_size));
+            }
+
+            let table = range(
+                bytes,
+                elf.program_headers_address,
+                program_size * elf.program_len as u64,
+                "program header table",
+            )?;
+
+            for chunk in table.chunks(program_size as usize) {
+                let header = match elf.class {
+                    Class::Class32 => program::Header::from_bytes32(chunk, endian)?,
+                    Class::Class64 => program::Header::from_bytes(chunk, endian)?,
+                };
+
+                range(bytes, header.offset, header.file_size, "segment")?;
+
+                programs.push(header);
+            }
+        }
+
+        let mut headers = vec![];
+
+        if elf.section_len > 0 {
+            if elf.section_size != section_size as u16 {
+                return Err(Error::Size("section header", elf.section_size));
+            }
+
+            let table = range(
+                bytes,
+                elf.section_headers_address,
+                section_size * elf.section_len as u64,
+                "section header table",
+            )?;
+
+            for chunk in table.chunks(section_size as usize) {
+                headers.push(match elf.class {
+                    Class::Class32 => section::Header::from_bytes32(chunk, endian)?,
+                    Class::Class64 => section::Header::from_bytes(chunk, endian)?,
+                });
+            }
+        }
+
+        let data = |header: &section::Header| {
+            if header.kind == section::KIND_NOBITS {
+                Ok(&bytes[..0])
+            } else {
+                range(bytes, header.offset, header.len, "section")
+            }
+        };
+
+        // an index of 0 means the file has no section names
+        let names = match headers.get(elf.section_index as usize) {
+            _ if elf.section_index == 0 => &bytes[..0],
+            Some(header) => data(header)?,
+            None => return Err(Error::Names(elf.section_index)),
+        };
+
+        let mut sections = vec![];
+
+        for header in headers {
+            let name = if names.is_empty() {
+                Some("")
+            } else {
+                names
+                    .get(header.name as usize..)
+                    .and_then(|names| names.split(|&byte| byte == 0).next())
+                    .filter(|name| (header.name as usize + name.len()) < names.len())
+                    .and_then(|name| core::str::from_utf8(name).ok())
+            };
+
+            let name = name.ok_or(Error::Name(header.name))?;
+
+            sections.push(Section {
+                name,
+                header,
+                data: data(&header)?,
+            });
+        }
+
+        Ok(File {
+            elf,
+            programs,
+            sections,
+        })
+    }
+
+    /// The first section called `name`.
+    pub fn section(&self, name: &str) -> Option<&Section<'a>> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::layout::{self, Layout};
+    use super::*;
+
+    const CODE: [u8; 2] = [0x90, 0xC3];
+
+    /// An executable with `.text`, `.data` and `.bss`, written by the layout engine.
+    fn write(class: Class) -> Vec<u8> {
+        let mut elf = Elf::new();
+
+        match class {
+            Class::Class32 => elf.class32().machine_i386().header32(),
+            Class::Class64 => elf.class64().machine_x86_64().header64(),
+        };
+
+        elf.endian_little()
+            .version(1)
+            .abi_sysv()
+            .abi_version()
+            .version2()
+            .flags(0)
+            .kind_exec();
+
+        let mut layout = Layout::new();
+        let mut section = layout::Section::new(".text");
+
+        section
+            .kind(section::KIND_PROGBITS)
+            .flags(section::FLAG_ALLOC | section::FLAG_EXEC)
+            .data(CODE.to_vec());
+
+        let text = layout.push_section(section);
+        let mut section = layout::Section::new(".data");
+
+        section
+            .kind(section::KIND_PROGBITS)
+            .flags(section::FLAG_ALLOC | section::FLAG_WRITE)
+            .data(vec![1, 2, 3, 4]);
+
+        let data = layout.push_section(section);
+        let mut section = layout::Section::new(".bss");
+
+        section
+            .kind(section::KIND_NOBITS)
+            .flags(section::FLAG_ALLOC | section::FLAG_WRITE)
+            .nobits(16);
+
+        let bss = layout.push_section(section);
+
+        let mut segment = layout::Segment::new(program::KIND_LOAD);
+
+        segment
+            .flags(program::FLAG_READ | program::FLAG_EXEC)
+            .section(text);
+        layout.push_segment(segment);
+
+        let mut segment = layout::Segment::new(program::KIND_LOAD);
+
+        segment
+            .flags(program::FLAG_READ | program::FLAG_WRITE)
+            .section(data)
+            .section(bss);
+        layout.push_segment(segment);
+        layout.entry(text, 1);
+        layout.write(&mut elf)
+    }
+
+    fn check(class: Class) {
+        let bytes = write(class);
+        let file = File::parse(&bytes).unwrap();
+        let names: Vec<&str> = file.sections.iter().map(|section| section.name).collect();
+        let text = file.section(".text").unwrap();
+
+        assert_eq!(file.elf.class, class);
+        assert_eq!(file.elf.endian, Endian::Little);
+        assert_eq!(file.elf.section_len as usize, file.sections.len());
+        assert_eq!(names, ["", ".text", ".data", ".bss", ".shstrtab"]);
+        assert_eq!(
+            file.sections[file.elf.section_index as usize].name,
+            ".shstrtab"
+        );
+
+        assert_eq!(text.data, CODE);
+        assert_eq!(file.elf.entry_address, text.header.address + 1);
+        assert_eq!(file.section(".data").unwrap().data, [1, 2, 3, 4]);
+        assert!(file.section(".bss").unwrap().data.is_empty());
+        assert_eq!(file.section(".bss").unwrap().header.len, 16);
+
+        // `PT_PHDR`, then a `PT_LOAD` for the headers and one for each segment
+        assert_eq!(file.programs[0].kind, program::KIND_PHDR);
+        assert!(
+            file.programs
+                .iter()
+                .any(|header| header.kind == program::KIND_LOAD
+                    && header.offset == text.header.offset)
+        );
+    }
+
+    #[test]
+    fn reads_elf64() {
+        check(Class::Class64);
+    }
+
+    #[test]
+    fn reads_elf32() {
+        check(Class::Class32);
+    }
+
+    #[test]
+    fn every_truncation_is_an_error() {
+        for class in [Class::Class32, Class::Class64] {
+            let bytes = write(class);
+
+            for len in 0..bytes.len() {
+                assert!(
+                    File::parse(&bytes[..len]).is_err(),
+                    "{:?} cut at {}",
+                    class,
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_header() {
+        let bytes = write(Class::Class64);
+
+        assert_eq!(
+            File::parse(&bytes[..20]).unwrap_err(),
+            Error::Truncated("file header")
+        );
+    }
+
+    #[test]
+    fn section_headers_out_of_bounds() {
+        let bytes = write(Class::Class64);
+
+        // the section header table ends the file
+        assert_eq!(
+            File::parse(&bytes[..bytes.len() - 1]).unwrap_err(),
+            Error::Bounds("section header table")
+        );
+    }
+
+    #[test]
+    fn section_out_of_bounds() {
+        let mut bytes = write(Class::Class64);
+        let file = File::parse(&bytes).unwrap();
+        // sh_offset of `.text`, the second header
+        let offset = (file.elf.section_headers_address + section::SIZE + 24) as usize;
+
+        let len = bytes.len() as u64;
+
+        bytes[offset..offset + 8].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(File::parse(&bytes).unwrap_err(), Error::Bounds("section"));
+
+        // an offset and size which overflow together
+        bytes[offset..offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(File::parse(&bytes).unwrap_err(), Error::Bounds("section"));
+    }
+
+    #[test]
+    fn segment_out_of_bounds() {
+        let mut bytes = write(Class::Class64);
+        let file = File::parse(&bytes).unwrap();
+        // p_offset of the first program header
+        let offset = (file.elf.program_headers_address + 8) as usize;
+
+        bytes[offset..offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(File::parse(&bytes).unwrap_err(), Error::Bounds("segment"));
+    }
+
+    #[test]
+    fn program_headers_out_of_bounds() {
+        let mut bytes = write(Class::Class64);
+        let len = bytes.len() as u64;
+
+        // e_phoff
+        bytes[0x20..0x28].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(
+            File::parse(&bytes).unwrap_err(),
+            Error::Bounds("program header table")
+        );
+    }
+}
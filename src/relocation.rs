@@ -0,0 +1,177 @@
+use super::elf::{Class, Endian};
+use super::reader::{Error, Reader};
+use super::writer::Writer;
+use core::convert::TryFrom;
+
+/// Size of an ELF64 relocation with an addend, the only kind x86-64 uses.
+pub const SIZE: u64 = 24;
+
+/// Size of an ELF32 relocation without an addend, the only kind i386 uses. The addend is
+/// kept in the relocated field.
+pub const SIZE32: u64 = 8;
+
+pub const KIND_X86_64_64: u32 = 1;
+pub const KIND_X86_64_PC32: u32 = 2;
+/// A call through the PLT, which is a direct call when statically linked.
+pub const KIND_X86_64_PLT32: u32 = 4;
+/// A `.got.plt` entry, filled in with the address of a function when it's first called.
+pub const KIND_X86_64_JUMP_SLOT: u32 = 7;
+pub const KIND_X86_64_32: u32 = 10;
+/// Like `KIND_X86_64_32`, but the field is sign-extended.
+pub const KIND_X86_64_32S: u32 = 11;
+
+pub const KIND_386_32: u32 = 1;
+pub const KIND_386_PC32: u32 = 2;
+pub const KIND_386_PLT32: u32 = 4;
+
+/// How a relocated field is computed, with `S` the address of the target, `A` the addend
+/// and `P` the address of the field.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Kind {
+    /// `S + A`, such as the immediate of `mov r32, imm32`
+    Absolute32,
+    /// `S + A - P`, such as a rip-relative displacement
+    Relative32,
+    /// `S + A` in 64 bits, such as a pointer in data
+    Absolute64,
+    /// `S + A - P` where `S` may be a PLT entry, the displacement of a call to a function
+    Plt32,
+}
+
+impl Kind {
+    /// Size of the field in bytes.
+    pub const fn size(&self) -> usize {
+        match self {
+            Kind::Absolute32 | Kind::Relative32 | Kind::Plt32 => 4,
+            Kind::Absolute64 => 8,
+        }
+    }
+
+    /// Value of the field, `None` if it doesn't fit in `size` bytes.
+    pub fn value(&self, target: u64, addend: i64, address: u64) -> Option<u64> {
+        let value = target as i64 + addend;
+
+        match self {
+            Kind::Absolute32 => u32::try_from(value).ok().map(u64::from),
+            Kind::Relative32 | Kind::Plt32 => i32::try_from(value - address as i64)
+                .ok()
+                .map(|value| value as u32 as u64),
+            Kind::Absolute64 => Some(value as u64),
+        }
+    }
+
+    /// Relocation kind for the machine of the given class.
+    pub fn id(&self, class: Class) -> u32 {
+        match (class, self) {
+            (Class::Class32, Kind::Absolute32) => KIND_386_32,
+            (Class::Class32, Kind::Relative32) => KIND_386_PC32,
+            (Class::Class32, Kind::Plt32) => KIND_386_PLT32,
+            (Class::Class64, Kind::Absolute32) => KIND_X86_64_32,
+            (Class::Class64, Kind::Relative32) => KIND_X86_64_PC32,
+            (Class::Class64, Kind::Absolute64) => KIND_X86_64_64,
+            (Class::Class64, Kind::Plt32) => KIND_X86_64_PLT32,
+            (Class::Class32, Kind::Absolute64) => unreachable!("i386 has no 64-bit relocations"),
+        }
+    }
+}
+
+/// An entry of a `.rela` or `.rel` section.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Relocation {
+    /// offset of the field in the section
+    pub offset: u64,
+    /// index of the symbol referred to
+    pub symbol: u32,
+    /// machine specific kind
+    pub kind: u32,
+    /// addend, zero for ELF32 where it's kept in the field
+    pub addend: i64,
+}
+
+impl Default for Relocation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Relocation {
+    pub const fn new() -> Relocation {
+        Relocation {
+            offset: 0,
+            symbol: 0,
+            kind: 0,
+            addend: 0,
+        }
+    }
+
+    pub const fn offset(&mut self, offset: u64) -> &mut Relocation {
+        self.offset = offset;
+        self
+    }
+
+    pub const fn symbol(&mut self, symbol: u32) -> &mut Relocation {
+        self.symbol = symbol;
+        self
+    }
+
+    pub const fn kind(&mut self, kind: u32) -> &mut Relocation {
+        self.kind = kind;
+        self
+    }
+
+    pub const fn addend(&mut self, addend: i64) -> &mut Relocation {
+        self.addend = addend;
+        self
+    }
+
+    /// Reads an ELF64 relocation with an addend, where the symbol is in the high half of
+    /// the info.
+    pub fn from_bytes(bytes: &[u8], endian: Endian) -> Result<Relocation, Error> {
+        let reader = Reader::new(bytes, endian, "relocation");
+        let info = reader.u64(8)?;
+
+        Ok(Relocation {
+            offset: reader.u64(0)?,
+            symbol: (info >> 32) as u32,
+            kind: info as u32,
+            addend: reader.u64(16)? as i64,
+        })
+    }
+
+    /// Reads an ELF32 relocation without an addend, where the symbol is in the high 24 bits
+    /// of the info.
+    pub fn from_bytes32(bytes: &[u8], endian: Endian) -> Result<Relocation, Error> {
+        let reader = Reader::new(bytes, endian, "relocation");
+        let info = reader.u32(4)?;
+
+        Ok(Relocation {
+            offset: reader.u32(0)? as u64,
+            symbol: info >> 8,
+            kind: info & 0xFF,
+            addend: 0,
+        })
+    }
+
+    /// Serializes an ELF32 relocation in the given byte order, dropping the addend.
+    pub fn to_array32(&self, endian: Endian) -> [u8; 8] {
+        let mut bytes = [0; 8];
+
+        Writer::new(&mut bytes, endian)
+            .u32(0, self.offset as u32)
+            .u32(4, (self.symbol << 8) | (self.kind & 0xFF));
+
+        bytes
+    }
+
+    /// Serializes an ELF64 relocation in the given byte order.
+    pub fn to_array(&self, endian: Endian) -> [u8; 24] {
+        let mut bytes = [0; 24];
+
+        Writer::new(&mut bytes, endian)
+            .u64(0, self.offset)
+            .u64(8, ((self.symbol as u64) << 32) | self.kind as u64)
+            .u64(16, self.addend as u64);
+
+        bytes
+    }
+}
@@ -1,26 +1,52 @@
+use super::elf::Endian;
+use super::reader::{Error, Reader};
+use super::writer::Writer;
+
+/// Size of an ELF64 section header.
+pub const SIZE: u64 = 64;
+
+/// Size of an ELF32 section header.
+pub const SIZE32: u64 = 40;
+
+pub const KIND_NULL: u32 = 0;
+pub const KIND_PROGBITS: u32 = 1;
+pub const KIND_SYMTAB: u32 = 2;
+pub const KIND_STRTAB: u32 = 3;
+pub const KIND_RELA: u32 = 4;
+pub const KIND_DYNAMIC: u32 = 6;
+pub const KIND_NOBITS: u32 = 8;
+pub const KIND_REL: u32 = 9;
+/// Symbols the dynamic linker resolves.
+pub const KIND_DYNSYM: u32 = 11;
+
+pub const FLAG_WRITE: u64 = 1 << 0;
+pub const FLAG_ALLOC: u64 = 1 << 1;
+pub const FLAG_EXEC: u64 = 1 << 2;
+/// `info` holds a section index
+pub const FLAG_INFO_LINK: u64 = 1 << 6;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(C)]
 pub struct Header {
     /// name (string table index)
-    name: u32,
+    pub name: u32,
     /// kind
-    kind: u32,
+    pub kind: u32,
     /// flags
-    flags: u64,
+    pub flags: u64,
     /// virtual address
-    address: u64,
+    pub address: u64,
     /// physical offset
-    offset: u64,
+    pub offset: u64,
     /// size
-    len: u64,
+    pub len: u64,
     /// link to another section
-    link: u32,
+    pub link: u32,
     /// additional information
-    info: u32,
+    pub info: u32,
     /// align
-    align: u64,
+    pub align: u64,
     /// entry size (if this section holds a table)
-    entry_len: u64,
+    pub entry_len: u64,
 }
 
 impl Header {
@@ -89,7 +115,78 @@ impl Header {
         self
     }
 
-    pub const fn to_array(self) -> [u8; 64] {
-        unsafe { core::mem::transmute(self) }
+    /// Reads an ELF64 section header.
+    pub fn from_bytes(bytes: &[u8], endian: Endian) -> Result<Header, Error> {
+        let reader = Reader::new(bytes, endian, "section header");
+
+        Ok(Header {
+            name: reader.u32(0)?,
+            kind: reader.u32(4)?,
+            flags: reader.u64(8)?,
+            address: reader.u64(16)?,
+            offset: reader.u64(24)?,
+            len: reader.u64(32)?,
+            link: reader.u32(40)?,
+            info: reader.u32(44)?,
+            align: reader.u64(48)?,
+            entry_len: reader.u64(56)?,
+        })
+    }
+
+    /// Reads an ELF32 section header.
+    pub fn from_bytes32(bytes: &[u8], endian: Endian) -> Result<Header, Error> {
+        let reader = Reader::new(bytes, endian, "section header");
+
+        Ok(Header {
+            name: reader.u32(0)?,
+            kind: reader.u32(4)?,
+            flags: reader.u32(8)? as u64,
+            address: reader.u32(12)? as u64,
+            offset: reader.u32(16)? as u64,
+            len: reader.u32(20)? as u64,
+            link: reader.u32(24)?,
+            info: reader.u32(28)?,
+            align: reader.u32(32)? as u64,
+            entry_len: reader.u32(36)? as u64,
+        })
+    }
+
+    /// Serializes an ELF32 section header in the given byte order, truncating the flags,
+    /// addresses and sizes to 32 bits.
+    pub fn to_array32(&self, endian: Endian) -> [u8; 40] {
+        let mut bytes = [0; 40];
+
+        Writer::new(&mut bytes, endian)
+            .u32(0, self.name)
+            .u32(4, self.kind)
+            .u32(8, self.flags as u32)
+            .u32(12, self.address as u32)
+            .u32(16, self.offset as u32)
+            .u32(20, self.len as u32)
+            .u32(24, self.link)
+            .u32(28, self.info)
+            .u32(32, self.align as u32)
+            .u32(36, self.entry_len as u32);
+
+        bytes
+    }
+
+    /// Serializes an ELF64 section header in the given byte order.
+    pub fn to_array(&self, endian: Endian) -> [u8; 64] {
+        let mut bytes = [0; 64];
+
+        Writer::new(&mut bytes, endian)
+            .u32(0, self.name)
+            .u32(4, self.kind)
+            .u64(8, self.flags)
+            .u64(16, self.address)
+            .u64(24, self.offset)
+            .u64(32, self.len)
+            .u32(40, self.link)
+            .u32(44, self.info)
+            .u64(48, self.align)
+            .u64(56, self.entry_len);
+
+        bytes
     }
 }
@@ -0,0 +1,109 @@
+use core::ops::Range;
+
+/// A byte range into the source.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    #[inline]
+    pub const fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Span covering both `self` and `other`.
+    #[inline]
+    pub fn to(self, other: Span) -> Self {
+        Self::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    #[inline]
+    pub const fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// A 1-based line and column.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Line lookup for a source file.
+#[derive(Clone, Debug)]
+pub struct Lines<'input> {
+    input: &'input str,
+    /// byte offset of the start of each line
+    starts: Vec<usize>,
+}
+
+impl<'input> Lines<'input> {
+    pub fn new(input: &'input str) -> Self {
+        let starts = Some(0)
+            .into_iter()
+            .chain(input.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+
+        Self { input, starts }
+    }
+
+    /// Number of lines.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Whether there are no lines, which never happens since an empty input has one.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// 0-based index of the line containing `offset`.
+    #[inline]
+    pub fn index(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        }
+    }
+
+    /// Line and column of `offset`, columns count characters.
+    pub fn location(&self, offset: usize) -> Location {
+        let index = self.index(offset);
+        let start = self.starts[index];
+        let column = self.input[start..offset.min(self.input.len())]
+            .chars()
+            .count();
+
+        Location {
+            line: index + 1,
+            column: column + 1,
+        }
+    }
+
+    /// Text of a 1-based line, without the trailing newline.
+    pub fn line(&self, line: usize) -> &'input str {
+        let start = self.starts[line - 1];
+        let end = self
+            .starts
+            .get(line)
+            .map(|end| end - 1)
+            .unwrap_or(self.input.len());
+
+        &self.input[start..end]
+    }
+}
@@ -0,0 +1,155 @@
+use super::elf::Endian;
+use super::reader::{Error, Reader};
+use super::writer::Writer;
+
+/// Size of an ELF64 symbol.
+pub const SIZE: u64 = 24;
+
+/// Size of an ELF32 symbol.
+pub const SIZE32: u64 = 16;
+
+pub const BIND_LOCAL: u8 = 0;
+pub const BIND_GLOBAL: u8 = 1;
+
+pub const KIND_NOTYPE: u8 = 0;
+pub const KIND_OBJECT: u8 = 1;
+pub const KIND_FUNC: u8 = 2;
+pub const KIND_SECTION: u8 = 3;
+pub const KIND_FILE: u8 = 4;
+
+/// Section index of symbols which aren't defined in any section.
+pub const INDEX_UNDEFINED: u16 = 0;
+/// Section index of symbols with an absolute value.
+pub const INDEX_ABSOLUTE: u16 = 0xFFF1;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    /// name (string table index)
+    pub name: u32,
+    /// binding in the high 4 bits, kind in the low 4 bits
+    pub info: u8,
+    /// visibility
+    pub other: u8,
+    /// index of the section this symbol is defined in
+    pub section: u16,
+    /// value, an address for executables
+    pub value: u64,
+    /// size
+    pub len: u64,
+}
+
+impl Default for Symbol {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Symbol {
+    pub const fn new() -> Symbol {
+        Symbol {
+            name: 0,
+            info: 0,
+            other: 0,
+            section: 0,
+            value: 0,
+            len: 0,
+        }
+    }
+
+    pub const fn name(&mut self, name: u32) -> &mut Symbol {
+        self.name = name;
+        self
+    }
+
+    pub const fn info(&mut self, bind: u8, kind: u8) -> &mut Symbol {
+        self.info = (bind << 4) | (kind & 0xF);
+        self
+    }
+
+    pub const fn other(&mut self, other: u8) -> &mut Symbol {
+        self.other = other;
+        self
+    }
+
+    pub const fn section(&mut self, index: u16) -> &mut Symbol {
+        self.section = index;
+        self
+    }
+
+    pub const fn value(&mut self, value: u64) -> &mut Symbol {
+        self.value = value;
+        self
+    }
+
+    pub const fn len(&mut self, len: u64) -> &mut Symbol {
+        self.len = len;
+        self
+    }
+
+    pub const fn bind(&self) -> u8 {
+        self.info >> 4
+    }
+
+    pub const fn kind(&self) -> u8 {
+        self.info & 0xF
+    }
+
+    /// Reads an ELF64 symbol.
+    pub fn from_bytes(bytes: &[u8], endian: Endian) -> Result<Symbol, Error> {
+        let reader = Reader::new(bytes, endian, "symbol");
+
+        Ok(Symbol {
+            name: reader.u32(0)?,
+            info: reader.u8(4)?,
+            other: reader.u8(5)?,
+            section: reader.u16(6)?,
+            value: reader.u64(8)?,
+            len: reader.u64(16)?,
+        })
+    }
+
+    /// Reads an ELF32 symbol, where the value and size come before the info.
+    pub fn from_bytes32(bytes: &[u8], endian: Endian) -> Result<Symbol, Error> {
+        let reader = Reader::new(bytes, endian, "symbol");
+
+        Ok(Symbol {
+            name: reader.u32(0)?,
+            value: reader.u32(4)? as u64,
+            len: reader.u32(8)? as u64,
+            info: reader.u8(12)?,
+            other: reader.u8(13)?,
+            section: reader.u16(14)?,
+        })
+    }
+
+    /// Serializes an ELF32 symbol in the given byte order, truncating the value and size to
+    /// 32 bits.
+    pub fn to_array32(&self, endian: Endian) -> [u8; 16] {
+        let mut bytes = [0; 16];
+
+        Writer::new(&mut bytes, endian)
+            .u32(0, self.name)
+            .u32(4, self.value as u32)
+            .u32(8, self.len as u32)
+            .u8(12, self.info)
+            .u8(13, self.other)
+            .u16(14, self.section);
+
+        bytes
+    }
+
+    /// Serializes an ELF64 symbol in the given byte order.
+    pub fn to_array(&self, endian: Endian) -> [u8; 24] {
+        let mut bytes = [0; 24];
+
+        Writer::new(&mut bytes, endian)
+            .u32(0, self.name)
+            .u8(4, self.info)
+            .u8(5, self.other)
+            .u16(6, self.section)
+            .u64(8, self.value)
+            .u64(16, self.len);
+
+        bytes
+    }
+}
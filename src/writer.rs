@@ -0,0 +1,46 @@
+use super::elf::Endian;
+
+/// Writes fields in the file's byte order at fixed offsets of a structure, the counterpart
+/// of `reader::Reader`.
+#[derive(Debug)]
+pub struct Writer<'a> {
+    bytes: &'a mut [u8],
+    endian: Endian,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(bytes: &'a mut [u8], endian: Endian) -> Writer<'a> {
+        Writer { bytes, endian }
+    }
+
+    pub fn array(&mut self, offset: usize, array: &[u8]) -> &mut Writer<'a> {
+        self.bytes[offset..offset + array.len()].copy_from_slice(array);
+        self
+    }
+
+    pub fn u8(&mut self, offset: usize, value: u8) -> &mut Writer<'a> {
+        self.bytes[offset] = value;
+        self
+    }
+
+    pub fn u16(&mut self, offset: usize, value: u16) -> &mut Writer<'a> {
+        match self.endian {
+            Endian::Little => self.array(offset, &value.to_le_bytes()),
+            Endian::Big => self.array(offset, &value.to_be_bytes()),
+        }
+    }
+
+    pub fn u32(&mut self, offset: usize, value: u32) -> &mut Writer<'a> {
+        match self.endian {
+            Endian::Little => self.array(offset, &value.to_le_bytes()),
+            Endian::Big => self.array(offset, &value.to_be_bytes()),
+        }
+    }
+
+    pub fn u64(&mut self, offset: usize, value: u64) -> &mut Writer<'a> {
+        match self.endian {
+            Endian::Little => self.array(offset, &value.to_le_bytes()),
+            Endian::Big => self.array(offset, &value.to_be_bytes()),
+        }
+    }
+}